@@ -0,0 +1,76 @@
+use crate::flats::MarkupDrift;
+use crate::i18n::Text;
+use crate::logger::Logger;
+use crate::storage::Storage;
+use crate::telegram::queue::MessageQueue;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use teloxide::types::ChatId;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::Instant;
+
+/// The same selector breaks on every page, alert about it at most this often.
+const ALERT_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+/// Forwards [`MarkupDrift`] reports from the parser to the admin chats.
+pub struct DriftAlerter {
+    receiver: mpsc::UnboundedReceiver<MarkupDrift>,
+    message_queue: MessageQueue,
+    storage: Arc<Mutex<Storage>>,
+    admin_chat_ids: Vec<i64>,
+    last_alerted: HashMap<String, Instant>,
+}
+
+impl DriftAlerter {
+    pub fn new(
+        receiver: mpsc::UnboundedReceiver<MarkupDrift>,
+        message_queue: MessageQueue,
+        storage: Arc<Mutex<Storage>>,
+        admin_chat_ids: Vec<i64>,
+    ) -> Self {
+        Self {
+            receiver,
+            message_queue,
+            storage,
+            admin_chat_ids,
+            last_alerted: HashMap::new(),
+        }
+    }
+
+    pub async fn run(mut self) {
+        while let Some(drift) = self.receiver.recv().await {
+            if self.admin_chat_ids.is_empty() || !self.should_alert(&drift.selector) {
+                continue;
+            }
+            let storage = self.storage.lock().await;
+            for chat_id in &self.admin_chat_ids {
+                let language = storage.chat_settings(*chat_id).language.unwrap_or_default();
+                let text = Text::MarkupDrift {
+                    url: &drift.url,
+                    selector: &drift.selector,
+                    detail: &drift.detail,
+                }
+                .render(language);
+                if let Err(error) = self.message_queue.send(ChatId(*chat_id), text) {
+                    Logger::error(
+                        format!("Failed to alert admin chat {}: {}", chat_id, error).as_str(),
+                    );
+                }
+            }
+        }
+    }
+
+    fn should_alert(&mut self, selector: &str) -> bool {
+        let now = Instant::now();
+        if self
+            .last_alerted
+            .get(selector)
+            .is_some_and(|alerted_at| now.duration_since(*alerted_at) < ALERT_COOLDOWN)
+        {
+            return false;
+        }
+        self.last_alerted.insert(selector.to_string(), now);
+        true
+    }
+}
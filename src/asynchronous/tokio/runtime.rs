@@ -5,9 +5,9 @@ pub struct AppRuntime {
 }
 
 impl AppRuntime {
-    pub fn new() -> Self {
+    pub fn new(worker_threads: usize) -> Self {
         let runtime = Builder::new_multi_thread()
-            .worker_threads(4)
+            .worker_threads(worker_threads)
             .enable_all()
             .build()
             .expect("Failed to create Tokio runtime");
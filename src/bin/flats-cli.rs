@@ -0,0 +1,517 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use flats_bot::asynchronous::tokio::runtime::AppRuntime;
+use flats_bot::config::Config;
+use flats_bot::export::{self, ExportFormat};
+use flats_bot::flats::{
+    Category, City, FilterForm, FilterInput, Flat, FlatCriteria, FlatDetails, FlatsParser, Series,
+};
+use flats_bot::geo::{self, Area, Coordinates, Geocoder};
+use flats_bot::i18n::Language;
+use flats_bot::sources::{City24Parser, ListingSources, Source, SsSource};
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Query ss.com flats from the terminal, without a Telegram bot token.
+#[derive(Parser)]
+#[command(name = "flats-cli", version)]
+struct Cli {
+    /// Override the ss.com base url.
+    #[arg(long, global = true)]
+    base_url: Option<String>,
+    /// Language of the scraped pages: lv or ru.
+    #[arg(long, global = true, default_value = "lv")]
+    language: String,
+    /// Real estate category: flats, houses, land, premises or garages.
+    #[arg(long, global = true, default_value = "flats", value_parser = parse_category)]
+    category: Category,
+    /// Street coordinates CSV extending the bundled geocoding dataset.
+    #[arg(long, global = true)]
+    geocoding_dataset: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the city, district and deal type tree.
+    Catalog {
+        /// Only load a single city, the full catalog takes a few hundred requests.
+        #[arg(long)]
+        city: Option<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Search listings in one or more districts of a city.
+    Search {
+        #[arg(long)]
+        city: String,
+        /// District name, repeat for several districts or pass `all` for the whole city.
+        #[arg(long = "district", required = true)]
+        districts: Vec<String>,
+        /// Deal type name, repeat for several; all deal types when omitted.
+        #[arg(long = "deal-type")]
+        deal_types: Vec<String>,
+        #[arg(long, default_value_t = 0)]
+        price_from: u32,
+        #[arg(long, default_value_t = u32::MAX)]
+        price_to: u32,
+        #[arg(long)]
+        rooms_from: Option<u32>,
+        #[arg(long)]
+        rooms_to: Option<u32>,
+        #[arg(long)]
+        area_from: Option<u32>,
+        #[arg(long)]
+        area_to: Option<u32>,
+        #[arg(long)]
+        floor_from: Option<u32>,
+        #[arg(long)]
+        floor_to: Option<u32>,
+        #[arg(long)]
+        not_first_floor: bool,
+        #[arg(long)]
+        not_last_floor: bool,
+        /// Building series code like 103 or lt, repeat to accept several.
+        #[arg(long = "series", value_parser = parse_series)]
+        series: Vec<Series>,
+        #[command(flatten)]
+        exclusions: Box<Exclusions>,
+        #[command(flatten)]
+        map_args: Box<MapArgs>,
+        /// Portal to search, ss or city24; repeat to merge several.
+        #[arg(long = "source", default_value = "ss", value_parser = parse_source)]
+        sources: Vec<Source>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Print the filter fields ss.com offers for a district and deal type.
+    Filters {
+        #[arg(long)]
+        city: String,
+        #[arg(long)]
+        district: String,
+        /// First deal type of the district when omitted.
+        #[arg(long)]
+        deal_type: Option<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Print the details of a single listing.
+    Details {
+        url: String,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+}
+
+/// Listings left out of a search.
+#[derive(Args)]
+struct Exclusions {
+    /// Building series to leave out, repeat for several.
+    #[arg(long = "exclude-series", value_parser = parse_series)]
+    excluded_series: Vec<Series>,
+    /// Part of a street name to leave out, repeat for several.
+    #[arg(long = "exclude-street")]
+    excluded_streets: Vec<String>,
+    /// Phrase the listing text must contain, repeat for several.
+    #[arg(long = "include")]
+    include_keywords: Vec<String>,
+    /// Phrase the listing text must not contain, repeat for several.
+    #[arg(long = "exclude")]
+    exclude_keywords: Vec<String>,
+}
+
+/// Narrowing a search to a circle and drawing the results.
+#[derive(Args)]
+struct MapArgs {
+    /// Center of the search area as latitude,longitude, e.g. 56.9496,24.1052.
+    #[arg(long, value_parser = parse_coordinates)]
+    near: Option<Coordinates>,
+    #[arg(long, default_value_t = 1.0)]
+    radius_km: f64,
+    /// Write a PNG map of the listings to this file.
+    #[arg(long)]
+    map: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+    /// CSV with `;` separators and a BOM, opens directly in Excel.
+    Excel,
+    /// JSON Lines, one record per line.
+    Jsonl,
+}
+
+impl OutputFormat {
+    fn export_format(&self) -> Option<ExportFormat> {
+        match self {
+            OutputFormat::Csv => Some(ExportFormat::Csv),
+            OutputFormat::Excel => Some(ExportFormat::ExcelCsv),
+            OutputFormat::Jsonl => Some(ExportFormat::JsonLines),
+            OutputFormat::Table | OutputFormat::Json => None,
+        }
+    }
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let cli = Cli::parse();
+    let language = Language::from_code(&cli.language)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported language '{}'", cli.language))?;
+    let base_url = cli.base_url.unwrap_or_else(|| Config::default().base_url);
+    let flats_parser = Arc::new(Mutex::new(FlatsParser::new(base_url.clone(), language)));
+    let city24_parser = City24Parser::new(Config::default().city24_api_url, language);
+    let geocoder = Geocoder::load(cli.geocoding_dataset.as_deref())?;
+    let listing_sources = ListingSources::new(
+        vec![
+            Box::new(SsSource::new(Arc::clone(&flats_parser), base_url)),
+            Box::new(city24_parser),
+        ],
+        Arc::new(geocoder),
+    );
+    let tokio_runtime = AppRuntime::new(2);
+
+    tokio_runtime.runtime.block_on(async {
+        match cli.command {
+            Command::Catalog { city, format } => {
+                let mut flats_parser = flats_parser.lock().await;
+                match city {
+                    Some(city) => flats_parser.parse_city_data(cli.category, &city).await?,
+                    None => flats_parser.parse_category_data(cli.category).await?,
+                }
+                print_catalog(&flats_parser.cities(cli.category), format)
+            }
+            Command::Search {
+                city,
+                districts,
+                deal_types,
+                price_from,
+                price_to,
+                rooms_from,
+                rooms_to,
+                area_from,
+                area_to,
+                floor_from,
+                floor_to,
+                not_first_floor,
+                not_last_floor,
+                series,
+                exclusions,
+                map_args,
+                sources,
+                format,
+            } => {
+                let mut flats_parser = flats_parser.lock().await;
+                flats_parser.parse_city_data(cli.category, &city).await?;
+                let Some(catalog_city) = flats_parser.cities(cli.category).into_iter().next()
+                else {
+                    return Err(anyhow::anyhow!("City '{}' has no districts", city));
+                };
+                let districts = if districts.iter().any(|district| district == "all") {
+                    catalog_city
+                        .districts
+                        .iter()
+                        .map(|district| district.name.clone())
+                        .collect::<HashSet<_>>()
+                } else {
+                    districts.into_iter().collect::<HashSet<_>>()
+                };
+                let deal_types = if deal_types.is_empty() {
+                    catalog_city
+                        .deal_type_names(&districts)
+                        .into_iter()
+                        .collect()
+                } else {
+                    deal_types.into_iter().collect()
+                };
+                let flat_criteria = FlatCriteria {
+                    category: cli.category,
+                    sources: sources.into_iter().collect(),
+                    city: catalog_city.name.clone(),
+                    districts,
+                    deal_types,
+                    price_from,
+                    price_to,
+                    rooms_from,
+                    rooms_to,
+                    square_meters_from: area_from,
+                    square_meters_to: area_to,
+                    floor_from,
+                    floor_to,
+                    not_first_floor,
+                    not_last_floor,
+                    series: series.into_iter().collect(),
+                    excluded_series: exclusions.excluded_series.into_iter().collect(),
+                    excluded_streets: lowercased(exclusions.excluded_streets),
+                    include_keywords: lowercased(exclusions.include_keywords),
+                    exclude_keywords: lowercased(exclusions.exclude_keywords),
+                    area: map_args.near.map(|center| Area {
+                        center,
+                        radius_km: map_args.radius_km,
+                    }),
+                };
+                drop(flats_parser);
+                let flats = listing_sources.search_matching(&flat_criteria).await?;
+                if let Some(path) = &map_args.map {
+                    let geocoder = listing_sources.geocoder();
+                    match geo::render_map(&flats, flat_criteria.area.as_ref(), geocoder)? {
+                        Some(png) => fs::write(path, png)?,
+                        None => eprintln!("None of the listings could be located on a map"),
+                    }
+                }
+                print_flats(&flats, format)
+            }
+            Command::Filters {
+                city,
+                district,
+                deal_type,
+                format,
+            } => {
+                let mut flats_parser = flats_parser.lock().await;
+                flats_parser.parse_city_data(cli.category, &city).await?;
+                let Some(catalog_city) = flats_parser.cities(cli.category).into_iter().next()
+                else {
+                    return Err(anyhow::anyhow!("City '{}' has no districts", city));
+                };
+                let Some(catalog_district) = catalog_city
+                    .districts
+                    .iter()
+                    .find(|candidate| candidate.name == district)
+                else {
+                    return Err(anyhow::anyhow!("District '{}' not found", district));
+                };
+                let catalog_deal_type = match &deal_type {
+                    Some(deal_type) => catalog_district
+                        .deal_types
+                        .iter()
+                        .find(|candidate| candidate.name == *deal_type),
+                    None => catalog_district.deal_types.first(),
+                };
+                let href = catalog_deal_type
+                    .map(|deal_type| deal_type.href.clone())
+                    .unwrap_or_else(|| catalog_district.href.clone());
+                let Some(form) = flats_parser.filter_form(&href).await? else {
+                    return Err(anyhow::anyhow!("{} has no filter form", href));
+                };
+                print_filters(&form, format)
+            }
+            Command::Details { url, format } => {
+                let details = listing_sources.fetch_details(&url).await?;
+                print_details(&details, format)
+            }
+        }
+    })
+}
+
+fn parse_category(name: &str) -> Result<Category, String> {
+    Category::from_name(name).ok_or_else(|| format!("unknown category '{}'", name))
+}
+
+fn parse_source(code: &str) -> Result<Source, String> {
+    Source::from_code(code).ok_or_else(|| format!("unknown source '{}'", code))
+}
+
+fn parse_series(name: &str) -> Result<Series, String> {
+    Series::from_name(name).ok_or_else(|| format!("unknown series '{}'", name))
+}
+
+fn parse_coordinates(text: &str) -> Result<Coordinates, String> {
+    Coordinates::parse(text).ok_or_else(|| format!("invalid coordinates '{}'", text))
+}
+
+fn lowercased(phrases: Vec<String>) -> BTreeSet<String> {
+    phrases
+        .into_iter()
+        .map(|phrase| phrase.trim().to_lowercase())
+        .collect()
+}
+
+fn print_catalog(cities: &[&City], format: OutputFormat) -> Result<(), anyhow::Error> {
+    match format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(cities)?);
+        }
+        OutputFormat::Jsonl => export::write_json_lines(cities, io::stdout())?,
+        OutputFormat::Csv | OutputFormat::Excel => {
+            let export_format = format.export_format().unwrap_or(ExportFormat::Csv);
+            let mut writer = export_format.csv_writer(io::stdout())?;
+            writer.write_record(["city", "district", "deal_type", "href"])?;
+            for city in cities {
+                for district in &city.districts {
+                    for deal_type in &district.deal_types {
+                        writer.write_record([
+                            &city.name,
+                            &district.name,
+                            &deal_type.name,
+                            &deal_type.href,
+                        ])?;
+                    }
+                }
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Table => {
+            for city in cities {
+                println!("{}", city.name);
+                let mut districts = city.districts.iter().collect::<Vec<_>>();
+                districts.sort_by(|a, b| a.name.cmp(&b.name));
+                for district in districts {
+                    let deal_types = district
+                        .deal_types
+                        .iter()
+                        .map(|deal_type| deal_type.name.as_str())
+                        .collect::<Vec<_>>();
+                    println!("  {} — {}", district.name, deal_types.join(", "));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_flats(flats: &[Flat], format: OutputFormat) -> Result<(), anyhow::Error> {
+    if let Some(export_format) = format.export_format() {
+        return export::write_flats(flats, export_format, io::stdout());
+    }
+    match format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(flats)?);
+        }
+        _ => {
+            let header = ["Street", "Rooms", "m²", "Floor", "Series", "Price", "Url"]
+                .map(String::from)
+                .to_vec();
+            let rows = flats
+                .iter()
+                .map(|flat| {
+                    vec![
+                        flat.street_name.clone(),
+                        flat.rooms.to_string(),
+                        flat.square_meters.to_string(),
+                        flat.floor_text(),
+                        flat.series.name(Language::En).to_string(),
+                        flat.price.clone(),
+                        flat.url.clone(),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            print_table(&header, &rows);
+            println!("\n{} flats", flats.len());
+        }
+    }
+    Ok(())
+}
+
+fn print_filters(form: &FilterForm, format: OutputFormat) -> Result<(), anyhow::Error> {
+    if let OutputFormat::Json = format {
+        println!("{}", serde_json::to_string_pretty(form)?);
+        return Ok(());
+    }
+    let input_name = |input: &Option<FilterInput>| {
+        input
+            .as_ref()
+            .map(|input| input.name.clone())
+            .unwrap_or_default()
+    };
+    let rows = form
+        .fields
+        .iter()
+        .map(|field| {
+            let options = [&field.min, &field.choice]
+                .into_iter()
+                .flatten()
+                .next()
+                .map(|input| {
+                    input
+                        .options
+                        .iter()
+                        .map(|option| option.label.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .unwrap_or_default();
+            vec![
+                field.kind.code().to_string(),
+                field.label.clone(),
+                input_name(&field.min),
+                input_name(&field.max),
+                input_name(&field.choice),
+                options,
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["Kind", "Label", "Min", "Max", "Choice", "Options"]
+        .map(String::from)
+        .to_vec();
+    print_table(&header, &rows);
+    Ok(())
+}
+
+fn print_details(details: &FlatDetails, format: OutputFormat) -> Result<(), anyhow::Error> {
+    if let Some(export_format) = format.export_format() {
+        return export::write_details(std::slice::from_ref(details), export_format, io::stdout());
+    }
+    match format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(details)?);
+        }
+        _ => {
+            let mut rows = vec![
+                vec![String::from("Url"), details.url.clone()],
+                vec![String::from("Price"), details.price.clone()],
+            ];
+            if let Some(published) = &details.published {
+                rows.push(vec![String::from("Published"), published.clone()]);
+            }
+            for (name, value) in &details.attributes {
+                rows.push(vec![name.trim_end_matches(':').to_string(), value.clone()]);
+            }
+            rows.push(vec![
+                String::from("Photos"),
+                details.photo_urls.len().to_string(),
+            ]);
+            print_table(&[String::from("Field"), String::from("Value")], &rows);
+            println!("\n{}", details.description);
+        }
+    }
+    Ok(())
+}
+
+fn print_table(header: &[String], rows: &[Vec<String>]) {
+    let mut widths = header
+        .iter()
+        .map(|cell| cell.chars().count())
+        .collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let format_row = |row: &[String]| {
+        row.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = *width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    println!("{}", format_row(header));
+    println!(
+        "{}",
+        widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>()
+            .join("  ")
+    );
+    for row in rows {
+        println!("{}", format_row(row));
+    }
+}
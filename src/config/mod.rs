@@ -0,0 +1,396 @@
+use crate::flats::DEFAULT_ROW_FAILURE_THRESHOLD;
+use crate::i18n::Language;
+use crate::logger::LogFormat;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Environment variable pointing to the TOML config file.
+pub const CONFIG_PATH_ENV: &str = "FLATS_BOT_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+const MIN_POLL_INTERVAL_SECS: u64 = 60;
+const MIN_HTTP_TOKEN_LENGTH: usize = 16;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Telegram bot token, `TELOXIDE_TOKEN` takes precedence.
+    pub telegram_token: Option<String>,
+    pub base_url: String,
+    /// JSON API of city24.lv, searched by subscriptions that include it.
+    pub city24_api_url: String,
+    pub scrape_language: Language,
+    pub poll_interval_secs: u64,
+    pub worker_threads: usize,
+    pub storage_path: PathBuf,
+    pub log_config: PathBuf,
+    /// `json` needs a log4rs config with the `json` encoder, like `log4rs.json.yaml`.
+    pub log_format: LogFormat,
+    /// Chats alerted about markup drift and allowed to use admin commands.
+    pub admin_chat_ids: Vec<i64>,
+    /// Share of unparsable rows on a listing page that triggers a markup drift alert.
+    pub row_failure_threshold: f64,
+    /// Address of the embedded HTTP API, e.g. `127.0.0.1:8080`; disabled when unset.
+    pub http_listen: Option<String>,
+    /// Bearer token of the `/api` routes, required unless `http_listen` is a loopback address.
+    pub http_token: Option<String>,
+    /// CSV of street coordinates extending the bundled `assets/geocoding/lv.csv`.
+    pub geocoding_dataset: Option<PathBuf>,
+    /// Photos of a new listing hashed to detect reposts, 0 disables the detection.
+    pub photos_per_listing: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            telegram_token: None,
+            base_url: String::from("https://www.ss.com"),
+            city24_api_url: String::from("https://api.city24.lv"),
+            scrape_language: Language::Lv,
+            poll_interval_secs: 600,
+            worker_threads: 4,
+            storage_path: PathBuf::from("data/storage.json"),
+            log_config: PathBuf::from("log4rs.yaml"),
+            log_format: LogFormat::Text,
+            admin_chat_ids: Vec::new(),
+            row_failure_threshold: DEFAULT_ROW_FAILURE_THRESHOLD,
+            http_listen: None,
+            http_token: None,
+            geocoding_dataset: None,
+            photos_per_listing: 4,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the file named by `FLATS_BOT_CONFIG` (or `config.toml`), applies `FLATS_BOT_*`
+    /// environment overrides and validates the result.
+    pub fn load() -> Result<Self, anyhow::Error> {
+        let explicit_path = env::var(CONFIG_PATH_ENV).ok();
+        let path = explicit_path
+            .clone()
+            .unwrap_or_else(|| String::from(DEFAULT_CONFIG_PATH));
+        let mut config = if Path::new(&path).exists() {
+            Self::from_file(&path)?
+        } else if explicit_path.is_some() {
+            return Err(anyhow::anyhow!(
+                "Config file {} set in {} does not exist",
+                path,
+                CONFIG_PATH_ENV
+            ));
+        } else {
+            Self::default()
+        };
+        config.apply_env_overrides()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let raw_toml = fs::read_to_string(path)
+            .map_err(|error| anyhow::anyhow!("Failed to read config file {:?}: {}", path, error))?;
+        toml::from_str(&raw_toml)
+            .map_err(|error| anyhow::anyhow!("Failed to parse config file {:?}: {}", path, error))
+    }
+
+    fn apply_env_overrides(&mut self) -> Result<(), anyhow::Error> {
+        if let Ok(token) = env::var("TELOXIDE_TOKEN") {
+            self.telegram_token = Some(token);
+        }
+        if let Ok(base_url) = env::var("FLATS_BOT_BASE_URL") {
+            self.base_url = base_url;
+        }
+        if let Ok(api_url) = env::var("FLATS_BOT_CITY24_API_URL") {
+            self.city24_api_url = api_url;
+        }
+        if let Ok(code) = env::var("FLATS_BOT_SCRAPE_LANGUAGE") {
+            self.scrape_language = Language::from_code(&code).ok_or_else(|| {
+                anyhow::anyhow!("FLATS_BOT_SCRAPE_LANGUAGE must be lv or ru, got '{}'", code)
+            })?;
+        }
+        if let Ok(secs) = env::var("FLATS_BOT_POLL_INTERVAL_SECS") {
+            self.poll_interval_secs = Self::parse_env("FLATS_BOT_POLL_INTERVAL_SECS", &secs)?;
+        }
+        if let Ok(threads) = env::var("FLATS_BOT_WORKER_THREADS") {
+            self.worker_threads = Self::parse_env("FLATS_BOT_WORKER_THREADS", &threads)?;
+        }
+        if let Ok(path) = env::var("FLATS_BOT_STORAGE_PATH") {
+            self.storage_path = PathBuf::from(path);
+        }
+        if let Ok(path) = env::var("FLATS_BOT_LOG_CONFIG") {
+            self.log_config = PathBuf::from(path);
+        }
+        if let Ok(format) = env::var("FLATS_BOT_LOG_FORMAT") {
+            self.log_format = match format.trim().to_lowercase().as_str() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "FLATS_BOT_LOG_FORMAT must be text or json, got '{}'",
+                        format
+                    ))
+                }
+            };
+        }
+        if let Ok(listen) = env::var("FLATS_BOT_HTTP_LISTEN") {
+            self.http_listen = Some(listen).filter(|listen| !listen.trim().is_empty());
+        }
+        if let Ok(token) = env::var("FLATS_BOT_HTTP_TOKEN") {
+            self.http_token = Some(token).filter(|token| !token.trim().is_empty());
+        }
+        if let Ok(path) = env::var("FLATS_BOT_GEOCODING_DATASET") {
+            self.geocoding_dataset =
+                Some(PathBuf::from(path)).filter(|path| !path.as_os_str().is_empty());
+        }
+        if let Ok(photos) = env::var("FLATS_BOT_PHOTOS_PER_LISTING") {
+            self.photos_per_listing = Self::parse_env("FLATS_BOT_PHOTOS_PER_LISTING", &photos)?;
+        }
+        if let Ok(threshold) = env::var("FLATS_BOT_ROW_FAILURE_THRESHOLD") {
+            self.row_failure_threshold =
+                Self::parse_env("FLATS_BOT_ROW_FAILURE_THRESHOLD", &threshold)?;
+        }
+        if let Ok(ids) = env::var("FLATS_BOT_ADMIN_CHAT_IDS") {
+            self.admin_chat_ids = ids
+                .split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(|id| Self::parse_env("FLATS_BOT_ADMIN_CHAT_IDS", id))
+                .collect::<Result<Vec<i64>, _>>()?;
+        }
+        Ok(())
+    }
+
+    fn parse_env<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, anyhow::Error> {
+        value
+            .trim()
+            .parse::<T>()
+            .map_err(|_| anyhow::anyhow!("{} has an invalid value '{}'", name, value))
+    }
+
+    /// Collects every problem at once so a broken deployment is fixed in one go.
+    fn validate(&mut self) -> Result<(), anyhow::Error> {
+        let mut problems: Vec<String> = Vec::new();
+
+        self.base_url = self.base_url.trim().trim_end_matches('/').to_string();
+        if !self.base_url.starts_with("http://") && !self.base_url.starts_with("https://") {
+            problems.push(format!(
+                "base_url must start with http:// or https://, got '{}'",
+                self.base_url
+            ));
+        }
+        self.city24_api_url = self.city24_api_url.trim().trim_end_matches('/').to_string();
+        if !self.city24_api_url.starts_with("http://")
+            && !self.city24_api_url.starts_with("https://")
+        {
+            problems.push(format!(
+                "city24_api_url must start with http:// or https://, got '{}'",
+                self.city24_api_url
+            ));
+        }
+        if self.scrape_language == Language::En {
+            problems.push(String::from(
+                "scrape_language must be lv or ru, ss.com has no English version",
+            ));
+        }
+        if self.poll_interval_secs < MIN_POLL_INTERVAL_SECS {
+            problems.push(format!(
+                "poll_interval_secs must be at least {}, got {}",
+                MIN_POLL_INTERVAL_SECS, self.poll_interval_secs
+            ));
+        }
+        if self.worker_threads == 0 {
+            problems.push(String::from("worker_threads must be at least 1"));
+        }
+        if self.storage_path.as_os_str().is_empty() {
+            problems.push(String::from("storage_path must not be empty"));
+        }
+        if let Some(path) = self
+            .geocoding_dataset
+            .as_ref()
+            .filter(|path| !path.exists())
+        {
+            problems.push(format!("geocoding_dataset file {:?} does not exist", path));
+        }
+        if !self.log_config.exists() {
+            problems.push(format!(
+                "log_config file {:?} does not exist",
+                self.log_config
+            ));
+        }
+        if self
+            .telegram_token
+            .as_deref()
+            .is_none_or(|token| token.trim().is_empty())
+        {
+            problems.push(String::from(
+                "telegram_token is not set, use the config file or TELOXIDE_TOKEN",
+            ));
+        }
+        if let Some(listen) = &self.http_listen {
+            match listen.parse::<SocketAddr>() {
+                Ok(address) if !address.ip().is_loopback() && self.http_token.is_none() => {
+                    problems.push(format!(
+                        "http_token must be set to serve the API on the non-loopback address {}",
+                        address
+                    ));
+                }
+                Ok(_) => {}
+                Err(_) => problems.push(format!(
+                    "http_listen must be an address like 127.0.0.1:8080, got '{}'",
+                    listen
+                )),
+            }
+        }
+        if self
+            .http_token
+            .as_deref()
+            .is_some_and(|token| token.trim().len() < MIN_HTTP_TOKEN_LENGTH)
+        {
+            problems.push(format!(
+                "http_token must have at least {} characters",
+                MIN_HTTP_TOKEN_LENGTH
+            ));
+        }
+        if !(self.row_failure_threshold > 0.0 && self.row_failure_threshold <= 1.0) {
+            problems.push(format!(
+                "row_failure_threshold must be within (0, 1], got {}",
+                self.row_failure_threshold
+            ));
+        }
+        if self.admin_chat_ids.contains(&0) {
+            problems.push(String::from("admin_chat_ids must not contain 0"));
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow::anyhow!(
+            "Invalid configuration:\n  - {}",
+            problems.join("\n  - ")
+        ))
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval_secs)
+    }
+
+    /// Polls can take a while and fail occasionally, so readiness tolerates a few missed ones.
+    pub fn scrape_stale_after(&self) -> Duration {
+        self.poll_interval() * 3
+    }
+
+    /// Address of the HTTP API when it is enabled, already validated by [`Config::load`].
+    pub fn http_address(&self) -> Option<SocketAddr> {
+        self.http_listen
+            .as_deref()
+            .and_then(|listen| listen.parse().ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid_config() -> Config {
+        Config {
+            telegram_token: Some(String::from("123456:ABC")),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn public_http_address_needs_a_token() {
+        let mut config = Config {
+            http_listen: Some(String::from("0.0.0.0:8080")),
+            ..valid_config()
+        };
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("http_token"), "{}", message);
+
+        config.http_token = Some(String::from("short"));
+        assert!(config.validate().is_err());
+        config.http_token = Some(String::from("a-long-enough-random-token"));
+        config.validate().unwrap();
+
+        let mut loopback = Config {
+            http_listen: Some(String::from("127.0.0.1:8080")),
+            ..valid_config()
+        };
+        loopback.validate().unwrap();
+    }
+
+    #[test]
+    fn default_config_with_a_token_is_valid() {
+        let mut config = valid_config();
+        config.base_url = String::from(" https://www.ss.com/ ");
+        config.validate().unwrap();
+        assert_eq!(config.base_url, "https://www.ss.com");
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let mut config = Config {
+            telegram_token: None,
+            base_url: String::from("www.ss.com"),
+            poll_interval_secs: 10,
+            worker_threads: 0,
+            http_listen: Some(String::from("localhost")),
+            row_failure_threshold: 1.5,
+            admin_chat_ids: vec![0],
+            ..Config::default()
+        };
+        let message = config.validate().unwrap_err().to_string();
+        for problem in [
+            "base_url",
+            "poll_interval_secs",
+            "worker_threads",
+            "telegram_token",
+            "http_listen",
+            "row_failure_threshold",
+            "admin_chat_ids",
+        ] {
+            assert!(
+                message.contains(problem),
+                "{} missing in {}",
+                problem,
+                message
+            );
+        }
+    }
+
+    /// Environment variables are shared by every test, so they are only touched here.
+    #[test]
+    fn env_overrides_replace_file_values() {
+        env::set_var("FLATS_BOT_POLL_INTERVAL_SECS", "900");
+        env::set_var("FLATS_BOT_ADMIN_CHAT_IDS", "1, 2,,3");
+        env::set_var("FLATS_BOT_HTTP_LISTEN", "");
+        env::set_var("FLATS_BOT_SCRAPE_LANGUAGE", "ru");
+        let mut config = Config {
+            http_listen: Some(String::from("127.0.0.1:8080")),
+            ..valid_config()
+        };
+        config.apply_env_overrides().unwrap();
+        assert_eq!(config.poll_interval_secs, 900);
+        assert_eq!(config.admin_chat_ids, vec![1, 2, 3]);
+        assert_eq!(config.http_listen, None);
+        assert_eq!(config.scrape_language, Language::Ru);
+
+        env::set_var("FLATS_BOT_WORKER_THREADS", "many");
+        let error = config.apply_env_overrides().unwrap_err().to_string();
+        assert!(error.contains("FLATS_BOT_WORKER_THREADS"));
+
+        for name in [
+            "FLATS_BOT_POLL_INTERVAL_SECS",
+            "FLATS_BOT_ADMIN_CHAT_IDS",
+            "FLATS_BOT_HTTP_LISTEN",
+            "FLATS_BOT_SCRAPE_LANGUAGE",
+            "FLATS_BOT_WORKER_THREADS",
+        ] {
+            env::remove_var(name);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::flats::{Flat, FlatDetails};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+/// Byte order mark so spreadsheet applications detect UTF-8 (Latvian and Russian letters).
+const UTF8_BOM: &[u8] = "\u{feff}".as_bytes();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    /// CSV with a BOM and `;` separators, as Excel expects with Latvian/Russian locales.
+    ExcelCsv,
+    JsonLines,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        match name.trim().to_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "xlsx" | "excel" => Some(ExportFormat::ExcelCsv),
+            "jsonl" | "json" => Some(ExportFormat::JsonLines),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv | ExportFormat::ExcelCsv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+        }
+    }
+
+    /// CSV writer with this format's delimiter, writing the BOM first when needed.
+    pub fn csv_writer<W: Write>(&self, mut writer: W) -> Result<csv::Writer<W>, anyhow::Error> {
+        let delimiter = match self {
+            ExportFormat::ExcelCsv => {
+                writer.write_all(UTF8_BOM)?;
+                b';'
+            }
+            _ => b',',
+        };
+        Ok(csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(writer))
+    }
+}
+
+/// Category specific fields get one column per name, after the common columns.
+pub fn write_flats<W: Write>(
+    flats: &[Flat],
+    format: ExportFormat,
+    writer: W,
+) -> Result<(), anyhow::Error> {
+    match format {
+        ExportFormat::JsonLines => write_json_lines(flats, writer),
+        ExportFormat::Csv | ExportFormat::ExcelCsv => {
+            let field_names = flats
+                .iter()
+                .flat_map(|flat| flat.fields.keys().cloned())
+                .collect::<BTreeSet<_>>();
+            let mut csv_writer = format.csv_writer(writer)?;
+
+            let mut header = vec![
+                "source",
+                "category",
+                "street_name",
+                "price",
+                "url",
+                "image_url",
+                "rooms",
+                "square_meters",
+                "floor",
+                "total_floors",
+                "series",
+                "city",
+                "district",
+                "deal_type",
+            ];
+            header.extend(field_names.iter().map(String::as_str));
+            csv_writer.write_record(&header)?;
+
+            for flat in flats {
+                let mut record = vec![
+                    flat.source.code().to_string(),
+                    flat.category.code().to_string(),
+                    flat.street_name.clone(),
+                    flat.price.clone(),
+                    flat.url.clone(),
+                    flat.image_url.clone(),
+                    flat.rooms.to_string(),
+                    flat.square_meters.to_string(),
+                    flat.floor.to_string(),
+                    flat.total_floors.to_string(),
+                    flat.series.to_string(),
+                    flat.city.clone(),
+                    flat.district.clone(),
+                    flat.deal_type.clone(),
+                ];
+                record.extend(
+                    field_names
+                        .iter()
+                        .map(|name| flat.fields.get(name).cloned().unwrap_or_default()),
+                );
+                csv_writer.write_record(&record)?;
+            }
+            csv_writer.flush()?;
+            Ok(())
+        }
+    }
+}
+
+/// Attribute names differ between listings, so CSV gets one column per name seen in any of them.
+pub fn write_details<W: Write>(
+    details: &[FlatDetails],
+    format: ExportFormat,
+    writer: W,
+) -> Result<(), anyhow::Error> {
+    if format == ExportFormat::JsonLines {
+        return write_json_lines(details, writer);
+    }
+
+    let attribute_names = details
+        .iter()
+        .flat_map(|details| details.attributes.keys().cloned())
+        .collect::<BTreeSet<_>>();
+    let mut csv_writer = format.csv_writer(writer)?;
+
+    let mut header = vec!["url", "price", "published"];
+    header.extend(
+        attribute_names
+            .iter()
+            .map(|name| name.trim_end_matches(':')),
+    );
+    header.extend(["photo_urls", "description"]);
+    csv_writer.write_record(&header)?;
+
+    for details in details {
+        let mut record = vec![
+            details.url.clone(),
+            details.price.clone(),
+            details.published.clone().unwrap_or_default(),
+        ];
+        record.extend(
+            attribute_names
+                .iter()
+                .map(|name| details.attributes.get(name).cloned().unwrap_or_default()),
+        );
+        record.push(details.photo_urls.join(" "));
+        record.push(details.description.clone());
+        csv_writer.write_record(&record)?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// One JSON document per line.
+pub fn write_json_lines<T: Serialize, W: Write>(
+    records: &[T],
+    mut writer: W,
+) -> Result<(), anyhow::Error> {
+    for record in records {
+        serde_json::to_writer(&mut writer, record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use crate::i18n::Language;
+use crate::storage::{Storage, StoredListing};
+use crate::subscriptions::Subscription;
+use chrono::{DateTime, Utc};
+
+/// Maximum amount of entries in a single feed.
+pub const FEED_ENTRIES_LIMIT: usize = 50;
+
+/// Latest listings reported for a subscription, newest first.
+pub fn subscription_listings<'a>(
+    storage: &'a Storage,
+    subscription: &Subscription,
+) -> Vec<&'a StoredListing> {
+    let mut listings = subscription
+        .seen_urls
+        .iter()
+        .filter_map(|url| storage.data.listings.get(url))
+        .collect::<Vec<_>>();
+    listings.sort_by_key(|listing| std::cmp::Reverse(listing.first_seen));
+    listings.truncate(FEED_ENTRIES_LIMIT);
+    listings
+}
+
+pub fn atom(subscription: &Subscription, listings: &[&StoredListing], self_url: &str) -> String {
+    let updated = listings
+        .iter()
+        .map(|listing| listing.first_seen)
+        .max()
+        .unwrap_or(subscription.created_at);
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    xml.push_str(
+        r#"<feed xmlns="http://www.w3.org/2005/Atom" xmlns:media="http://search.yahoo.com/mrss/">"#,
+    );
+    xml.push_str(&format!(
+        "<title>{}</title>",
+        escape(&feed_title(subscription))
+    ));
+    xml.push_str(&format!(
+        "<id>urn:flats-bot:subscription:{}</id>",
+        subscription.id
+    ));
+    xml.push_str(&format!(
+        r#"<link rel="self" href="{}"/>"#,
+        escape(self_url)
+    ));
+    xml.push_str(&format!("<updated>{}</updated>", updated.to_rfc3339()));
+    for listing in listings {
+        let flat = &listing.flat;
+        xml.push_str("<entry>");
+        xml.push_str(&format!("<title>{}</title>", escape(&entry_title(listing))));
+        xml.push_str(&format!("<id>{}</id>", escape(&flat.url)));
+        xml.push_str(&format!(r#"<link href="{}"/>"#, escape(&flat.url)));
+        xml.push_str(&format!(
+            "<published>{}</published>",
+            listing.first_seen.to_rfc3339()
+        ));
+        xml.push_str(&format!(
+            "<updated>{}</updated>",
+            listing.last_seen.to_rfc3339()
+        ));
+        if !flat.image_url.is_empty() {
+            xml.push_str(&format!(
+                r#"<media:thumbnail url="{}"/>"#,
+                escape(&flat.image_url)
+            ));
+        }
+        xml.push_str(&format!(
+            r#"<content type="html">{}</content>"#,
+            escape(&entry_html(listing))
+        ));
+        xml.push_str("</entry>");
+    }
+    xml.push_str("</feed>");
+    xml
+}
+
+pub fn rss(subscription: &Subscription, listings: &[&StoredListing], self_url: &str) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    xml.push_str(r#"<rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/">"#);
+    xml.push_str("<channel>");
+    xml.push_str(&format!(
+        "<title>{}</title>",
+        escape(&feed_title(subscription))
+    ));
+    xml.push_str(&format!("<link>{}</link>", escape(self_url)));
+    xml.push_str(&format!(
+        "<description>{}</description>",
+        escape(&feed_title(subscription))
+    ));
+    if let Some(latest) = listings.iter().map(|listing| listing.first_seen).max() {
+        xml.push_str(&format!(
+            "<lastBuildDate>{}</lastBuildDate>",
+            latest.to_rfc2822()
+        ));
+    }
+    for listing in listings {
+        let flat = &listing.flat;
+        xml.push_str("<item>");
+        xml.push_str(&format!("<title>{}</title>", escape(&entry_title(listing))));
+        xml.push_str(&format!("<link>{}</link>", escape(&flat.url)));
+        xml.push_str(&format!(
+            r#"<guid isPermaLink="true">{}</guid>"#,
+            escape(&flat.url)
+        ));
+        xml.push_str(&format!(
+            "<pubDate>{}</pubDate>",
+            listing.first_seen.to_rfc2822()
+        ));
+        if !flat.image_url.is_empty() {
+            xml.push_str(&format!(
+                r#"<media:thumbnail url="{}"/>"#,
+                escape(&flat.image_url)
+            ));
+        }
+        xml.push_str(&format!(
+            "<description>{}</description>",
+            escape(&entry_html(listing))
+        ));
+        xml.push_str("</item>");
+    }
+    xml.push_str("</channel></rss>");
+    xml
+}
+
+fn feed_title(subscription: &Subscription) -> String {
+    let criteria = &subscription.criteria;
+    let mut districts = criteria.districts.iter().cloned().collect::<Vec<_>>();
+    districts.sort();
+    let mut deal_types = criteria.deal_types.iter().cloned().collect::<Vec<_>>();
+    deal_types.sort();
+    format!(
+        "#{} {}: {} ({}) {}-{} €",
+        subscription.id,
+        criteria.city,
+        districts.join(", "),
+        deal_types.join(", "),
+        criteria.price_from,
+        criteria.price_to
+    )
+}
+
+fn entry_title(listing: &StoredListing) -> String {
+    let flat = &listing.flat;
+    format!(
+        "{}, {} rooms, {} m² — {}",
+        flat.street_name, flat.rooms, flat.square_meters, flat.price
+    )
+}
+
+fn entry_html(listing: &StoredListing) -> String {
+    let flat = &listing.flat;
+    let mut html = String::new();
+    if !flat.image_url.is_empty() {
+        html.push_str(&format!(
+            r#"<p><img src="{}" alt=""/></p>"#,
+            escape(&flat.image_url)
+        ));
+    }
+    html.push_str(&format!(
+        "<p>{}<br/>{} rooms, {} m², floor {}, {}<br/>Price: {}</p>",
+        escape(&flat.street_name),
+        flat.rooms,
+        flat.square_meters,
+        flat.floor_text(),
+        escape(flat.series.name(Language::En)),
+        escape(&flat.price)
+    ));
+    if listing.price_history.len() > 1 {
+        let history = listing
+            .price_history
+            .iter()
+            .map(|point| format!("{} {}", format_date(point.seen_at), escape(&point.price)))
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        html.push_str(&format!("<p>Price history:<br/>{}</p>", history));
+    }
+    html
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flats::Flat;
+
+    fn subscription() -> Subscription {
+        let criteria = serde_json::from_value(serde_json::json!({
+            "city": "Rīga",
+            "districts": ["Centrs"],
+            "deal_types": ["Pārdod"],
+            "price_from": 0,
+            "price_to": 100000
+        }))
+        .unwrap();
+        Subscription {
+            id: 3,
+            chat_id: 1,
+            criteria,
+            seen_urls: Default::default(),
+            rejected_urls: Default::default(),
+            active: true,
+            min_discount: None,
+            feed_token: Subscription::new_feed_token(),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn listing() -> StoredListing {
+        StoredListing {
+            flat: Flat {
+                street_name: String::from("Brīvības <b>& Co</b>"),
+                price: String::from("85,000 €"),
+                url: String::from("https://www.ss.com/msg/a.html?x=1&y=\"2\""),
+                image_url: String::from("https://i.ss.com/a.th2.jpg"),
+                ..Flat::default()
+            },
+            first_seen: Utc::now(),
+            last_seen: Utc::now(),
+            price_history: Vec::new(),
+            photo_hashes: None,
+        }
+    }
+
+    #[test]
+    fn escape_replaces_markup_characters() {
+        assert_eq!(
+            escape(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn feeds_escape_listing_text_and_urls() {
+        let listing = listing();
+        for xml in [
+            atom(
+                &subscription(),
+                &[&listing],
+                "http://localhost/feeds/t/atom.xml",
+            ),
+            rss(
+                &subscription(),
+                &[&listing],
+                "http://localhost/feeds/t/rss.xml",
+            ),
+        ] {
+            assert!(!xml.contains("<b>"), "{}", xml);
+            assert!(!xml.contains("& Co"), "{}", xml);
+            assert!(
+                xml.contains("Brīvības &lt;b&gt;&amp; Co&lt;/b&gt;"),
+                "{}",
+                xml
+            );
+            assert!(xml.contains("a.html?x=1&amp;y=&quot;2&quot;"), "{}", xml);
+        }
+    }
+
+    #[test]
+    fn feed_tokens_are_random() {
+        let token = Subscription::new_feed_token();
+        assert_eq!(token.len(), 32);
+        assert!(token.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(token, Subscription::new_feed_token());
+    }
+}
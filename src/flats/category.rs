@@ -0,0 +1,79 @@
+use crate::i18n::Language;
+use serde::{Deserialize, Serialize};
+
+/// ss.com real estate section a search targets.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    #[default]
+    Flats,
+    Houses,
+    Land,
+    Premises,
+    Garages,
+}
+
+impl Category {
+    pub const ALL: [Category; 5] = [
+        Category::Flats,
+        Category::Houses,
+        Category::Land,
+        Category::Premises,
+        Category::Garages,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Category::Flats => "flats",
+            Category::Houses => "houses",
+            Category::Land => "land",
+            Category::Premises => "premises",
+            Category::Garages => "garages",
+        }
+    }
+
+    /// Section path below `/real-estate/` on ss.com.
+    pub fn ss_path(&self) -> &'static str {
+        match self {
+            Category::Flats => "flats",
+            Category::Houses => "homes-summer-residences",
+            Category::Land => "plots-and-lands",
+            Category::Premises => "premises",
+            Category::Garages => "garages",
+        }
+    }
+
+    pub fn name(&self, language: Language) -> &'static str {
+        use Language::{En, Lv, Ru};
+        match (self, language) {
+            (Category::Flats, Lv) => "Dzīvokļi",
+            (Category::Flats, Ru) => "Квартиры",
+            (Category::Flats, En) => "Flats",
+            (Category::Houses, Lv) => "Mājas",
+            (Category::Houses, Ru) => "Дома",
+            (Category::Houses, En) => "Houses",
+            (Category::Land, Lv) => "Zeme",
+            (Category::Land, Ru) => "Земля",
+            (Category::Land, En) => "Land",
+            (Category::Premises, Lv) => "Telpas",
+            (Category::Premises, Ru) => "Помещения",
+            (Category::Premises, En) => "Premises",
+            (Category::Garages, Lv) => "Garāžas",
+            (Category::Garages, Ru) => "Гаражи",
+            (Category::Garages, En) => "Garages",
+        }
+    }
+
+    /// Accepts the code or the name in any supported language, ignoring case.
+    pub fn from_name(name: &str) -> Option<Category> {
+        let name = name.trim().to_lowercase();
+        Category::ALL.into_iter().find(|category| {
+            category.code() == name
+                || Language::ALL
+                    .iter()
+                    .any(|language| category.name(*language).to_lowercase() == name)
+        })
+    }
+}
@@ -0,0 +1,391 @@
+use super::{leading_number, text_of, FlatCriteria};
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use std::sync::LazyLock;
+
+const FILTER_FORM_SELECTOR: &str = "form#filter_frm";
+const FILTER_INPUT_SELECTOR: &str = "select[name], input[name]";
+
+/// `topt[8][min]` style names of range inputs, `opt[6]` of single choice ones.
+static FILTER_NAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^t?opt\[(\d+)\](?:\[(min|max)\])?$").expect("filter name regex is valid")
+});
+
+/// What an ss.com filter field narrows, recognised by its label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterKind {
+    Price,
+    Rooms,
+    Area,
+    Floor,
+    Series,
+    Other,
+}
+
+/// Lowercased label prefixes in Latvian and Russian.
+const KIND_LABELS: [(FilterKind, &[&str]); 5] = [
+    (FilterKind::Price, &["cena", "цена"]),
+    (FilterKind::Rooms, &["istabas", "ist.", "комнат", "комн."]),
+    (FilterKind::Area, &["platība", "площадь", "m2", "м2"]),
+    (FilterKind::Floor, &["stāvs", "этаж"]),
+    (FilterKind::Series, &["sērija", "серия"]),
+];
+
+impl FilterKind {
+    /// Kinds a saved search can be narrowed by with `/filter`.
+    pub const RANGES: [FilterKind; 4] = [
+        FilterKind::Price,
+        FilterKind::Rooms,
+        FilterKind::Area,
+        FilterKind::Floor,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            FilterKind::Price => "price",
+            FilterKind::Rooms => "rooms",
+            FilterKind::Area => "area",
+            FilterKind::Floor => "floor",
+            FilterKind::Series => "series",
+            FilterKind::Other => "other",
+        }
+    }
+
+    /// Accepts the English code or the Latvian/Russian label, e.g. `rooms` or `istabas`.
+    pub fn from_name(name: &str) -> Option<FilterKind> {
+        let name = name.trim().to_lowercase();
+        match FilterKind::from_label(&name) {
+            FilterKind::Other => {}
+            kind => return Some(kind),
+        }
+        FilterKind::RANGES
+            .into_iter()
+            .chain([FilterKind::Series])
+            .find(|kind| kind.code() == name)
+    }
+
+    fn from_label(label: &str) -> FilterKind {
+        let label = label.to_lowercase();
+        KIND_LABELS
+            .iter()
+            .find(|(_, prefixes)| prefixes.iter().any(|prefix| label.starts_with(prefix)))
+            .map(|(kind, _)| *kind)
+            .unwrap_or(FilterKind::Other)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterOption {
+    pub value: String,
+    pub label: String,
+}
+
+/// A single form control, `options` is empty for free text inputs.
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterInput {
+    pub name: String,
+    pub options: Vec<FilterOption>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterField {
+    pub kind: FilterKind,
+    pub label: String,
+    pub min: Option<FilterInput>,
+    pub max: Option<FilterInput>,
+    /// Single choice fields like the series.
+    pub choice: Option<FilterInput>,
+}
+
+/// The filter form of a listing page, submitted to let ss.com do the filtering.
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterForm {
+    /// Path the form posts to, filtered pages live below it.
+    pub action: String,
+    pub fields: Vec<FilterField>,
+}
+
+impl FilterForm {
+    pub fn parse(document: &Html) -> Option<FilterForm> {
+        let form_selector = Selector::parse(FILTER_FORM_SELECTOR).ok()?;
+        let input_selector = Selector::parse(FILTER_INPUT_SELECTOR).ok()?;
+        let form = document.select(&form_selector).next()?;
+        let action = form.value().attr("action")?.to_string();
+
+        let mut fields: Vec<(String, FilterField)> = Vec::new();
+        for element in form.select(&input_selector) {
+            let Some(name) = element.value().attr("name") else {
+                continue;
+            };
+            let Some(captures) = FILTER_NAME.captures(name) else {
+                continue;
+            };
+            let id = captures[1].to_string();
+            let input = FilterInput {
+                name: name.to_string(),
+                options: Self::parse_options(&element),
+            };
+            let index = match fields.iter().position(|(field_id, _)| *field_id == id) {
+                Some(index) => index,
+                None => {
+                    let label = Self::row_label(&element);
+                    fields.push((
+                        id,
+                        FilterField {
+                            kind: FilterKind::from_label(&label),
+                            label,
+                            min: None,
+                            max: None,
+                            choice: None,
+                        },
+                    ));
+                    fields.len() - 1
+                }
+            };
+            let field = &mut fields[index].1;
+            match captures.get(2).map(|bound| bound.as_str()) {
+                Some("min") => field.min = Some(input),
+                Some("max") => field.max = Some(input),
+                _ => field.choice = Some(input),
+            }
+        }
+
+        Some(FilterForm {
+            action,
+            fields: fields.into_iter().map(|(_, field)| field).collect(),
+        })
+    }
+
+    pub fn field(&self, kind: FilterKind) -> Option<&FilterField> {
+        self.fields.iter().find(|field| field.kind == kind)
+    }
+
+    /// Form values narrowing the search to `criteria`, empty when nothing can be narrowed.
+    pub fn values(&self, criteria: &FlatCriteria) -> Vec<(String, String)> {
+        let price_from = Some(criteria.price_from).filter(|price| *price > 0);
+        let price_to = Some(criteria.price_to).filter(|price| *price < u32::MAX);
+        // the last floor depends on the building, only "not first" narrows on ss.com
+        let floor_from = match criteria.not_first_floor {
+            true => Some(criteria.floor_from.unwrap_or(0).max(2)),
+            false => criteria.floor_from,
+        };
+        let ranges = [
+            (FilterKind::Price, price_from, price_to),
+            (FilterKind::Rooms, criteria.rooms_from, criteria.rooms_to),
+            (
+                FilterKind::Area,
+                criteria.square_meters_from,
+                criteria.square_meters_to,
+            ),
+            (FilterKind::Floor, floor_from, criteria.floor_to),
+        ];
+
+        let mut values = Vec::new();
+        for (kind, from, to) in ranges {
+            let Some(field) = self.field(kind) else {
+                continue;
+            };
+            if let (Some(input), Some(from)) = (&field.min, from) {
+                values.extend(input.value_for(from, Bound::Min));
+            }
+            if let (Some(input), Some(to)) = (&field.max, to) {
+                values.extend(input.value_for(to, Bound::Max));
+            }
+        }
+
+        // the series is a single choice, several accepted series are filtered locally
+        if let ([series], Some(input)) = (
+            criteria.series.iter().collect::<Vec<_>>().as_slice(),
+            self.field(FilterKind::Series)
+                .and_then(|field| field.choice.as_ref()),
+        ) {
+            if let Some(option) = input
+                .options
+                .iter()
+                .find(|option| series.matches_ss_name(&option.label))
+            {
+                values.push((input.name.clone(), option.value.clone()));
+            }
+        }
+        values
+    }
+
+    fn parse_options(element: &ElementRef) -> Vec<FilterOption> {
+        let Ok(option_selector) = Selector::parse("option") else {
+            return Vec::new();
+        };
+        element
+            .select(&option_selector)
+            .filter_map(|option| {
+                let value = option.value().attr("value")?.trim().to_string();
+                // the first option is an empty "any" choice
+                (!value.is_empty()).then(|| FilterOption {
+                    value,
+                    label: text_of(option),
+                })
+            })
+            .collect()
+    }
+
+    /// Text of the first cell of the table row holding the input, e.g. `Cena:`.
+    fn row_label(element: &ElementRef) -> String {
+        let Some(row) = element
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .find(|ancestor| ancestor.value().name() == "tr")
+        else {
+            return String::new();
+        };
+        let Ok(td_selector) = Selector::parse("td") else {
+            return String::new();
+        };
+        row.select(&td_selector)
+            .next()
+            .map(text_of)
+            .unwrap_or_default()
+            .trim_end_matches(':')
+            .to_string()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Bound {
+    Min,
+    Max,
+}
+
+impl FilterInput {
+    /// The value to submit for `wanted`; selects only offer some values, so the closest one
+    /// that does not exclude matching listings is picked, the rest is filtered locally.
+    fn value_for(&self, wanted: u32, bound: Bound) -> Option<(String, String)> {
+        if self.options.is_empty() {
+            return Some((self.name.clone(), wanted.to_string()));
+        }
+        let numeric_options = self.options.iter().map(|option| {
+            let number = option
+                .value
+                .parse::<u32>()
+                .unwrap_or_else(|_| leading_number(&option.label));
+            (number, option)
+        });
+        let option = match bound {
+            Bound::Min => numeric_options
+                .filter(|(number, _)| *number <= wanted)
+                .max_by_key(|(number, _)| *number),
+            Bound::Max => numeric_options
+                .filter(|(number, _)| *number >= wanted)
+                .min_by_key(|(number, _)| *number),
+        };
+        option.map(|(_, option)| (self.name.clone(), option.value.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const FILTER_FRM: &str = include_str!("fixtures/filter_frm.html");
+
+    fn form() -> FilterForm {
+        FilterForm::parse(&Html::parse_document(FILTER_FRM)).expect("fixture has a filter form")
+    }
+
+    fn criteria(filters: serde_json::Value) -> FlatCriteria {
+        let mut criteria = json!({
+            "city": "Rīga",
+            "districts": ["Centrs"],
+            "deal_types": ["Pārdod"],
+            "price_from": 0,
+            "price_to": u32::MAX,
+        });
+        for (key, value) in filters.as_object().expect("filters are an object") {
+            criteria[key] = value.clone();
+        }
+        serde_json::from_value(criteria).expect("criteria are valid")
+    }
+
+    #[test]
+    fn parse_recognises_the_fields_by_label() {
+        let form = form();
+        assert_eq!(
+            form.action,
+            "/lv/real-estate/flats/riga/centre/sell/filter/"
+        );
+        let kinds = form
+            .fields
+            .iter()
+            .map(|field| field.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                FilterKind::Price,
+                FilterKind::Rooms,
+                FilterKind::Area,
+                FilterKind::Floor,
+                FilterKind::Series,
+            ]
+        );
+
+        let rooms = form.field(FilterKind::Rooms).expect("rooms field");
+        assert_eq!(
+            rooms.min.as_ref().map(|input| input.name.as_str()),
+            Some("topt[1][min]")
+        );
+        assert_eq!(rooms.max.as_ref().map(|input| input.options.len()), Some(6));
+        let price = form.field(FilterKind::Price).expect("price field");
+        assert!(price
+            .min
+            .as_ref()
+            .is_some_and(|input| input.options.is_empty()));
+        let series = form.field(FilterKind::Series).expect("series field");
+        assert!(series.min.is_none() && series.max.is_none());
+        assert_eq!(
+            series.choice.as_ref().map(|input| input.name.as_str()),
+            Some("opt[6]")
+        );
+    }
+
+    #[test]
+    fn values_are_empty_without_filters() {
+        assert!(form().values(&criteria(json!({}))).is_empty());
+    }
+
+    #[test]
+    fn values_pick_the_closest_options_that_keep_every_match() {
+        let values = form().values(&criteria(json!({
+            "price_from": 50000,
+            "price_to": 120000,
+            "rooms_from": 2,
+            "rooms_to": 3,
+            "square_meters_from": 45,
+            "floor_from": 4,
+            "floor_to": 6,
+            "series": ["lt"],
+        })));
+        let expected = [
+            ("topt[8][min]", "50000"),
+            ("topt[8][max]", "120000"),
+            ("topt[1][min]", "2"),
+            ("topt[1][max]", "3"),
+            ("topt[3][min]", "45"),
+            ("topt[4][min]", "3"),
+            ("topt[4][max]", "9"),
+            ("opt[6]", "LT"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn values_skip_the_first_floor_and_several_series() {
+        let values = form().values(&criteria(json!({
+            "not_first_floor": true,
+            "series": ["lt", "103"],
+        })));
+        assert_eq!(values, [(String::from("topt[4][min]"), String::from("2"))]);
+    }
+}
@@ -1,20 +1,59 @@
-use crate::{asynchronous::tokio::runtime::AppRuntime, logger};
+mod category;
+mod filter;
+mod series;
+
+pub use category::Category;
+pub use filter::{FilterField, FilterForm, FilterInput, FilterKind, FilterOption};
+pub use series::Series;
+
+use crate::geo::{self, Area, Location};
+use crate::health::health;
+use crate::i18n::Language;
+use crate::logger;
+use crate::metrics::metrics;
+use crate::photos::Repost;
+use crate::sources::Source;
+use crate::stats::DealScore;
+use log::Level;
 use logger::Logger;
 use regex::Regex;
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
 use scraper::{ElementRef, Html, Selector};
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{mpsc, Mutex};
+
+const CATEGORY_LINK_SELECTOR: &str = "a.a_category";
+const DEAL_TYPE_SELECTOR: &str = "select.filter_sel.l100 > option";
+const LISTING_TABLE_SELECTOR: &str = "form#filter_frm>table>tbody";
+const LISTING_ROW_SELECTOR: &str = "tr";
+const DESCRIPTION_SELECTOR: &str = "div#msg_div_msg";
+/// Street, rooms, m2, floor, series and price.
+const FLAT_COLUMNS: usize = 6;
+/// Lowercased header titles of other categories, in Latvian and Russian.
+const ROOMS_TITLES: [&str; 2] = ["ist.", "комн."];
+const AREA_TITLES: [&str; 4] = ["m2", "м2", "platība", "площадь"];
+const FLOOR_TITLES: [&str; 4] = ["stāvs", "stāvi", "этаж", "этажей"];
+/// Share of unparsable rows on a listing page above which the markup is considered changed.
+pub const DEFAULT_ROW_FAILURE_THRESHOLD: f64 = 0.3;
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Serialize)]
+pub struct DealType {
+    pub name: String,
+    pub href: String,
+}
+
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Serialize)]
 pub struct CategoryStructure {
     pub name: String,
     pub href: String,
-    pub deal_types: Vec<String>,
+    pub deal_types: Vec<DealType>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
 pub struct City {
     pub name: String,
     pub href: String,
@@ -31,6 +70,24 @@ impl Hash for City {
     }
 }
 
+impl City {
+    /// Deal type names offered by at least one of the given districts, sorted and deduplicated.
+    pub fn deal_type_names(&self, districts: &HashSet<String>) -> Vec<String> {
+        let mut names = self
+            .districts
+            .iter()
+            .filter(|district| districts.contains(&district.name))
+            .flat_map(|district| district.deal_types.iter().map(|dt| dt.name.clone()))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+/// A single row of a listing table; despite the name it may be a house, land or garage too.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Flat {
     pub street_name: String,
     pub price: String,
@@ -39,137 +96,598 @@ pub struct Flat {
     pub rooms: u32,
     pub square_meters: u32,
     pub floor: u32,
-    pub series: String,
+    /// Floors of the building, 0 when the listing does not say.
+    #[serde(default)]
+    pub total_floors: u32,
+    pub series: Series,
+    /// Start of the listing text shown in search results.
+    #[serde(default)]
+    pub summary: String,
+    /// City, district and deal type names as ss.com shows them, empty for older listings.
+    #[serde(default)]
+    pub city: String,
+    #[serde(default)]
+    pub district: String,
+    #[serde(default)]
+    pub deal_type: String,
+    /// Coordinates from the offline geocoder, set after searching.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+    /// Price compared to similar stored listings, set for new matches before notifying.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deal: Option<DealScore>,
+    /// Earlier listing with the same photos, set for new matches before notifying.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repost: Option<Repost>,
+    #[serde(default)]
+    pub category: Category,
+    #[serde(default)]
+    pub source: Source,
+    /// Columns of other categories keyed by the table header, e.g. `Zeme` -> `1200 m²` for houses.
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Everything shown on a single listing page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlatDetails {
+    pub url: String,
+    pub price: String,
+    pub description: String,
+    /// Label/value rows of the options table, e.g. `Stāvs:` -> `3/5`.
+    pub attributes: BTreeMap<String, String>,
+    pub photo_urls: Vec<String>,
+    pub published: Option<String>,
 }
 
+impl Flat {
+    /// Numeric part of the listed price, e.g. `85,000 €` -> `85000`.
+    pub fn price_value(&self) -> Option<u32> {
+        let digits = self
+            .price
+            .chars()
+            .filter(|c| c.is_ascii_digit())
+            .collect::<String>();
+        digits.parse::<u32>().ok()
+    }
+
+    pub fn is_first_floor(&self) -> bool {
+        self.floor == 1
+    }
+
+    pub fn is_last_floor(&self) -> bool {
+        self.total_floors > 0 && self.floor == self.total_floors
+    }
+
+    /// Floor as ss.com shows it, e.g. `3/9`.
+    pub fn floor_text(&self) -> String {
+        if self.total_floors > 0 {
+            format!("{}/{}", self.floor, self.total_floors)
+        } else {
+            self.floor.to_string()
+        }
+    }
+
+    /// Identifies the same property listed on several portals, which spell street types and
+    /// diacritics differently, e.g. `Brīvības iela 12` and `Brivibas 12`.
+    pub fn dedup_key(&self) -> (Category, String, u32, u32, u32, Option<u32>) {
+        let house_numbers = self
+            .street_name
+            .split_whitespace()
+            .filter(|word| word.chars().any(|c| c.is_ascii_digit()))
+            .map(|word| {
+                word.chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .flat_map(char::to_lowercase)
+                    .collect::<String>()
+            });
+        let street = std::iter::once(geo::normalize_street(&self.street_name))
+            .chain(house_numbers)
+            .collect::<Vec<_>>()
+            .join(" ");
+        (
+            self.category,
+            street,
+            self.rooms,
+            self.square_meters,
+            self.floor,
+            self.price_value(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FlatCriteria {
-    pub href: String,
+    #[serde(default)]
+    pub category: Category,
+    /// Portals searched, results are merged.
+    #[serde(default = "FlatCriteria::default_sources")]
+    pub sources: BTreeSet<Source>,
     pub city: String,
-    pub district: String,
-    pub deal_type: String,
+    pub districts: HashSet<String>,
+    pub deal_types: HashSet<String>,
     pub price_from: u32,
     pub price_to: u32,
-    // pub rooms_from: u32,
-    // pub rooms_to: u32,
-    // pub square_meters_from: u32,
-    // pub square_meters_to: u32,
-    // pub floor_from: u32,
-    // pub floor_to: u32,
-    // pub series: String,
+    #[serde(default)]
+    pub rooms_from: Option<u32>,
+    #[serde(default)]
+    pub rooms_to: Option<u32>,
+    #[serde(default)]
+    pub square_meters_from: Option<u32>,
+    #[serde(default)]
+    pub square_meters_to: Option<u32>,
+    #[serde(default)]
+    pub floor_from: Option<u32>,
+    #[serde(default)]
+    pub floor_to: Option<u32>,
+    #[serde(default)]
+    pub not_first_floor: bool,
+    #[serde(default)]
+    pub not_last_floor: bool,
+    /// Accepted building series, any when empty.
+    #[serde(default)]
+    pub series: BTreeSet<Series>,
+    #[serde(default)]
+    pub excluded_series: BTreeSet<Series>,
+    /// Lowercased parts of street names never reported, e.g. `brīvības`.
+    #[serde(default)]
+    pub excluded_streets: BTreeSet<String>,
+    /// Lowercased phrases the listing text must all contain.
+    #[serde(default)]
+    pub include_keywords: BTreeSet<String>,
+    /// Lowercased phrases the listing text must not contain, e.g. `bez remonta`.
+    #[serde(default)]
+    pub exclude_keywords: BTreeSet<String>,
+    /// Only listings located within the circle, see [`FlatCriteria::matches_area`].
+    #[serde(default)]
+    pub area: Option<Area>,
+}
+
+impl FlatCriteria {
+    pub fn default_sources() -> BTreeSet<Source> {
+        BTreeSet::from([Source::Ss])
+    }
+
+    pub fn matches(&self, flat: &Flat) -> bool {
+        // 0 means the listing does not show the value, e.g. the floor of land
+        let within = |value: u32, from: Option<u32>, to: Option<u32>| {
+            value == 0 || (from.is_none_or(|from| value >= from) && to.is_none_or(|to| value <= to))
+        };
+        let price_matches = match flat.price_value() {
+            Some(price) => price >= self.price_from && price <= self.price_to,
+            None => false,
+        };
+        price_matches
+            && within(flat.rooms, self.rooms_from, self.rooms_to)
+            && within(
+                flat.square_meters,
+                self.square_meters_from,
+                self.square_meters_to,
+            )
+            && within(flat.floor, self.floor_from, self.floor_to)
+            && !(self.not_first_floor && flat.is_first_floor())
+            && !(self.not_last_floor && flat.is_last_floor())
+            && (self.series.is_empty() || self.series.contains(&flat.series))
+            && !self.excluded_series.contains(&flat.series)
+            && !self.is_excluded_street(&flat.street_name)
+            && !self.has_excluded_keyword(&flat.summary)
+    }
+
+    /// Checked once listings are geocoded, which happens after they were searched.
+    pub fn matches_area(&self, flat: &Flat) -> bool {
+        self.area.is_none_or(|area| {
+            flat.location
+                .is_some_and(|location| area.contains(&location))
+        })
+    }
+
+    /// Whether matching needs the full listing text, the summary may not mention every keyword.
+    pub fn has_keywords(&self) -> bool {
+        !self.include_keywords.is_empty() || !self.exclude_keywords.is_empty()
+    }
+
+    /// Keyword rules against the listing text, the summary and the description when fetched.
+    pub fn matches_text(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        self.include_keywords
+            .iter()
+            .all(|keyword| text.contains(keyword.as_str()))
+            && !self.has_excluded_keyword(&text)
+    }
+
+    fn has_excluded_keyword(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        self.exclude_keywords
+            .iter()
+            .any(|keyword| text.contains(keyword.as_str()))
+    }
+
+    fn is_excluded_street(&self, street_name: &str) -> bool {
+        let street_name = street_name.to_lowercase();
+        self.excluded_streets
+            .iter()
+            .any(|street| street_name.contains(street.as_str()))
+    }
+
+    /// Sets or, with both bounds `None`, clears a range filter; `false` for other kinds.
+    pub fn set_range(&mut self, kind: FilterKind, from: Option<u32>, to: Option<u32>) -> bool {
+        match kind {
+            FilterKind::Price => {
+                self.price_from = from.unwrap_or(0);
+                self.price_to = to.unwrap_or(u32::MAX);
+            }
+            FilterKind::Rooms => (self.rooms_from, self.rooms_to) = (from, to),
+            FilterKind::Area => (self.square_meters_from, self.square_meters_to) = (from, to),
+            FilterKind::Floor => (self.floor_from, self.floor_to) = (from, to),
+            FilterKind::Series | FilterKind::Other => return false,
+        }
+        true
+    }
+
+    /// Whether anything besides the city, districts and deal types narrows the search.
+    pub fn has_filters(&self) -> bool {
+        self.price_from > 0
+            || self.price_to < u32::MAX
+            || [
+                self.rooms_from,
+                self.rooms_to,
+                self.square_meters_from,
+                self.square_meters_to,
+                self.floor_from,
+                self.floor_to,
+            ]
+            .iter()
+            .any(Option::is_some)
+            || self.not_first_floor
+            || self.not_last_floor
+            || !self.series.is_empty()
+    }
+}
+
+/// A page that no longer has the structure the selectors expect.
+#[derive(Debug, Clone)]
+pub struct MarkupDrift {
+    pub url: String,
+    pub selector: String,
+    pub detail: String,
 }
 
 pub struct FlatsParser {
-    tokio: Arc<AppRuntime>,
-    pub cities: HashSet<City>,
-    pub deal_types: Vec<String>,
+    /// Cities of every category loaded so far, see [`FlatsParser::ensure_category_loaded`].
+    pub catalogs: HashMap<Category, HashSet<City>>,
     url_base: String,
+    language: Language,
     request_client: Client,
+    drift_reporter: Option<mpsc::UnboundedSender<MarkupDrift>>,
+    row_failure_threshold: f64,
+    /// Filter forms by category href, they rarely change.
+    filter_forms: Arc<std::sync::Mutex<HashMap<String, FilterForm>>>,
 }
 
 impl FlatsParser {
-    pub fn new(tokio: Arc<AppRuntime>) -> Self {
-        let cities: HashSet<City> = HashSet::new();
-        let url_base = String::from("https://www.ss.com");
-        let request_client = Client::new();
-        let deal_types: Vec<String> = Vec::new();
+    pub fn new(url_base: String, language: Language) -> Self {
         Self {
-            tokio,
-            cities,
-            deal_types,
+            catalogs: HashMap::new(),
             url_base,
-            request_client,
+            language,
+            request_client: Client::new(),
+            drift_reporter: None,
+            row_failure_threshold: DEFAULT_ROW_FAILURE_THRESHOLD,
+            filter_forms: Arc::new(std::sync::Mutex::new(HashMap::new())),
         }
     }
 
-    pub async fn parse_global_data(&mut self) -> Result<(), anyhow::Error> {
-        let Ok(regex) = Regex::new(r"\u{a0}") else {
-            Logger::info("Failed to create regex");
-            return Err(anyhow::anyhow!("Failed to create regex"));
-        };
+    /// A parser sharing the client, settings and filter forms of this one but none of its
+    /// catalogs, crawling with it does not need the lock on this one.
+    fn crawler(&self) -> FlatsParser {
+        Self {
+            catalogs: HashMap::new(),
+            url_base: self.url_base.clone(),
+            language: self.language,
+            request_client: self.request_client.clone(),
+            drift_reporter: self.drift_reporter.clone(),
+            row_failure_threshold: self.row_failure_threshold,
+            filter_forms: Arc::clone(&self.filter_forms),
+        }
+    }
 
-        let full_url = format!("{}/lv/real-estate/flats/", self.url_base);
-        let raw_html: Result<String, anyhow::Error> = {
-            let res = self.request_client.get(&full_url).send().await?;
-            if !res.status().is_success() {
-                return Err(anyhow::anyhow!(
-                    "Failed to get successful response from {}",
-                    full_url
-                ));
+    /// Listings matching `criteria`. The lock is only held to copy the catalog of the
+    /// category, crawling the listing pages does not block other users of the parser.
+    pub async fn search(
+        flats_parser: &Mutex<FlatsParser>,
+        criteria: &FlatCriteria,
+    ) -> Result<Vec<Flat>, anyhow::Error> {
+        Self::ensure_category_loaded(flats_parser, criteria.category).await?;
+        let searcher = {
+            let flats_parser = flats_parser.lock().await;
+            let mut searcher = flats_parser.crawler();
+            if let Some(cities) = flats_parser.catalogs.get(&criteria.category) {
+                searcher.catalogs.insert(criteria.category, cities.clone());
             }
-            let res = res.text().await?;
-            Ok(res)
+            searcher
         };
-        let raw_html = raw_html?;
-        let html = Html::parse_document(&raw_html);
+        searcher.parse_flats_by_criteria(criteria).await
+    }
 
-        let Ok(h4_selector) = Selector::parse("a.a_category") else {
-            Logger::info("Failed to parse selector");
-            return Err(anyhow::anyhow!("Failed to parse selector"));
+    /// Details of a listing, fetched without holding the lock.
+    pub async fn fetch_details(
+        flats_parser: &Mutex<FlatsParser>,
+        url: &str,
+    ) -> Result<FlatDetails, anyhow::Error> {
+        let crawler = flats_parser.lock().await.crawler();
+        crawler.parse_flat_details(url).await
+    }
+
+    /// Sends every detected [`MarkupDrift`] to `reporter`, e.g. to alert the admins.
+    pub fn set_drift_reporter(
+        &mut self,
+        reporter: mpsc::UnboundedSender<MarkupDrift>,
+        row_failure_threshold: f64,
+    ) {
+        self.drift_reporter = Some(reporter);
+        self.row_failure_threshold = row_failure_threshold;
+    }
+
+    fn report_drift(&self, url: &str, selector: &str, detail: String) {
+        metrics().parse_failure(selector);
+        Logger::warn(
+            format!(
+                "Markup drift on {}: selector '{}' {}",
+                url, selector, detail
+            )
+            .as_str(),
+        );
+        if let Some(reporter) = &self.drift_reporter {
+            let _ = reporter.send(MarkupDrift {
+                url: url.to_string(),
+                selector: selector.to_string(),
+                detail,
+            });
+        }
+    }
+
+    async fn fetch_html(&self, full_url: &str) -> Result<String, anyhow::Error> {
+        self.fetch_html_with(&self.request_client, full_url).await
+    }
+
+    async fn fetch_html_with(
+        &self,
+        client: &Client,
+        full_url: &str,
+    ) -> Result<String, anyhow::Error> {
+        self.send_html(full_url, client.get(full_url)).await
+    }
+
+    /// Submits a form the way the browser does and returns the resulting page.
+    async fn post_form(
+        &self,
+        client: &Client,
+        full_url: &str,
+        values: &[(String, String)],
+    ) -> Result<String, anyhow::Error> {
+        self.send_html(full_url, client.post(full_url).form(values))
+            .await
+    }
+
+    async fn send_html(
+        &self,
+        full_url: &str,
+        request: RequestBuilder,
+    ) -> Result<String, anyhow::Error> {
+        let metrics = metrics();
+        let _timer = metrics.scrape_duration.start_timer();
+        let started_at = Instant::now();
+        let res = match request.send().await {
+            Ok(res) => res,
+            Err(error) => {
+                metrics.http_errors.with_label_values(&["network"]).inc();
+                return Err(error.into());
+            }
         };
+        if !res.status().is_success() {
+            metrics
+                .http_errors
+                .with_label_values(&[res.status().as_str()])
+                .inc();
+            Logger::log(
+                Level::Warn,
+                "Page request failed",
+                &[
+                    ("url", &full_url),
+                    ("status", &res.status().as_u16()),
+                    ("duration_ms", &started_at.elapsed().as_millis()),
+                ],
+            );
+            return Err(anyhow::anyhow!(
+                "Failed to get successful response from {}",
+                full_url
+            ));
+        }
+        let res = res.text().await?;
+        metrics
+            .pages_fetched
+            .with_label_values(&[Source::Ss.code()])
+            .inc();
+        health().record_scrape();
+        Logger::log(
+            Level::Debug,
+            "Page fetched",
+            &[
+                ("url", &full_url),
+                ("duration_ms", &started_at.elapsed().as_millis()),
+            ],
+        );
+        Ok(res)
+    }
 
-        // Select all matching h4 elements
-        let cities: Vec<ElementRef> = html.select(&h4_selector).collect();
-        let mut cities_href_map: HashMap<String, String> = HashMap::new(); // <city_name, city_href>
-        for element in cities {
-            let city_name = element.text().collect::<String>();
-            let Some(city_href) = element.value().attr("href") else {
-                Logger::info(format!("Failed to get href attribute for {:?}", city_name).as_str());
-                continue;
-            };
-            cities_href_map.insert(city_name.to_string(), city_href.to_string());
+    /// Cities of a category sorted by name, empty until the category is loaded.
+    pub fn cities(&self, category: Category) -> Vec<&City> {
+        let mut cities = self
+            .catalogs
+            .get(&category)
+            .map(|cities| cities.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        cities.sort_by(|a, b| a.name.cmp(&b.name));
+        cities
+    }
+
+    pub fn find_city(&self, category: Category, city_name: &str) -> Option<&City> {
+        self.catalogs
+            .get(&category)?
+            .iter()
+            .find(|city| city.name.eq(city_name))
+    }
+
+    pub fn is_category_loaded(&self, category: Category) -> bool {
+        self.catalogs.contains_key(&category)
+    }
+
+    /// Reloads flats and every other category loaded so far. Crawling takes minutes, so the
+    /// lock is only held to swap the new catalogs in; the previous ones are kept when loading
+    /// fails.
+    pub async fn refresh_catalogs(flats_parser: &Mutex<FlatsParser>) -> Result<(), anyhow::Error> {
+        let (crawler, categories) = {
+            let flats_parser = flats_parser.lock().await;
+            let mut categories = flats_parser
+                .catalogs
+                .keys()
+                .copied()
+                .collect::<HashSet<_>>();
+            categories.insert(Category::Flats);
+            let mut categories = categories.into_iter().collect::<Vec<_>>();
+            categories.sort();
+            (flats_parser.crawler(), categories)
+        };
+        let mut catalogs = Vec::new();
+        for category in categories {
+            catalogs.push((category, crawler.crawl_category(category).await?));
+        }
+        let mut flats_parser = flats_parser.lock().await;
+        for (category, cities) in catalogs {
+            flats_parser.insert_catalog(category, cities);
         }
+        Ok(())
+    }
 
-        // make requests to get districts for each city
-        for (city_name, city_href) in cities_href_map {
-            let full_url = format!("{}{}", self.url_base, city_href);
-            let raw_html: Result<String, anyhow::Error> = {
-                let res = self.request_client.get(&full_url).send().await?;
-                if !res.status().is_success() {
-                    return Err(anyhow::anyhow!(
-                        "Failed to get successful response from {}",
-                        full_url
-                    ));
-                }
-                let res = res.text().await?;
-                Ok(res)
-            };
-            if let Err(error) = raw_html {
-                Logger::info(
-                    format!("Failed to get response from {}: {}", full_url, error).as_str(),
-                );
-                continue;
+    /// Loads other categories on first use. Crawling one takes a while, so the lock is only
+    /// held to insert the result.
+    pub async fn ensure_category_loaded(
+        flats_parser: &Mutex<FlatsParser>,
+        category: Category,
+    ) -> Result<(), anyhow::Error> {
+        let crawler = {
+            let flats_parser = flats_parser.lock().await;
+            if flats_parser.is_category_loaded(category) {
+                return Ok(());
             }
+            flats_parser.crawler()
+        };
+        let cities = crawler.crawl_category(category).await?;
+        flats_parser.lock().await.insert_catalog(category, cities);
+        Ok(())
+    }
 
-            let html = Html::parse_document(&raw_html.unwrap());
+    /// Loads the cities, districts and deal types of a single category.
+    pub async fn parse_category_data(&mut self, category: Category) -> Result<(), anyhow::Error> {
+        let cities = self.crawl_category(category).await?;
+        self.insert_catalog(category, cities);
+        Ok(())
+    }
 
-            let Ok(href_selector) = Selector::parse("a.a_category") else {
-                Logger::info("Failed to parse selector");
-                return Err(anyhow::anyhow!("Failed to parse selector"));
-            };
+    fn insert_catalog(&mut self, category: Category, cities: HashSet<City>) {
+        self.catalogs.insert(category, cities);
+        health().set_catalog_cities(self.catalogs.values().map(HashSet::len).sum());
+    }
 
-            let districts: Vec<ElementRef> = html.select(&href_selector).collect();
-            let mut districts_set: HashSet<CategoryStructure> = HashSet::new();
-            for district in districts {
-                let district_name = district.text().collect::<String>();
-                let Some(district_href) = district.value().attr("href") else {
-                    Logger::info(format!("Failed to get href attribute from {:?}", district_name).as_str());
-                    continue;
-                };
+    async fn crawl_category(&self, category: Category) -> Result<HashSet<City>, anyhow::Error> {
+        let city_links = self.fetch_city_links(category).await?;
 
-                let full_deal_types_url = format!("{}{}", self.url_base, district_href);
-                let raw_deal_types_html: Result<String, anyhow::Error> = {
-                    let res = self.request_client.get(&full_deal_types_url).send().await?;
-                    if !res.status().is_success() {
-                        return Err(anyhow::anyhow!(
-                            "Failed to get successful response from {}",
-                            full_deal_types_url
-                        ));
-                    }
-                    let res = res.text().await?;
-                    Ok(res)
-                };
+        // make requests to get districts for each city
+        let mut cities: HashSet<City> = HashSet::new();
+        for (city_name, city_href) in city_links {
+            if let Some(city) = self.parse_city(city_name, city_href).await? {
+                cities.insert(city);
+            }
+        }
+        Ok(cities)
+    }
+
+    /// Loads the districts of a single city only, much cheaper than [`Self::parse_category_data`].
+    pub async fn parse_city_data(
+        &mut self,
+        category: Category,
+        city_name: &str,
+    ) -> Result<(), anyhow::Error> {
+        let cities = self.fetch_city_links(category).await?;
+        let Some((city_name, city_href)) = cities
+            .into_iter()
+            .find(|(name, _)| name.to_lowercase() == city_name.trim().to_lowercase())
+        else {
+            return Err(anyhow::anyhow!("City '{}' not found", city_name));
+        };
+        let Some(city) = self.parse_city(city_name.clone(), city_href).await? else {
+            return Err(anyhow::anyhow!(
+                "Failed to load districts of '{}'",
+                city_name
+            ));
+        };
+        self.catalogs.entry(category).or_default().insert(city);
+        Ok(())
+    }
+
+    async fn fetch_city_links(
+        &self,
+        category: Category,
+    ) -> Result<Vec<(String, String)>, anyhow::Error> {
+        let full_url = format!(
+            "{}/{}/real-estate/{}/",
+            self.url_base,
+            self.language.ss_path(),
+            category.ss_path()
+        );
+        let raw_html = self.fetch_html(&full_url).await?;
+        let links = Self::parse_category_links(&raw_html)?;
+        if links.is_empty() {
+            self.report_drift(
+                &full_url,
+                CATEGORY_LINK_SELECTOR,
+                String::from("found no cities"),
+            );
+        }
+        Ok(links)
+    }
+
+    /// Returns `None` when the city page itself could not be loaded.
+    async fn parse_city(
+        &self,
+        city_name: String,
+        city_href: String,
+    ) -> Result<Option<City>, anyhow::Error> {
+        let full_url = format!("{}{}", self.url_base, city_href);
+        let raw_html = match self.fetch_html(&full_url).await {
+            Ok(raw_html) => raw_html,
+            Err(error) => {
+                Logger::info(
+                    format!("Failed to get response from {}: {}", full_url, error).as_str(),
+                );
+                return Ok(None);
+            }
+        };
 
-                if let Err(error) = raw_deal_types_html {
+        let districts = Self::parse_category_links(&raw_html)?;
+        if districts.is_empty() {
+            self.report_drift(
+                &full_url,
+                CATEGORY_LINK_SELECTOR,
+                String::from("found no districts"),
+            );
+        }
+        let mut districts_set: HashSet<CategoryStructure> = HashSet::new();
+        for (district_name, district_href) in districts {
+            let full_deal_types_url = format!("{}{}", self.url_base, district_href);
+            let raw_deal_types_html = match self.fetch_html(&full_deal_types_url).await {
+                Ok(raw_html) => raw_html,
+                Err(error) => {
                     Logger::info(
                         format!(
                             "Failed to get response from {}: {}",
@@ -183,68 +701,245 @@ impl FlatsParser {
                         error
                     ));
                 }
-                let deal_types_html = Html::parse_document(&raw_deal_types_html.unwrap());
-                let Ok(deal_types_selector) = Selector::parse("select.filter_sel.l100 > option") else {
-                    Logger::info("Failed to parse selector");
-                    return Err(anyhow::anyhow!("Failed to parse selector"));
+            };
+            let deal_types = Self::parse_deal_types(&raw_deal_types_html)?;
+            if deal_types.is_empty() {
+                self.report_drift(
+                    &full_deal_types_url,
+                    DEAL_TYPE_SELECTOR,
+                    String::from("found no deal types"),
+                );
+            }
+
+            districts_set.insert(CategoryStructure {
+                name: district_name,
+                href: district_href,
+                deal_types,
+            });
+        }
+        Ok(Some(City {
+            name: city_name,
+            href: city_href,
+            districts: districts_set,
+        }))
+    }
+
+    /// Collects `(name, href)` pairs of all `a.a_category` links on a catalog page.
+    fn parse_category_links(raw_html: &str) -> Result<Vec<(String, String)>, anyhow::Error> {
+        let html = Html::parse_document(raw_html);
+        let Ok(href_selector) = Selector::parse(CATEGORY_LINK_SELECTOR) else {
+            Logger::info("Failed to parse selector");
+            return Err(anyhow::anyhow!("Failed to parse selector"));
+        };
+
+        let mut links: Vec<(String, String)> = Vec::new();
+        for element in html.select(&href_selector) {
+            let name = element.text().collect::<String>();
+            let Some(href) = element.value().attr("href") else {
+                Logger::info(format!("Failed to get href attribute for {:?}", name).as_str());
+                continue;
+            };
+            if links.iter().any(|(_, known_href)| known_href == href) {
+                continue;
+            }
+            links.push((name, href.to_string()));
+        }
+        Ok(links)
+    }
+
+    fn parse_deal_types(raw_html: &str) -> Result<Vec<DealType>, anyhow::Error> {
+        let Ok(regex) = Regex::new(r"\u{a0}") else {
+            Logger::info("Failed to create regex");
+            return Err(anyhow::anyhow!("Failed to create regex"));
+        };
+        let html = Html::parse_document(raw_html);
+        let Ok(deal_types_selector) = Selector::parse(DEAL_TYPE_SELECTOR) else {
+            Logger::info("Failed to parse selector");
+            return Err(anyhow::anyhow!("Failed to parse selector"));
+        };
+
+        let deal_types = html
+            .select(&deal_types_selector)
+            .filter_map(|option| {
+                let name = option.text().collect::<String>();
+                let name = regex.replace_all(&name, "").trim().to_string();
+                let Some(href) = option.value().attr("value") else {
+                    Logger::info(format!("Failed to get value attribute for {:?}", name).as_str());
+                    return None;
                 };
+                Some(DealType {
+                    name,
+                    href: href.to_string(),
+                })
+            })
+            .collect();
+        Ok(deal_types)
+    }
+
+    /// Resolves the category hrefs to crawl for the selected districts and deal types.
+    pub fn criteria_hrefs(&self, flat_criteria: &FlatCriteria) -> Vec<String> {
+        self.criteria_locations(flat_criteria).into_keys().collect()
+    }
 
-                let deal_types = deal_types_html
-                    .select(&deal_types_selector)
-                    .collect::<Vec<ElementRef>>();
+    /// Category hrefs of the criteria with the district and deal type names they list.
+    fn criteria_locations(&self, flat_criteria: &FlatCriteria) -> BTreeMap<String, (&str, &str)> {
+        let Some(city) = self.find_city(flat_criteria.category, &flat_criteria.city) else {
+            return BTreeMap::new();
+        };
 
-                let deal_types: Vec<String> = deal_types
+        city.districts
+            .iter()
+            .filter(|district| flat_criteria.districts.contains(&district.name))
+            .flat_map(|district| {
+                district
+                    .deal_types
                     .iter()
-                    .map(|deal_type| {
-                        let deal_type = deal_type.text().collect::<String>();
-                        let cleaned_deal_type = regex.replace_all(&deal_type, "");
-                        cleaned_deal_type.to_string()
-                    })
-                    .collect();
-
-                districts_set.insert(CategoryStructure {
-                    name: district_name.to_string(),
-                    href: district_href.to_string(),
-                    deal_types,
-                });
+                    .map(move |deal_type| (district.name.as_str(), deal_type))
+            })
+            .filter(|(_, deal_type)| flat_criteria.deal_types.contains(&deal_type.name))
+            .map(|(district, deal_type)| {
+                (deal_type.href.clone(), (district, deal_type.name.as_str()))
+            })
+            .collect()
+    }
+
+    pub async fn parse_flats_by_criteria(
+        &self,
+        flat_criteria: &FlatCriteria,
+    ) -> Result<Vec<Flat>, anyhow::Error> {
+        let locations = self.criteria_locations(flat_criteria);
+        if locations.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No categories match the selected districts and deal types"
+            ));
+        }
+
+        let mut seen_urls: HashSet<String> = HashSet::new();
+        let mut flats: Vec<Flat> = Vec::new();
+        for (href, (district, deal_type)) in locations {
+            for mut flat in self.parse_flats_by_href(&href, flat_criteria).await? {
+                if flat_criteria.matches(&flat) && seen_urls.insert(flat.url.clone()) {
+                    flat.city = flat_criteria.city.clone();
+                    flat.district = district.to_string();
+                    flat.deal_type = deal_type.to_string();
+                    flats.push(flat);
+                }
             }
-            self.cities.insert(City {
-                name: city_name,
-                href: city_href,
-                districts: districts_set,
-            });
         }
-        Ok(())
+        Ok(flats)
     }
 
-    pub async fn parse_flats_by_criteria(
+    /// Filter form of a category href, fetched from its first listing page once.
+    pub async fn filter_form(&self, href: &str) -> Result<Option<FilterForm>, anyhow::Error> {
+        let href = href.trim_end_matches('/');
+        if let Some(form) = self.cached_filter_form(href) {
+            return Ok(Some(form));
+        }
+        let full_url = format!("{}{}/page1.html", self.url_base, href);
+        let raw_html = self.fetch_html(&full_url).await?;
+        let Some(form) = FilterForm::parse(&Html::parse_document(&raw_html)) else {
+            Logger::debug(format!("No filter form on {}", full_url).as_str());
+            return Ok(None);
+        };
+        if let Ok(mut filter_forms) = self.filter_forms.lock() {
+            filter_forms.insert(href.to_string(), form.clone());
+        }
+        Ok(Some(form))
+    }
+
+    fn cached_filter_form(&self, href: &str) -> Option<FilterForm> {
+        self.filter_forms.lock().ok()?.get(href).cloned()
+    }
+
+    /// Crawls every listing page of a single category href, through the filter form when the
+    /// criteria narrow the search so ss.com only returns matching pages.
+    async fn parse_flats_by_href(
         &self,
-        flat_criteria: FlatCriteria,
-    ) -> Result<(), anyhow::Error> {
-        let full_url = format!("{}{}/page1.html", self.url_base, flat_criteria.href);
-        let raw_html: Result<String, anyhow::Error> = {
-            let res = self.request_client.get(&full_url).send().await?;
-            if !res.status().is_success() {
+        href: &str,
+        flat_criteria: &FlatCriteria,
+    ) -> Result<Vec<Flat>, anyhow::Error> {
+        let category = flat_criteria.category;
+        let href = href.trim_end_matches('/');
+        let filter_values = if flat_criteria.has_filters() {
+            match self.filter_form(href).await {
+                Ok(form) => form
+                    .map(|form| (form.action.clone(), form.values(flat_criteria)))
+                    .filter(|(_, values)| !values.is_empty()),
+                Err(error) => {
+                    Logger::info(
+                        format!("Failed to load the filter form of {}: {}", href, error).as_str(),
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let (client, pages_base, full_url, first_page) = match filter_values {
+            Some((action, values)) => {
+                // ss.com keeps a submitted filter in the session cookie and the following pages
+                // are plain GETs, so concurrent filtered searches must not share a cookie jar
+                let session = Client::builder().cookie_store(true).build()?;
+                let pages_base = format!("{}{}", self.url_base, action.trim_end_matches('/'));
+                let full_url = format!("{}/", pages_base);
+                let first_page = self.post_form(&session, &full_url, &values).await;
+                (session, pages_base, full_url, first_page)
+            }
+            None => {
+                let pages_base = format!("{}{}", self.url_base, href);
+                let full_url = format!("{}/page1.html", pages_base);
+                let first_page = self.fetch_html(&full_url).await;
+                (
+                    self.request_client.clone(),
+                    pages_base,
+                    full_url,
+                    first_page,
+                )
+            }
+        };
+        let raw_html = match first_page {
+            Ok(raw_html) => raw_html,
+            Err(error) => {
+                Logger::info(
+                    format!("Failed to get response from {}: {}", full_url, error).as_str(),
+                );
                 return Err(anyhow::anyhow!(
-                    "Failed to get successful response from {}",
-                    full_url
+                    "Failed to get response from {}: {}",
+                    full_url,
+                    error
                 ));
             }
-            let res = res.text().await?;
-            Ok(res)
         };
-        if let Err(error) = raw_html {
-            Logger::info(format!("Failed to get response from {}: {}", full_url, error).as_str());
-            return Err(anyhow::anyhow!(
-                "Failed to get response from {}: {}",
-                full_url,
-                error
-            ));
+        let (mut flats, pages_count) = self.parse_listing_page(&full_url, &raw_html, category)?;
+
+        for page in 2..=pages_count {
+            let full_url = format!("{}/page{}.html", pages_base, page);
+            let raw_html = match self.fetch_html_with(&client, &full_url).await {
+                Ok(raw_html) => raw_html,
+                Err(error) => {
+                    Logger::info(
+                        format!("Failed to get response from {}: {}", full_url, error).as_str(),
+                    );
+                    continue;
+                }
+            };
+            let (page_flats, _) = self.parse_listing_page(&full_url, &raw_html, category)?;
+            flats.extend(page_flats);
         }
-        let raw_html = raw_html?;
-        let document = Html::parse_document(&raw_html);
+        Ok(flats)
+    }
 
-        let Ok(table_selector) = Selector::parse("form#filter_frm>table>tbody") else {
+    /// Parses the flats table of a listing page and returns it together with the number of pages.
+    fn parse_listing_page(
+        &self,
+        url: &str,
+        raw_html: &str,
+        category: Category,
+    ) -> Result<(Vec<Flat>, u32), anyhow::Error> {
+        let document = Html::parse_document(raw_html);
+
+        let Ok(table_selector) = Selector::parse(LISTING_TABLE_SELECTOR) else {
             Logger::info("Failed to parse selector");
             return Err(anyhow::anyhow!("Failed to parse selector"));
         };
@@ -259,35 +954,531 @@ impl FlatsParser {
             return Err(anyhow::anyhow!("Failed to parse selector"));
         };
 
-        // only one page of flats
-        if document.select(&pages_selector).next().is_none() {}
-
-        let page_selector = document.select(&pages_selector).next();
-        if page_selector.is_none() {
-            return Err(anyhow::anyhow!("Failed to get page selector"));
-        }
-        let page_selector = page_selector.unwrap();
+        // only one page of flats when there is no pager
+        let pages_count = match document.select(&pages_selector).next() {
+            Some(page_selector) => page_selector
+                .select(&page_index_selector)
+                .filter_map(|page| page.text().collect::<String>().trim().parse::<u32>().ok())
+                .max()
+                .unwrap_or(1),
+            None => 1,
+        };
 
         let Some(tbody_element) = document.select(&table_selector).nth(1) else {
-            Logger::info("Failed to get tbody element");
+            self.report_drift(
+                url,
+                LISTING_TABLE_SELECTOR,
+                String::from("found no listings table"),
+            );
             return Err(anyhow::anyhow!("Failed to get tbody element"));
         };
 
-        let Ok(tr_element) = Selector::parse("tr") else {
+        let Ok(tr_selector) = Selector::parse(LISTING_ROW_SELECTOR) else {
             Logger::info("Failed to parse selector");
             return Err(anyhow::anyhow!("Failed to parse selector"));
-        
         };
-        let tr_elements = tbody_element.select(&tr_element).collect::<Vec<ElementRef>>();
+        let tr_elements = tbody_element
+            .select(&tr_selector)
+            .collect::<Vec<ElementRef>>();
+        let num_rows = tr_elements.len();
+        let header_names = tr_elements
+            .first()
+            .map(Self::parse_header_names)
+            .unwrap_or_default();
+        let mut flats: Vec<Flat> = Vec::new();
+        let mut failed_rows = 0;
         for (index, tr_element) in tr_elements.iter().enumerate() {
-            let num_rows = tr_elements.len();
             if index == 0 || index == num_rows - 1 {
-                continue; // Skip the first and last rows
+                continue; // Skip the header and the last rows
             }
-            println!("element: {:?}", tr_element);
+            match self.parse_flat_row(tr_element, &header_names, category) {
+                Some(flat) => flats.push(flat),
+                None => {
+                    failed_rows += 1;
+                    Logger::debug(
+                        format!("Failed to parse flat row {:?}", tr_element.value().id()).as_str(),
+                    )
+                }
+            }
+        }
 
+        let data_rows = num_rows.saturating_sub(2);
+        if failed_rows > 0 && failed_rows as f64 / data_rows as f64 > self.row_failure_threshold {
+            self.report_drift(
+                url,
+                LISTING_ROW_SELECTOR,
+                format!("failed to parse {} of {} rows", failed_rows, data_rows),
+            );
         }
 
-        Ok(())
+        Ok((flats, pages_count))
+    }
+
+    pub async fn parse_flat_details(&self, url: &str) -> Result<FlatDetails, anyhow::Error> {
+        let full_url = if url.starts_with("http") {
+            url.to_string()
+        } else {
+            format!("{}{}", self.url_base, url)
+        };
+        let raw_html = self.fetch_html(&full_url).await?;
+        self.parse_details_page(&full_url, &raw_html)
+    }
+
+    fn parse_details_page(&self, url: &str, raw_html: &str) -> Result<FlatDetails, anyhow::Error> {
+        let document = Html::parse_document(raw_html);
+        let Ok(description_selector) = Selector::parse(DESCRIPTION_SELECTOR) else {
+            Logger::info("Failed to parse selector");
+            return Err(anyhow::anyhow!("Failed to parse selector"));
+        };
+        let Ok(option_name_selector) = Selector::parse("td.ads_opt_name") else {
+            Logger::info("Failed to parse selector");
+            return Err(anyhow::anyhow!("Failed to parse selector"));
+        };
+        let Ok(option_value_selector) = Selector::parse("td.ads_opt") else {
+            Logger::info("Failed to parse selector");
+            return Err(anyhow::anyhow!("Failed to parse selector"));
+        };
+        let Ok(price_selector) = Selector::parse("td.ads_price") else {
+            Logger::info("Failed to parse selector");
+            return Err(anyhow::anyhow!("Failed to parse selector"));
+        };
+        let Ok(photo_selector) = Selector::parse("div.pic_dv_thumbnail a") else {
+            Logger::info("Failed to parse selector");
+            return Err(anyhow::anyhow!("Failed to parse selector"));
+        };
+        let Ok(footer_selector) = Selector::parse("td.msg_footer") else {
+            Logger::info("Failed to parse selector");
+            return Err(anyhow::anyhow!("Failed to parse selector"));
+        };
+
+        let Some(description_element) = document.select(&description_selector).next() else {
+            self.report_drift(
+                url,
+                DESCRIPTION_SELECTOR,
+                String::from("found no description"),
+            );
+            return Err(anyhow::anyhow!("Failed to find description on {}", url));
+        };
+        // the description div also contains the options table, keep only its own text
+        let description = description_element
+            .children()
+            .filter_map(|child| child.value().as_text().map(|text| text.trim().to_string()))
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let attributes = document
+            .select(&option_name_selector)
+            .zip(document.select(&option_value_selector))
+            .map(|(name, value)| (text_of(name), text_of(value)))
+            .collect::<BTreeMap<_, _>>();
+
+        let price = document
+            .select(&price_selector)
+            .next()
+            .map(text_of)
+            .unwrap_or_default();
+
+        let photo_urls = document
+            .select(&photo_selector)
+            .filter_map(|photo| photo.value().attr("href"))
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        let published = document
+            .select(&footer_selector)
+            .map(text_of)
+            .find_map(|footer| {
+                footer
+                    .split_once(':')
+                    .filter(|(label, _)| label.contains("Datums") || label.contains("Дата"))
+                    .map(|(_, date)| date.trim().to_string())
+            });
+
+        Ok(FlatDetails {
+            url: url.to_string(),
+            price,
+            description,
+            attributes,
+            photo_urls,
+            published,
+        })
+    }
+
+    /// Column titles of the data columns, from the header row of a listing table.
+    fn parse_header_names(header_row: &ElementRef) -> Vec<String> {
+        let Ok(td_selector) = Selector::parse("td") else {
+            return Vec::new();
+        };
+        // the first cell is the title of the image and description columns
+        header_row
+            .select(&td_selector)
+            .skip(1)
+            .map(text_of)
+            .collect()
+    }
+
+    fn parse_flat_row(
+        &self,
+        tr_element: &ElementRef,
+        header_names: &[String],
+        category: Category,
+    ) -> Option<Flat> {
+        let td_selector = Selector::parse("td").ok()?;
+        let link_selector = Selector::parse("a.am").ok()?;
+        let image_selector = Selector::parse("img").ok()?;
+
+        // other categories are read by their header titles, they differ between sections
+        let data_columns = match category {
+            Category::Flats => FLAT_COLUMNS,
+            // at least the address and the price
+            _ if header_names.len() < 2 => return None,
+            _ => header_names.len(),
+        };
+        let cells = tr_element.select(&td_selector).collect::<Vec<ElementRef>>();
+        // checkbox, image and description come before the data columns
+        if cells.len() < data_columns + 3 {
+            return None;
+        }
+        let link = tr_element.select(&link_selector).next()?;
+        let url = format!("{}{}", self.url_base, link.value().attr("href")?);
+        let image_url = tr_element
+            .select(&image_selector)
+            .next()
+            .and_then(|image| image.value().attr("src"))
+            .unwrap_or_default()
+            .to_string();
+
+        let columns = cells[cells.len() - data_columns..]
+            .iter()
+            .map(|cell| text_of(*cell))
+            .collect::<Vec<String>>();
+        let summary = text_of(cells[cells.len() - data_columns - 1]);
+
+        if category == Category::Flats {
+            // the last six columns are always street, rooms, m2, floor, series and price
+            let (floor, total_floors) = parse_floor(&columns[3]);
+            return Some(Flat {
+                street_name: columns[0].clone(),
+                rooms: leading_number(&columns[1]),
+                square_meters: leading_number(&columns[2]),
+                floor,
+                total_floors,
+                series: Series::from(columns[4].clone()),
+                summary,
+                city: String::new(),
+                district: String::new(),
+                deal_type: String::new(),
+                location: None,
+                deal: None,
+                repost: None,
+                price: columns[5].clone(),
+                url,
+                image_url,
+                category,
+                source: Source::Ss,
+                fields: BTreeMap::new(),
+            });
+        }
+
+        // the first column is always the address and the last one the price
+        let last = data_columns - 1;
+        let fields = header_names[1..last]
+            .iter()
+            .cloned()
+            .zip(columns[1..last].iter().cloned())
+            .collect::<BTreeMap<_, _>>();
+        let number_of = |titles: &[&str]| {
+            fields
+                .iter()
+                .find(|(title, _)| titles.contains(&title.to_lowercase().as_str()))
+                .map(|(_, value)| leading_number(value))
+                .unwrap_or_default()
+        };
+        Some(Flat {
+            street_name: columns[0].clone(),
+            rooms: number_of(&ROOMS_TITLES),
+            square_meters: number_of(&AREA_TITLES),
+            floor: number_of(&FLOOR_TITLES),
+            total_floors: 0,
+            series: Series::default(),
+            summary,
+            city: String::new(),
+            district: String::new(),
+            deal_type: String::new(),
+            location: None,
+            deal: None,
+            repost: None,
+            price: columns[last].clone(),
+            url,
+            image_url,
+            category,
+            source: Source::Ss,
+            fields,
+        })
+    }
+}
+
+/// Text of an element with whitespace between its text nodes collapsed.
+fn text_of(element: ElementRef) -> String {
+    element
+        .text()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Splits `3/9` into the floor and the floors of the building, `3` alone leaves the latter 0.
+fn parse_floor(text: &str) -> (u32, u32) {
+    let mut parts = text.split('/').map(str::trim);
+    let floor = parts.next().map(leading_number).unwrap_or_default();
+    let total_floors = parts.next().map(leading_number).unwrap_or_default();
+    (floor, total_floors)
+}
+
+fn leading_number(text: &str) -> u32 {
+    text.chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>()
+        .parse::<u32>()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat_priced(price: &str) -> Flat {
+        Flat {
+            price: price.to_string(),
+            ..Flat::default()
+        }
+    }
+
+    #[test]
+    fn price_value_ignores_separators_and_currency() {
+        assert_eq!(flat_priced("85,000 €").price_value(), Some(85000));
+        assert_eq!(flat_priced("450 €/mēn.").price_value(), Some(450));
+        assert_eq!(flat_priced("1 250 000 €").price_value(), Some(1250000));
+    }
+
+    fn flat_at(street_name: &str) -> Flat {
+        Flat {
+            street_name: street_name.to_string(),
+            rooms: 2,
+            square_meters: 54,
+            floor: 3,
+            price: String::from("85 000 €"),
+            ..Flat::default()
+        }
+    }
+
+    #[test]
+    fn dedup_key_ignores_street_types_and_diacritics() {
+        let key = flat_at("Brīvības iela 12").dedup_key();
+        assert_eq!(flat_at("Brivibas 12").dedup_key(), key);
+        assert_eq!(flat_at("brīvības  12").dedup_key(), key);
+        assert_eq!(
+            flat_at("K. Barona 5").dedup_key(),
+            flat_at("K. Barona iela 5").dedup_key()
+        );
+    }
+
+    #[test]
+    fn dedup_key_tells_houses_and_flats_apart() {
+        let key = flat_at("Brīvības iela 12").dedup_key();
+        assert_ne!(flat_at("Brīvības iela 14").dedup_key(), key);
+        assert_ne!(flat_at("Brīvības gatve 12").dedup_key(), key);
+        let mut bigger = flat_at("Brīvības iela 12");
+        bigger.square_meters = 60;
+        assert_ne!(bigger.dedup_key(), key);
+    }
+
+    #[test]
+    fn price_value_is_none_without_digits() {
+        assert_eq!(flat_priced("maiņai").price_value(), None);
+        assert_eq!(flat_priced("").price_value(), None);
+    }
+
+    #[test]
+    fn parse_floor_splits_floor_and_total() {
+        assert_eq!(parse_floor("3/9"), (3, 9));
+        assert_eq!(parse_floor(" 12 / 16 "), (12, 16));
+        assert_eq!(parse_floor("2/5/lifts"), (2, 5));
+    }
+
+    #[test]
+    fn parse_floor_defaults_missing_parts_to_zero() {
+        assert_eq!(parse_floor("4"), (4, 0));
+        assert_eq!(parse_floor("-/5"), (0, 5));
+        assert_eq!(parse_floor(""), (0, 0));
+    }
+
+    fn criteria_with_keywords(include: &[&str], exclude: &[&str]) -> FlatCriteria {
+        serde_json::from_value(serde_json::json!({
+            "city": "Rīga",
+            "districts": [],
+            "deal_types": [],
+            "price_from": 0,
+            "price_to": u32::MAX,
+            "include_keywords": include,
+            "exclude_keywords": exclude,
+        }))
+        .expect("criteria are valid")
+    }
+
+    #[test]
+    fn matches_text_requires_every_included_phrase() {
+        let criteria = criteria_with_keywords(&["balkons", "lifts"], &[]);
+        assert!(criteria.has_keywords());
+        assert!(criteria.matches_text("Balkons uz pagalmu, mājā ir LIFTS"));
+        assert!(!criteria.matches_text("Dzīvoklis ar balkonu, bez lifta"));
+    }
+
+    #[test]
+    fn matches_text_rejects_any_excluded_phrase() {
+        let criteria = criteria_with_keywords(&[], &["bez remonta", "krāsns"]);
+        assert!(criteria.matches_text("Pēc kapitālā remonta"));
+        assert!(!criteria.matches_text("Pārdod dzīvokli BEZ REMONTA"));
+        assert!(!criteria.matches_text("Apkure - krāsns"));
+    }
+
+    #[test]
+    fn matches_text_accepts_anything_without_keywords() {
+        let criteria = criteria_with_keywords(&[], &[]);
+        assert!(!criteria.has_keywords());
+        assert!(criteria.matches_text(""));
+    }
+
+    const FILTER_HREF: &str = "/lv/real-estate/flats/riga/centre/sell/";
+
+    /// A listing page whose rows are all on `street`, with a pager when there is a next page.
+    fn listing_page(street: &str, pages: u32) -> String {
+        let row = |index: u32| {
+            format!(
+                "<tr><td></td><td><img src=\"/{street}.jpg\"></td>\
+                 <td><a class=\"am\" href=\"/msg/{street}-{index}.html\">Pārdod</a></td>\
+                 <td>{street}</td><td>2</td><td>54</td><td>3/5</td><td>LT proj.</td>\
+                 <td>85 000 €</td></tr>"
+            )
+        };
+        let pager = (1..=pages)
+            .map(|page| format!("<a href=\"page{page}.html\">{page}</a>"))
+            .collect::<String>();
+        format!(
+            "<html><body><form id=\"filter_frm\"><table><tbody><tr><td></td></tr></tbody></table>\
+             <table><tbody><tr><td></td><td>Iela</td></tr>{}{}<tr><td></td></tr></tbody></table>\
+             <div class=\"td2\">{pager}</div></form></body></html>",
+            row(1),
+            row(2),
+        )
+    }
+
+    /// The page of the filter kept in the session cookie, like ss.com lists the filtered pages.
+    fn filtered_page(headers: &axum::http::HeaderMap, page: u32) -> String {
+        let price = headers
+            .get(axum::http::header::COOKIE)
+            .and_then(|cookie| cookie.to_str().ok())
+            .and_then(|cookie| cookie.strip_prefix("filter="))
+            .unwrap_or_default();
+        listing_page(&format!("page{}-{}", page, price), 3)
+    }
+
+    /// Serves the filter form and remembers the submitted minimal price in the session cookie.
+    /// The second page is answered only once both searches ask for it, so both filters are
+    /// submitted before either search fetches its third page.
+    async fn serve_filtered_listings() -> String {
+        use axum::extract::Form;
+        use axum::http::{header, HeaderMap};
+        use axum::routing::{get, post};
+
+        let second_pages = Arc::new(tokio::sync::Barrier::new(2));
+        let filter_path = format!("{}filter/", FILTER_HREF);
+        let app = axum::Router::new()
+            .route(
+                &format!("{}page1.html", FILTER_HREF),
+                get(|| async { include_str!("fixtures/filter_frm.html") }),
+            )
+            .route(
+                &filter_path,
+                post(|Form(values): Form<HashMap<String, String>>| async move {
+                    let price = values.get("topt[8][min]").cloned().unwrap_or_default();
+                    (
+                        [(header::SET_COOKIE, format!("filter={}; Path=/", price))],
+                        listing_page(&format!("page1-{}", price), 3),
+                    )
+                }),
+            )
+            .route(
+                &format!("{}page2.html", filter_path),
+                get(move |headers: HeaderMap| async move {
+                    second_pages.wait().await;
+                    filtered_page(&headers, 2)
+                }),
+            )
+            .route(
+                &format!("{}page3.html", filter_path),
+                get(|headers: HeaderMap| async move { filtered_page(&headers, 3) }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a free port");
+        let address = listener.local_addr().expect("a local address");
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{}", address)
+    }
+
+    fn streets(flats: &[Flat]) -> Vec<&str> {
+        flats.iter().map(|flat| flat.street_name.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn interleaved_filtered_searches_keep_their_own_filter() {
+        let parser = FlatsParser::new(serve_filtered_listings().await, Language::Lv);
+        let cheap: FlatCriteria = serde_json::from_value(serde_json::json!({
+            "city": "Rīga",
+            "districts": ["Centrs"],
+            "deal_types": ["Pārdod"],
+            "price_from": 10000,
+            "price_to": u32::MAX,
+        }))
+        .expect("criteria are valid");
+        let pricey: FlatCriteria = serde_json::from_value(serde_json::json!({
+            "city": "Rīga",
+            "districts": ["Centrs"],
+            "deal_types": ["Pārdod"],
+            "price_from": 90000,
+            "price_to": u32::MAX,
+        }))
+        .expect("criteria are valid");
+
+        let (cheap_flats, pricey_flats) = tokio::join!(
+            parser.parse_flats_by_href(FILTER_HREF, &cheap),
+            parser.parse_flats_by_href(FILTER_HREF, &pricey),
+        );
+
+        assert_eq!(
+            streets(&cheap_flats.expect("cheap flats")),
+            [
+                "page1-10000",
+                "page1-10000",
+                "page2-10000",
+                "page2-10000",
+                "page3-10000",
+                "page3-10000"
+            ]
+        );
+        assert_eq!(
+            streets(&pricey_flats.expect("pricey flats")),
+            [
+                "page1-90000",
+                "page1-90000",
+                "page2-90000",
+                "page2-90000",
+                "page3-90000",
+                "page3-90000"
+            ]
+        );
     }
 }
@@ -0,0 +1,214 @@
+use crate::i18n::Language;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Building series of a flat as ss.com abbreviates it, e.g. `LT proj.` or `Hrušč.`.
+///
+/// Serialized as [`Series::code`], unknown abbreviations are kept verbatim in `Other`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Series {
+    Lithuanian,
+    S103,
+    S104,
+    S119,
+    S467,
+    S602,
+    Khrushchev,
+    Stalin,
+    PreWar,
+    NewBuild,
+    Renovated,
+    Special,
+    SmallFamily,
+    PrivateHouse,
+    Czech,
+    Other(String),
+}
+
+impl Default for Series {
+    fn default() -> Self {
+        Series::Other(String::new())
+    }
+}
+
+impl Series {
+    pub const KNOWN: [Series; 15] = [
+        Series::Lithuanian,
+        Series::S103,
+        Series::S104,
+        Series::S119,
+        Series::S467,
+        Series::S602,
+        Series::Khrushchev,
+        Series::Stalin,
+        Series::PreWar,
+        Series::NewBuild,
+        Series::Renovated,
+        Series::Special,
+        Series::SmallFamily,
+        Series::PrivateHouse,
+        Series::Czech,
+    ];
+
+    pub fn code(&self) -> &str {
+        match self {
+            Series::Lithuanian => "lt",
+            Series::S103 => "103",
+            Series::S104 => "104",
+            Series::S119 => "119",
+            Series::S467 => "467",
+            Series::S602 => "602",
+            Series::Khrushchev => "khrushchev",
+            Series::Stalin => "stalin",
+            Series::PreWar => "prewar",
+            Series::NewBuild => "new",
+            Series::Renovated => "renovated",
+            Series::Special => "special",
+            Series::SmallFamily => "small_family",
+            Series::PrivateHouse => "private_house",
+            Series::Czech => "czech",
+            Series::Other(text) => text,
+        }
+    }
+
+    /// Abbreviations ss.com uses on Latvian and Russian pages.
+    fn ss_names(&self) -> [&'static str; 2] {
+        match self {
+            Series::Lithuanian => ["LT proj.", "Лит. пр."],
+            Series::S103 => ["103.", "103."],
+            Series::S104 => ["104.", "104."],
+            Series::S119 => ["119.", "119."],
+            Series::S467 => ["467.", "467."],
+            Series::S602 => ["602.", "602."],
+            Series::Khrushchev => ["Hrušč.", "Хрущ."],
+            Series::Stalin => ["Staļina", "Сталинка"],
+            Series::PreWar => ["P. kara", "Довоен."],
+            Series::NewBuild => ["Jaun.", "Нов."],
+            Series::Renovated => ["Renov.", "Рекон."],
+            Series::Special => ["Specpr.", "Спец. пр."],
+            Series::SmallFamily => ["M. ģim.", "М. сем."],
+            Series::PrivateHouse => ["Priv. m.", "Ч. дом"],
+            Series::Czech => ["Čehu pr.", "Чешск."],
+            Series::Other(_) => ["", ""],
+        }
+    }
+
+    /// Parses a code, an ss.com abbreviation or a name in any supported language.
+    pub fn from_name(name: &str) -> Option<Series> {
+        let name = name.trim().to_lowercase();
+        Series::KNOWN.into_iter().find(|series| {
+            series.code() == name
+                || series
+                    .ss_names()
+                    .iter()
+                    .any(|ss_name| ss_name.to_lowercase() == name)
+                || Language::ALL
+                    .iter()
+                    .any(|language| series.name(*language).to_lowercase() == name)
+        })
+    }
+
+    pub fn name(&self, language: Language) -> &str {
+        use Language::{En, Lv, Ru};
+        match (self, language) {
+            (Series::Lithuanian, Lv) => "Lietuviešu projekts",
+            (Series::Lithuanian, Ru) => "Литовский проект",
+            (Series::Lithuanian, En) => "Lithuanian project",
+            (Series::S103, _) => "103",
+            (Series::S104, _) => "104",
+            (Series::S119, _) => "119",
+            (Series::S467, _) => "467",
+            (Series::S602, _) => "602",
+            (Series::Khrushchev, Lv) => "Hruščovka",
+            (Series::Khrushchev, Ru) => "Хрущёвка",
+            (Series::Khrushchev, En) => "Khrushchyovka",
+            (Series::Stalin, Lv) => "Staļinka",
+            (Series::Stalin, Ru) => "Сталинка",
+            (Series::Stalin, En) => "Stalin era",
+            (Series::PreWar, Lv) => "Pirmskara",
+            (Series::PreWar, Ru) => "Довоенный",
+            (Series::PreWar, En) => "Pre-war",
+            (Series::NewBuild, Lv) => "Jaunais projekts",
+            (Series::NewBuild, Ru) => "Новый проект",
+            (Series::NewBuild, En) => "New build",
+            (Series::Renovated, Lv) => "Renovēts",
+            (Series::Renovated, Ru) => "Реконструированный",
+            (Series::Renovated, En) => "Renovated",
+            (Series::Special, Lv) => "Specprojekts",
+            (Series::Special, Ru) => "Спецпроект",
+            (Series::Special, En) => "Special project",
+            (Series::SmallFamily, Lv) => "Mazģimenes",
+            (Series::SmallFamily, Ru) => "Малосемейка",
+            (Series::SmallFamily, En) => "Small family",
+            (Series::PrivateHouse, Lv) => "Privātmāja",
+            (Series::PrivateHouse, Ru) => "Частный дом",
+            (Series::PrivateHouse, En) => "Private house",
+            (Series::Czech, Lv) => "Čehu projekts",
+            (Series::Czech, Ru) => "Чешский проект",
+            (Series::Czech, En) => "Czech project",
+            (Series::Other(text), _) => text,
+        }
+    }
+
+    /// Whether an ss.com filter form option stands for this series.
+    pub fn matches_ss_name(&self, ss_name: &str) -> bool {
+        Series::from_name(ss_name).as_ref() == Some(self)
+    }
+}
+
+impl From<String> for Series {
+    fn from(text: String) -> Self {
+        Series::from_name(&text).unwrap_or(Series::Other(text.trim().to_string()))
+    }
+}
+
+impl From<Series> for String {
+    fn from(series: Series) -> Self {
+        series.code().to_string()
+    }
+}
+
+impl fmt::Display for Series {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_name_accepts_codes_abbreviations_and_names() {
+        assert_eq!(Series::from_name("lt"), Some(Series::Lithuanian));
+        assert_eq!(Series::from_name("LT proj."), Some(Series::Lithuanian));
+        assert_eq!(Series::from_name("Лит. пр."), Some(Series::Lithuanian));
+        assert_eq!(Series::from_name(" hrušč. "), Some(Series::Khrushchev));
+        assert_eq!(Series::from_name("Khrushchyovka"), Some(Series::Khrushchev));
+        assert_eq!(Series::from_name("119."), Some(Series::S119));
+        assert_eq!(Series::from_name("Малосемейка"), Some(Series::SmallFamily));
+    }
+
+    #[test]
+    fn from_name_rejects_unknown_series() {
+        assert_eq!(Series::from_name("Brick"), None);
+        assert_eq!(Series::from_name(""), None);
+    }
+
+    #[test]
+    fn unknown_series_round_trip_verbatim() {
+        let series = Series::from(String::from(" Koka "));
+        assert_eq!(series, Series::Other(String::from("Koka")));
+        assert_eq!(String::from(series), "Koka");
+        assert_eq!(String::from(Series::from(String::from("Jaun."))), "new");
+    }
+
+    #[test]
+    fn every_known_series_parses_from_its_code() {
+        for series in Series::KNOWN {
+            assert_eq!(Series::from_name(series.code()), Some(series.clone()));
+            assert!(series.matches_ss_name(series.ss_names()[0]));
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use super::{Area, Geocoder, Precision, KM_PER_DEGREE};
+use crate::flats::Flat;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+use plotters::prelude::*;
+use std::collections::BTreeSet;
+
+const MAP_SIZE: u32 = 800;
+/// Prices are written next to the markers only while they do not overlap too much.
+const MAX_PRICE_LABELS: usize = 25;
+/// Points of the polygon drawn for a search area.
+const AREA_SEGMENTS: usize = 64;
+const STREET_COLOR: RGBColor = RGBColor(214, 39, 40);
+/// District centroids are approximate, they are drawn hollow.
+const DISTRICT_COLOR: RGBColor = RGBColor(255, 127, 14);
+const AREA_COLOR: RGBColor = RGBColor(31, 119, 180);
+const LABEL_COLOR: RGBColor = RGBColor(130, 130, 130);
+
+/// PNG of the located listings on a plain coordinate grid with the district names of their
+/// cities for orientation, `None` when none of them could be located.
+pub fn render_map(
+    flats: &[Flat],
+    area: Option<&Area>,
+    geocoder: &Geocoder,
+) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    let located = flats
+        .iter()
+        .filter_map(|flat| Some((flat, flat.location?)))
+        .filter(|(_, location)| location.precision != Precision::City)
+        .collect::<Vec<_>>();
+    if located.is_empty() {
+        return Ok(None);
+    }
+
+    let mut points = located
+        .iter()
+        .map(|(_, location)| {
+            (
+                location.coordinates.longitude,
+                location.coordinates.latitude,
+            )
+        })
+        .collect::<Vec<_>>();
+    let area_outline = area.map(area_outline).unwrap_or_default();
+    points.extend(&area_outline);
+    let (longitudes, latitudes) = bounds(&points);
+
+    let mut pixels = vec![0u8; (MAP_SIZE * MAP_SIZE * 3) as usize];
+    {
+        let root =
+            BitMapBackend::with_buffer(&mut pixels, (MAP_SIZE, MAP_SIZE)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .margin(12)
+            .x_label_area_size(30)
+            .y_label_area_size(50)
+            .build_cartesian_2d(longitudes.clone(), latitudes.clone())?;
+        chart
+            .configure_mesh()
+            .light_line_style(WHITE)
+            .x_label_formatter(&|longitude| format!("{:.3}", longitude))
+            .y_label_formatter(&|latitude| format!("{:.3}", latitude))
+            .draw()?;
+
+        let cities = located
+            .iter()
+            .map(|(flat, _)| flat.city.as_str())
+            .collect::<BTreeSet<_>>();
+        let district_labels = cities
+            .into_iter()
+            .flat_map(|city| geocoder.districts(city))
+            .filter(|(_, coordinates)| {
+                longitudes.contains(&coordinates.longitude)
+                    && latitudes.contains(&coordinates.latitude)
+            });
+        chart.draw_series(district_labels.map(|(district, coordinates)| {
+            Text::new(
+                district.to_string(),
+                (coordinates.longitude, coordinates.latitude),
+                ("sans-serif", 13).into_font().color(&LABEL_COLOR),
+            )
+        }))?;
+
+        if let Some(area) = area {
+            chart.draw_series(std::iter::once(PathElement::new(
+                area_outline,
+                AREA_COLOR.stroke_width(2),
+            )))?;
+            chart.draw_series(std::iter::once(Cross::new(
+                (area.center.longitude, area.center.latitude),
+                6,
+                AREA_COLOR.stroke_width(2),
+            )))?;
+        }
+
+        chart.draw_series(located.iter().map(|(_, location)| {
+            let point = (
+                location.coordinates.longitude,
+                location.coordinates.latitude,
+            );
+            match location.precision {
+                Precision::Street => Circle::new(point, 5, STREET_COLOR.filled()),
+                _ => Circle::new(point, 6, DISTRICT_COLOR.stroke_width(2)),
+            }
+        }))?;
+        if located.len() <= MAX_PRICE_LABELS {
+            chart.draw_series(located.iter().map(|(flat, location)| {
+                Text::new(
+                    flat.price.clone(),
+                    (
+                        location.coordinates.longitude,
+                        location.coordinates.latitude,
+                    ),
+                    ("sans-serif", 12).into_font().color(&BLACK),
+                )
+            }))?;
+        }
+        root.present()?;
+    }
+
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(&pixels, MAP_SIZE, MAP_SIZE, ColorType::Rgb8)?;
+    Ok(Some(png))
+}
+
+/// Closed polygon of the area circle as `(longitude, latitude)` points.
+fn area_outline(area: &Area) -> Vec<(f64, f64)> {
+    let latitude_radius = area.radius_km / KM_PER_DEGREE;
+    let longitude_radius = latitude_radius / area.center.latitude.to_radians().cos();
+    (0..=AREA_SEGMENTS)
+        .map(|segment| {
+            let angle = segment as f64 / AREA_SEGMENTS as f64 * std::f64::consts::TAU;
+            (
+                area.center.longitude + longitude_radius * angle.cos(),
+                area.center.latitude + latitude_radius * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+/// Padded longitude and latitude ranges covering every point, widened so that a kilometer is
+/// as long horizontally as vertically.
+fn bounds(points: &[(f64, f64)]) -> (std::ops::Range<f64>, std::ops::Range<f64>) {
+    let (mut west, mut east, mut south, mut north) = points.iter().fold(
+        (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+        |(west, east, south, north), (longitude, latitude)| {
+            (
+                west.min(*longitude),
+                east.max(*longitude),
+                south.min(*latitude),
+                north.max(*latitude),
+            )
+        },
+    );
+    let longitude_scale = ((south + north) / 2.0).to_radians().cos();
+    // at least about a kilometer around a single listing
+    let padding = ((north - south).max((east - west) * longitude_scale) * 0.1).max(0.01);
+    (south, north) = (south - padding, north + padding);
+    (west, east) = (
+        west - padding / longitude_scale,
+        east + padding / longitude_scale,
+    );
+
+    let (width, height) = ((east - west) * longitude_scale, north - south);
+    if width < height {
+        let extra = (height - width) / longitude_scale / 2.0;
+        (west, east) = (west - extra, east + extra);
+    } else {
+        let extra = (width - height) / 2.0;
+        (south, north) = (south - extra, north + extra);
+    }
+    (west..east, south..north)
+}
@@ -0,0 +1,439 @@
+mod map;
+
+pub use map::render_map;
+
+use crate::flats::Flat;
+use crate::logger::Logger;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// Centroids bundled with the bot, see the file header for the format.
+const BUNDLED_DATASET: &str = include_str!("../../assets/geocoding/lv.csv");
+const EARTH_RADIUS_KM: f64 = 6371.0;
+/// Kilometers per degree of latitude.
+pub const KM_PER_DEGREE: f64 = 111.32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    /// Parses `56.9496,24.1052`.
+    pub fn parse(text: &str) -> Option<Coordinates> {
+        let (latitude, longitude) = text.split_once(',')?;
+        let coordinates = Coordinates {
+            latitude: latitude.trim().parse().ok()?,
+            longitude: longitude.trim().parse().ok()?,
+        };
+        ((-90.0..=90.0).contains(&coordinates.latitude)
+            && (-180.0..=180.0).contains(&coordinates.longitude))
+        .then_some(coordinates)
+    }
+
+    /// Great-circle distance by the haversine formula.
+    pub fn distance_km(&self, other: &Coordinates) -> f64 {
+        let (latitude, other_latitude) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let half_latitude = (other_latitude - latitude) / 2.0;
+        let half_longitude = (other.longitude - self.longitude).to_radians() / 2.0;
+        let a = half_latitude.sin().powi(2)
+            + latitude.cos() * other_latitude.cos() * half_longitude.sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+/// What a listing's coordinates stand for, only streets are exact enough for small radii.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Precision {
+    Street,
+    District,
+    City,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub coordinates: Coordinates,
+    pub precision: Precision,
+}
+
+/// A circle subscriptions can be narrowed to, regardless of district boundaries.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Area {
+    pub center: Coordinates,
+    pub radius_km: f64,
+}
+
+/// A district whose centroid is this much farther than the radius may still reach into it.
+pub const DISTRICT_MARGIN_KM: f64 = 2.0;
+
+impl Area {
+    /// Whether a listing lies within the circle. The bundled dataset has the major Riga streets
+    /// only, see `geocoding_dataset` for a fuller one, so listings on other streets match when
+    /// their district may reach into the circle. A city centroid says too little.
+    pub fn contains(&self, location: &Location) -> bool {
+        let distance_km = self.center.distance_km(&location.coordinates);
+        match location.precision {
+            Precision::Street => distance_km <= self.radius_km,
+            Precision::District => distance_km <= self.radius_km + DISTRICT_MARGIN_KM,
+            Precision::City => false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DatasetRow {
+    kind: String,
+    city: String,
+    district: String,
+    street: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+/// Resolves listing addresses to coordinates from an offline dataset of street, district and
+/// city centroids, falling back to the coarser ones when a street is unknown.
+#[derive(Debug, Default)]
+pub struct Geocoder {
+    /// Keyed by the normalized city and street name, ordered to look streets up by suffix.
+    streets: BTreeMap<(String, String), Coordinates>,
+    /// Also keeps the district name as written for map labels.
+    districts: HashMap<(String, String), (String, Coordinates)>,
+    cities: HashMap<String, Coordinates>,
+}
+
+impl Geocoder {
+    /// The bundled dataset extended by the one at `dataset_path`, whose rows take precedence.
+    pub fn load(dataset_path: Option<&Path>) -> Result<Self, anyhow::Error> {
+        let mut geocoder = Geocoder::default();
+        geocoder.add_dataset(BUNDLED_DATASET)?;
+        if let Some(path) = dataset_path {
+            let raw_csv = fs::read_to_string(path).map_err(|error| {
+                anyhow::anyhow!("Failed to read geocoding dataset {:?}: {}", path, error)
+            })?;
+            geocoder.add_dataset(&raw_csv).map_err(|error| {
+                anyhow::anyhow!("Failed to parse geocoding dataset {:?}: {}", path, error)
+            })?;
+        }
+        Logger::info(
+            format!(
+                "Geocoder loaded {} streets, {} districts and {} cities",
+                geocoder.streets.len(),
+                geocoder.districts.len(),
+                geocoder.cities.len()
+            )
+            .as_str(),
+        );
+        Ok(geocoder)
+    }
+
+    fn add_dataset(&mut self, raw_csv: &str) -> Result<(), anyhow::Error> {
+        let mut reader = csv::ReaderBuilder::new()
+            .comment(Some(b'#'))
+            .trim(csv::Trim::All)
+            .from_reader(raw_csv.as_bytes());
+        for row in reader.deserialize::<DatasetRow>() {
+            let row = row?;
+            let coordinates = Coordinates {
+                latitude: row.latitude,
+                longitude: row.longitude,
+            };
+            let city = normalize(&row.city);
+            match row.kind.as_str() {
+                "city" => {
+                    self.cities.insert(city, coordinates);
+                }
+                "district" => {
+                    let key = (city, normalize(&row.district));
+                    self.districts.insert(key, (row.district, coordinates));
+                }
+                "street" => {
+                    self.streets
+                        .insert((city, normalize_street(&row.street)), coordinates);
+                }
+                kind => return Err(anyhow::anyhow!("Unknown dataset row kind '{}'", kind)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Coordinates of the street, else of the district, else of the city of a listing.
+    pub fn locate(&self, flat: &Flat) -> Option<Location> {
+        let city = normalize(&flat.city);
+        let street = self
+            .street(&city, &flat.street_name)
+            .map(|coordinates| (coordinates, Precision::Street));
+        let district = || {
+            self.districts
+                .get(&(city.clone(), normalize(&flat.district)))
+                .map(|(_, coordinates)| (coordinates, Precision::District))
+        };
+        let city = || {
+            self.cities
+                .get(&city)
+                .map(|coordinates| (coordinates, Precision::City))
+        };
+        street
+            .or_else(district)
+            .or_else(city)
+            .map(|(coordinates, precision)| Location {
+                coordinates: *coordinates,
+                precision,
+            })
+    }
+
+    /// Exact match first, then the only street of the city whose full name ends with the given
+    /// one, e.g. `krisjana barona` for `K. Barona`. The initial of an abbreviated first name
+    /// picks between such streets, a name matching several of them is not located.
+    fn street(&self, city: &str, street_name: &str) -> Option<&Coordinates> {
+        let street = normalize_street(street_name);
+        if street.is_empty() {
+            return None;
+        }
+        if let Some(coordinates) = self.streets.get(&(city.to_string(), street.clone())) {
+            return Some(coordinates);
+        }
+        let suffix = format!(" {}", street);
+        let initial = street_initial(street_name);
+        let mut candidates = self
+            .streets
+            .range((city.to_string(), String::new())..)
+            .take_while(|((street_city, _), _)| street_city == city)
+            .filter(|((_, name), _)| {
+                name.ends_with(&suffix) && initial.is_none_or(|initial| name.starts_with(initial))
+            })
+            .map(|(_, coordinates)| coordinates);
+        match (candidates.next(), candidates.next()) {
+            (Some(coordinates), None) => Some(coordinates),
+            _ => None,
+        }
+    }
+
+    /// Centroid of a district of a city, names are matched regardless of diacritics.
+    pub fn district(&self, city: &str, district: &str) -> Option<Coordinates> {
+        self.districts
+            .get(&(normalize(city), normalize(district)))
+            .map(|(_, coordinates)| *coordinates)
+    }
+
+    /// Districts of a city with their centroids, drawn on maps for orientation.
+    pub fn districts(&self, city: &str) -> Vec<(&str, Coordinates)> {
+        let city = normalize(city);
+        self.districts
+            .iter()
+            .filter(|((district_city, _), _)| *district_city == city)
+            .map(|(_, (district, coordinates))| (district.as_str(), *coordinates))
+            .collect()
+    }
+}
+
+/// Lowercased with the Latvian diacritics removed, as listings are not consistent about them.
+fn normalize(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'ā' => 'a',
+            'č' => 'c',
+            'ē' => 'e',
+            'ģ' => 'g',
+            'ī' => 'i',
+            'ķ' => 'k',
+            'ļ' => 'l',
+            'ņ' => 'n',
+            'ō' => 'o',
+            'ŗ' => 'r',
+            'š' => 's',
+            'ū' => 'u',
+            'ž' => 'z',
+            c => c,
+        })
+        .collect()
+}
+
+/// Street name without the house number and first name initials; ss.com omits `iela` and
+/// abbreviates the other street types, e.g. `K. Barona 12` or `Anniņmuižas bulv. 38`.
+pub(crate) fn normalize_street(street: &str) -> String {
+    normalize(street)
+        .split_whitespace()
+        .filter(|word| !word.chars().any(|c| c.is_ascii_digit()))
+        .enumerate()
+        .filter(|(index, word)| !(*index == 0 && word.ends_with('.') && word.chars().count() <= 4))
+        .map(|(_, word)| word)
+        .filter_map(|word| match word {
+            "iela" | "iel." => None,
+            "g." => Some("gatve"),
+            "bulv." | "bulv" => Some("bulvaris"),
+            "pr." | "prosp." => Some("prospekts"),
+            "lauk." => Some("laukums"),
+            word => Some(word),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// First letter of the abbreviated first name [`normalize_street`] drops, e.g. `k` for
+/// `K. Barona 12`.
+fn street_initial(street: &str) -> Option<char> {
+    normalize(street)
+        .split_whitespace()
+        .find(|word| !word.chars().any(|c| c.is_ascii_digit()))
+        .filter(|word| word.ends_with('.') && word.chars().count() <= 4)
+        .and_then(|word| word.chars().next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATASET: &str = "kind,city,district,street,latitude,longitude
+city,Rīga,,,56.9496,24.1052
+district,Rīga,Centrs,,56.9560,24.1200
+street,Rīga,,Krišjāņa Barona iela,56.9540,24.1320
+street,Rīga,,Aleksandra Barona iela,56.9000,24.1000
+street,Rīga,,Krišjāņa Valdemāra iela,56.9580,24.1150
+street,Jūrmala,,Aleksandra Barona iela,56.9680,23.7704
+";
+
+    fn geocoder() -> Geocoder {
+        let mut geocoder = Geocoder::default();
+        geocoder.add_dataset(DATASET).expect("dataset is valid");
+        geocoder
+    }
+
+    fn flat(street_name: &str, district: &str) -> Flat {
+        Flat {
+            city: String::from("Rīga"),
+            district: district.to_string(),
+            street_name: street_name.to_string(),
+            ..Flat::default()
+        }
+    }
+
+    #[test]
+    fn normalize_street_drops_numbers_initials_and_iela() {
+        assert_eq!(normalize_street("K. Barona 12"), "barona");
+        assert_eq!(street_initial("K. Barona 12"), Some('k'));
+        assert_eq!(street_initial("Barona 12"), None);
+        assert_eq!(
+            normalize_street("Krišjāņa Barona iela 12-4"),
+            "krisjana barona"
+        );
+        assert_eq!(
+            normalize_street("Anniņmuižas bulv. 38"),
+            "anninmuizas bulvaris"
+        );
+        assert_eq!(normalize_street("Brīvības g. 201"), "brivibas gatve");
+        assert_eq!(
+            normalize_street("Kārļa Ulmaņa gatve 2"),
+            "karla ulmana gatve"
+        );
+    }
+
+    #[test]
+    fn distance_km_matches_known_distances() {
+        let riga = Coordinates::parse("56.9496,24.1052").expect("valid coordinates");
+        let daugavpils = Coordinates::parse("55.8747,26.5362").expect("valid coordinates");
+        assert_eq!(riga.distance_km(&riga), 0.0);
+        assert_eq!(riga.distance_km(&daugavpils), daugavpils.distance_km(&riga));
+        // a degree of latitude and a quarter of the equator
+        let origin = Coordinates {
+            latitude: 0.0,
+            longitude: 0.0,
+        };
+        let north = Coordinates {
+            latitude: 1.0,
+            ..origin
+        };
+        let east = Coordinates {
+            longitude: 90.0,
+            ..origin
+        };
+        assert!((origin.distance_km(&north) - 111.195).abs() < 0.01);
+        assert!(
+            (origin.distance_km(&east) - EARTH_RADIUS_KM * std::f64::consts::FRAC_PI_2).abs()
+                < 1e-6
+        );
+    }
+
+    #[test]
+    fn parse_rejects_out_of_range_coordinates() {
+        assert_eq!(
+            Coordinates::parse("56.9,124.1").map(|c| c.latitude),
+            Some(56.9)
+        );
+        assert!(Coordinates::parse("91,24").is_none());
+        assert!(Coordinates::parse("56.9 24.1").is_none());
+    }
+
+    #[test]
+    fn locate_prefers_streets_and_matches_unambiguous_suffixes() {
+        let geocoder = geocoder();
+        let exact = geocoder
+            .locate(&flat("Krišjāņa Barona iela 5", "Centrs"))
+            .expect("known street");
+        assert_eq!(exact.precision, Precision::Street);
+        assert_eq!(exact.coordinates.latitude, 56.9540);
+
+        let suffix = geocoder
+            .locate(&flat("Valdemāra 10", "Centrs"))
+            .expect("known street");
+        assert_eq!(suffix.precision, Precision::Street);
+        assert_eq!(suffix.coordinates.latitude, 56.9580);
+
+        // the initial picks between the Riga streets ending with "barona"
+        let initial = geocoder
+            .locate(&flat("K. Barona 5", "Centrs"))
+            .expect("known street");
+        assert_eq!(initial.precision, Precision::Street);
+        assert_eq!(initial.coordinates.latitude, 56.9540);
+        let initial = geocoder
+            .locate(&flat("A. Barona 5", "Centrs"))
+            .expect("known street");
+        assert_eq!(initial.coordinates.latitude, 56.9000);
+
+        // without one the street is ambiguous, only the district is known
+        let ambiguous = geocoder
+            .locate(&flat("Barona 5", "Centrs"))
+            .expect("known district");
+        assert_eq!(ambiguous.precision, Precision::District);
+        assert_eq!(ambiguous.coordinates.latitude, 56.9560);
+
+        let district = geocoder
+            .locate(&flat("Nezināmā 1", "Centrs"))
+            .expect("known district");
+        assert_eq!(district.precision, Precision::District);
+        let city = geocoder
+            .locate(&flat("Nezināmā 1", "Nezināms"))
+            .expect("known city");
+        assert_eq!(city.precision, Precision::City);
+    }
+
+    #[test]
+    fn area_contains_streets_and_nearby_districts() {
+        let geocoder = geocoder();
+        let area = Area {
+            center: Coordinates::parse("56.9540,24.1320").expect("valid coordinates"),
+            radius_km: 0.5,
+        };
+        let located = |street_name: &str, district: &str| {
+            geocoder
+                .locate(&flat(street_name, district))
+                .expect("known location")
+        };
+        assert!(area.contains(&located("Krišjāņa Barona iela 5", "Centrs")));
+        assert!(!area.contains(&located("Aleksandra Barona iela 1", "Centrs")));
+        // the Centrs centroid is 0.8 km away, the district may reach into the circle
+        assert!(area.contains(&located("Nezināmā 1", "Centrs")));
+        let far_area = Area {
+            center: Coordinates::parse("56.9680,23.7704").expect("valid coordinates"),
+            ..area
+        };
+        assert!(!far_area.contains(&located("Nezināmā 1", "Centrs")));
+        // a city centroid says nothing about where in the city the listing is
+        assert!(!area.contains(&located("Nezināmā 1", "Nezināms")));
+    }
+}
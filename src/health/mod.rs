@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
+use std::sync::LazyLock;
+
+/// Liveness signals reported by the components, read by `/healthz` and `/readyz`.
+#[derive(Default)]
+pub struct Health {
+    catalog_cities: AtomicUsize,
+    /// Unix timestamp of the last page fetched successfully, `0` when none was.
+    last_successful_scrape: AtomicI64,
+    dispatcher_running: AtomicBool,
+    /// Unix timestamp of the last time the dispatcher made progress.
+    dispatcher_heartbeat: AtomicI64,
+    last_update: AtomicI64,
+}
+
+static HEALTH: LazyLock<Health> = LazyLock::new(Health::default);
+
+pub fn health() -> &'static Health {
+    &HEALTH
+}
+
+/// Self-check result, `problems` lists every failed check.
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub ok: bool,
+    pub problems: Vec<String>,
+    pub catalog_cities: usize,
+    pub last_successful_scrape: Option<DateTime<Utc>>,
+    pub storage_ok: bool,
+    pub dispatcher_running: bool,
+    pub dispatcher_heartbeat: Option<DateTime<Utc>>,
+    pub last_update: Option<DateTime<Utc>>,
+}
+
+impl Health {
+    pub fn set_catalog_cities(&self, count: usize) {
+        self.catalog_cities.store(count, Ordering::Relaxed);
+    }
+
+    pub fn catalog_cities(&self) -> usize {
+        self.catalog_cities.load(Ordering::Relaxed)
+    }
+
+    pub fn record_scrape(&self) {
+        self.last_successful_scrape
+            .store(Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    pub fn last_successful_scrape(&self) -> Option<DateTime<Utc>> {
+        Self::timestamp(&self.last_successful_scrape)
+    }
+
+    pub fn set_dispatcher_running(&self, running: bool) {
+        self.dispatcher_running.store(running, Ordering::Relaxed);
+    }
+
+    pub fn dispatcher_running(&self) -> bool {
+        self.dispatcher_running.load(Ordering::Relaxed)
+    }
+
+    pub fn record_dispatcher_heartbeat(&self) {
+        self.dispatcher_heartbeat
+            .store(Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    pub fn dispatcher_heartbeat(&self) -> Option<DateTime<Utc>> {
+        Self::timestamp(&self.dispatcher_heartbeat)
+    }
+
+    pub fn record_update(&self) {
+        self.last_update
+            .store(Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    pub fn last_update(&self) -> Option<DateTime<Utc>> {
+        Self::timestamp(&self.last_update)
+    }
+
+    fn timestamp(value: &AtomicI64) -> Option<DateTime<Utc>> {
+        match value.load(Ordering::Relaxed) {
+            0 => None,
+            secs => DateTime::from_timestamp(secs, 0),
+        }
+    }
+}
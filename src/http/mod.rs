@@ -0,0 +1,544 @@
+use crate::feed;
+use crate::flats::{Category, City, Flat, FlatCriteria, FlatsParser};
+use crate::health::{health, HealthReport};
+use crate::logger::{LogContext, Logger};
+use crate::metrics::metrics;
+use crate::sources::{ListingSources, Source};
+use crate::storage::{PricePoint, Storage, StoredListing};
+use crate::subscriptions::Subscription;
+use axum::extract::{OriginalUri, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use log::Level;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+const DEFAULT_LISTINGS_LIMIT: usize = 100;
+/// A storage lock held longer than this is reported as a wedged bot.
+const STORAGE_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Long polling returns every 10 seconds, a dispatcher silent for longer is wedged.
+const DISPATCHER_STALE_AFTER: Duration = Duration::from_secs(60);
+/// Probes are frequent, the storage directory is written to at most this often.
+const STORAGE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time and outcome of a storage write check.
+type StorageCheck = (Instant, Result<(), String>);
+
+#[derive(Clone)]
+struct ApiState {
+    flats_parser: Arc<Mutex<FlatsParser>>,
+    listing_sources: ListingSources,
+    storage: Arc<Mutex<Storage>>,
+    /// `/readyz` fails when subscriptions exist but nothing was scraped for this long.
+    scrape_stale_after: Duration,
+    /// Bearer token required by the `/api` routes, only optional on a loopback address.
+    token: Option<String>,
+    /// Time and outcome of the last storage write check.
+    storage_check: Arc<std::sync::Mutex<Option<StorageCheck>>>,
+}
+
+/// JSON API over the same catalog, subscriptions and listings the bot uses.
+pub struct HttpServer {
+    address: SocketAddr,
+    state: ApiState,
+}
+
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(serde_json::json!({ "error": self.message }));
+        (self.status, body).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct SubscriptionsQuery {
+    chat_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct CatalogQuery {
+    #[serde(default)]
+    category: Category,
+}
+
+#[derive(Deserialize)]
+struct ListingsQuery {
+    limit: Option<usize>,
+    since: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct PriceHistoryQuery {
+    url: String,
+}
+
+impl HttpServer {
+    pub fn new(
+        address: SocketAddr,
+        flats_parser: Arc<Mutex<FlatsParser>>,
+        listing_sources: ListingSources,
+        storage: Arc<Mutex<Storage>>,
+        scrape_stale_after: Duration,
+        token: Option<String>,
+    ) -> Self {
+        Self {
+            address,
+            state: ApiState {
+                flats_parser,
+                listing_sources,
+                storage,
+                scrape_stale_after,
+                token,
+                storage_check: Arc::new(std::sync::Mutex::new(None)),
+            },
+        }
+    }
+
+    pub fn router(&self) -> Router {
+        let api = Router::new()
+            .route("/api/catalog", get(Self::catalog))
+            .route("/api/subscriptions", get(Self::subscriptions))
+            .route("/api/subscriptions/{id}", get(Self::subscription))
+            .route("/api/listings", get(Self::listings))
+            .route("/api/price-history", get(Self::price_history))
+            .route("/api/search", post(Self::search))
+            .route_layer(middleware::from_fn_with_state(
+                self.state.clone(),
+                Self::authorize,
+            ));
+        Router::new()
+            .merge(api)
+            .route("/feeds/{token}/atom.xml", get(Self::atom_feed))
+            .route("/feeds/{token}/rss.xml", get(Self::rss_feed))
+            .route("/metrics", get(Self::metrics))
+            .route("/healthz", get(Self::healthz))
+            .route("/readyz", get(Self::readyz))
+            .layer(middleware::from_fn(Self::log_context))
+            .with_state(self.state.clone())
+    }
+
+    /// Serves until `shutdown` is cancelled, then waits for open requests to complete.
+    pub async fn run(self, shutdown: CancellationToken) -> Result<(), anyhow::Error> {
+        if self.state.token.is_none() && !self.address.ip().is_loopback() {
+            return Err(anyhow::anyhow!(
+                "Refusing to serve the API on {} without a token",
+                self.address
+            ));
+        }
+        let listener = tokio::net::TcpListener::bind(self.address).await?;
+        Logger::info(format!("HTTP API listening on {}", self.address).as_str());
+        axum::serve(listener, self.router())
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await?;
+        Ok(())
+    }
+
+    /// Gives every request its own correlation id in the logs.
+    async fn log_context(request: Request, next: Next) -> Response {
+        let started_at = Instant::now();
+        let method = request.method().clone();
+        let path = request.uri().path().to_string();
+        LogContext::correlated("http")
+            .with("method", &method)
+            .with("path", &path)
+            .scope(async {
+                let response = next.run(request).await;
+                Logger::log(
+                    Level::Debug,
+                    "HTTP request handled",
+                    &[
+                        ("status", &response.status().as_u16()),
+                        ("duration_ms", &started_at.elapsed().as_millis()),
+                    ],
+                );
+                response
+            })
+            .await
+    }
+
+    /// Rejects `/api` requests without the configured bearer token.
+    async fn authorize(
+        State(state): State<ApiState>,
+        request: Request,
+        next: Next,
+    ) -> Result<Response, ApiError> {
+        let Some(token) = &state.token else {
+            return Ok(next.run(request).await);
+        };
+        let authorized = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()));
+        if !authorized {
+            return Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "A valid bearer token is required",
+            ));
+        }
+        Ok(next.run(request).await)
+    }
+
+    async fn catalog(
+        State(state): State<ApiState>,
+        Query(query): Query<CatalogQuery>,
+    ) -> Result<Json<Vec<City>>, ApiError> {
+        if let Err(error) =
+            FlatsParser::ensure_category_loaded(&state.flats_parser, query.category).await
+        {
+            Logger::error(
+                format!(
+                    "Failed to load {} catalog: {}",
+                    query.category.code(),
+                    error
+                )
+                .as_str(),
+            );
+            return Err(ApiError::new(StatusCode::BAD_GATEWAY, error.to_string()));
+        }
+        let flats_parser = state.flats_parser.lock().await;
+        let cities = flats_parser
+            .cities(query.category)
+            .into_iter()
+            .cloned()
+            .collect();
+        Ok(Json(cities))
+    }
+
+    async fn subscriptions(
+        State(state): State<ApiState>,
+        Query(query): Query<SubscriptionsQuery>,
+    ) -> Json<Vec<Subscription>> {
+        let storage = state.storage.lock().await;
+        let subscriptions = storage
+            .data
+            .subscriptions
+            .iter()
+            .filter(|subscription| {
+                query
+                    .chat_id
+                    .is_none_or(|chat_id| subscription.chat_id == chat_id)
+            })
+            .cloned()
+            .collect();
+        Json(subscriptions)
+    }
+
+    async fn subscription(
+        State(state): State<ApiState>,
+        Path(id): Path<u64>,
+    ) -> Result<Json<Subscription>, ApiError> {
+        let storage = state.storage.lock().await;
+        Self::find_subscription(&storage, id).cloned().map(Json)
+    }
+
+    /// Most recently seen listings first.
+    async fn listings(
+        State(state): State<ApiState>,
+        Query(query): Query<ListingsQuery>,
+    ) -> Json<Vec<StoredListing>> {
+        let storage = state.storage.lock().await;
+        let mut listings = storage
+            .data
+            .listings
+            .values()
+            .filter(|listing| query.since.is_none_or(|since| listing.last_seen >= since))
+            .cloned()
+            .collect::<Vec<_>>();
+        listings.sort_by_key(|listing| std::cmp::Reverse(listing.last_seen));
+        listings.truncate(query.limit.unwrap_or(DEFAULT_LISTINGS_LIMIT));
+        Json(listings)
+    }
+
+    async fn price_history(
+        State(state): State<ApiState>,
+        Query(query): Query<PriceHistoryQuery>,
+    ) -> Result<Json<Vec<PricePoint>>, ApiError> {
+        let storage = state.storage.lock().await;
+        storage
+            .data
+            .listings
+            .get(&query.url)
+            .map(|listing| Json(listing.price_history.clone()))
+            .ok_or_else(|| {
+                ApiError::new(
+                    StatusCode::NOT_FOUND,
+                    format!("Listing {} not found", query.url),
+                )
+            })
+    }
+
+    async fn atom_feed(
+        State(state): State<ApiState>,
+        Path(token): Path<String>,
+        headers: HeaderMap,
+        OriginalUri(uri): OriginalUri,
+    ) -> Result<Response, ApiError> {
+        let self_url = Self::request_url(&headers, &uri);
+        let storage = state.storage.lock().await;
+        let subscription = Self::find_feed(&storage, &token)?;
+        let listings = feed::subscription_listings(&storage, subscription);
+        let xml = feed::atom(subscription, &listings, &self_url);
+        Ok((
+            [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+            xml,
+        )
+            .into_response())
+    }
+
+    async fn rss_feed(
+        State(state): State<ApiState>,
+        Path(token): Path<String>,
+        headers: HeaderMap,
+        OriginalUri(uri): OriginalUri,
+    ) -> Result<Response, ApiError> {
+        let self_url = Self::request_url(&headers, &uri);
+        let storage = state.storage.lock().await;
+        let subscription = Self::find_feed(&storage, &token)?;
+        let listings = feed::subscription_listings(&storage, subscription);
+        let xml = feed::rss(subscription, &listings, &self_url);
+        Ok((
+            [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+            xml,
+        )
+            .into_response())
+    }
+
+    fn find_subscription(storage: &Storage, id: u64) -> Result<&Subscription, ApiError> {
+        storage
+            .data
+            .subscriptions
+            .iter()
+            .find(|subscription| subscription.id == id)
+            .ok_or_else(|| {
+                ApiError::new(
+                    StatusCode::NOT_FOUND,
+                    format!("Subscription {} not found", id),
+                )
+            })
+    }
+
+    fn find_feed<'a>(storage: &'a Storage, token: &str) -> Result<&'a Subscription, ApiError> {
+        storage
+            .data
+            .subscriptions
+            .iter()
+            .find(|subscription| {
+                constant_time_eq(token.as_bytes(), subscription.feed_token.as_bytes())
+            })
+            .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Feed not found"))
+    }
+
+    fn request_url(headers: &HeaderMap, uri: &axum::http::Uri) -> String {
+        let host = headers
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .unwrap_or("localhost");
+        format!("http://{}{}", host, uri.path())
+    }
+
+    /// Prometheus scrape target, active chats are counted from storage on every scrape.
+    async fn metrics(State(state): State<ApiState>) -> Result<Response, ApiError> {
+        let metrics = metrics();
+        {
+            let storage = state.storage.lock().await;
+            let active_chats = storage
+                .data
+                .subscriptions
+                .iter()
+                .filter(|subscription| subscription.active)
+                .map(|subscription| subscription.chat_id)
+                .collect::<HashSet<_>>();
+            metrics.active_chats.set(active_chats.len() as i64);
+        }
+        let body = metrics
+            .render()
+            .map_err(|error| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+        Ok((
+            [(
+                header::CONTENT_TYPE,
+                "text/plain; version=0.0.4; charset=utf-8",
+            )],
+            body,
+        )
+            .into_response())
+    }
+
+    /// Liveness: the dispatcher runs and storage can be locked and written.
+    async fn healthz(State(state): State<ApiState>) -> Response {
+        Self::health_response(Self::self_check(&state, false).await)
+    }
+
+    /// Readiness: liveness plus a loaded catalog and a recent successful scrape.
+    async fn readyz(State(state): State<ApiState>) -> Response {
+        Self::health_response(Self::self_check(&state, true).await)
+    }
+
+    fn health_response(report: HealthReport) -> Response {
+        let status = if report.ok {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        (status, Json(report)).into_response()
+    }
+
+    async fn self_check(state: &ApiState, readiness: bool) -> HealthReport {
+        let health = health();
+        let mut problems: Vec<String> = Vec::new();
+
+        let mut has_active_subscriptions = false;
+        let storage_ok =
+            match tokio::time::timeout(STORAGE_LOCK_TIMEOUT, state.storage.lock()).await {
+                Ok(storage) => {
+                    has_active_subscriptions = storage
+                        .data
+                        .subscriptions
+                        .iter()
+                        .any(|subscription| subscription.active);
+                    match Self::check_writable(state, &storage) {
+                        Ok(()) => true,
+                        Err(error) => {
+                            problems.push(format!("storage is not writable: {}", error));
+                            false
+                        }
+                    }
+                }
+                Err(_) => {
+                    problems.push(format!(
+                        "storage lock not acquired within {:?}",
+                        STORAGE_LOCK_TIMEOUT
+                    ));
+                    false
+                }
+            };
+        let dispatcher_heartbeat = health.dispatcher_heartbeat();
+        if !health.dispatcher_running() {
+            problems.push(String::from("Telegram dispatcher is not running"));
+        } else if dispatcher_heartbeat.is_none_or(|heartbeat| {
+            (Utc::now() - heartbeat).to_std().unwrap_or_default() > DISPATCHER_STALE_AFTER
+        }) {
+            problems.push(format!(
+                "Telegram dispatcher made no progress within {:?}",
+                DISPATCHER_STALE_AFTER
+            ));
+        }
+
+        let last_successful_scrape = health.last_successful_scrape();
+        if readiness {
+            if health.catalog_cities() == 0 {
+                problems.push(String::from("catalog is not loaded"));
+            }
+            let is_stale = last_successful_scrape.is_none_or(|scraped_at| {
+                (Utc::now() - scraped_at).to_std().unwrap_or_default() > state.scrape_stale_after
+            });
+            if has_active_subscriptions && is_stale {
+                problems.push(format!(
+                    "no successful scrape within {:?}",
+                    state.scrape_stale_after
+                ));
+            }
+        }
+
+        HealthReport {
+            ok: problems.is_empty(),
+            problems,
+            catalog_cities: health.catalog_cities(),
+            last_successful_scrape,
+            storage_ok,
+            dispatcher_running: health.dispatcher_running(),
+            dispatcher_heartbeat,
+            last_update: health.last_update(),
+        }
+    }
+
+    /// The cached outcome of [`Storage::check_writable`] while it is recent.
+    fn check_writable(state: &ApiState, storage: &Storage) -> Result<(), String> {
+        let mut storage_check = state
+            .storage_check
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((checked_at, result)) = storage_check.as_ref() {
+            if checked_at.elapsed() < STORAGE_CHECK_INTERVAL {
+                return result.clone();
+            }
+        }
+        let result = storage.check_writable().map_err(|error| error.to_string());
+        *storage_check = Some((Instant::now(), result.clone()));
+        result
+    }
+
+    async fn search(
+        State(state): State<ApiState>,
+        Json(flat_criteria): Json<FlatCriteria>,
+    ) -> Result<Json<Vec<Flat>>, ApiError> {
+        if flat_criteria.price_from > flat_criteria.price_to {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "price_from must not be greater than price_to",
+            ));
+        }
+        if flat_criteria.sources.is_empty() {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "sources must name at least one portal",
+            ));
+        }
+        if flat_criteria.sources.contains(&Source::Ss) {
+            if let Err(error) =
+                FlatsParser::ensure_category_loaded(&state.flats_parser, flat_criteria.category)
+                    .await
+            {
+                return Err(ApiError::new(StatusCode::BAD_GATEWAY, error.to_string()));
+            }
+            let flats_parser = state.flats_parser.lock().await;
+            if flats_parser.criteria_hrefs(&flat_criteria).is_empty() {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "No categories match the selected city, districts and deal types",
+                ));
+            }
+        }
+        match state.listing_sources.search_matching(&flat_criteria).await {
+            Ok(flats) => Ok(Json(flats)),
+            Err(error) => {
+                Logger::error(format!("HTTP search failed: {}", error).as_str());
+                Err(ApiError::new(StatusCode::BAD_GATEWAY, error.to_string()))
+            }
+        }
+    }
+}
+
+/// Compares without returning early, so response times do not leak the token.
+fn constant_time_eq(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0u8, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
@@ -0,0 +1,1197 @@
+use crate::flats::{Category, Flat, FlatCriteria, Series};
+use crate::notifications::{DeliveryMode, QuietHours};
+use crate::stats::MarketStats;
+use crate::subscriptions::Subscription;
+use crate::telegram::admin::{BotStats, UserSummary};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Lv,
+    Ru,
+    #[default]
+    En,
+}
+
+impl Language {
+    pub const ALL: [Language; 3] = [Language::Lv, Language::Ru, Language::En];
+
+    /// Resolves a Telegram `language_code` (e.g. `ru` or `en-US`) to a supported language.
+    pub fn from_code(code: &str) -> Option<Language> {
+        let code = code.trim().to_lowercase();
+        let primary = code.split(['-', '_']).next().unwrap_or_default();
+        match primary {
+            "lv" => Some(Language::Lv),
+            "ru" => Some(Language::Ru),
+            "en" => Some(Language::En),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::Lv => "lv",
+            Language::Ru => "ru",
+            Language::En => "en",
+        }
+    }
+
+    /// Path prefix of ss.com pages in this language; ss.com has no English version.
+    pub fn ss_path(&self) -> &'static str {
+        match self {
+            Language::Ru => "ru",
+            Language::Lv | Language::En => "lv",
+        }
+    }
+
+    /// Keyword accepted instead of a list of districts to search the whole city.
+    pub fn whole_city_keyword(&self) -> &'static str {
+        match self {
+            Language::Lv => "visa pilsēta",
+            Language::Ru => "весь город",
+            Language::En => "whole city",
+        }
+    }
+}
+
+/// Every text the bot sends, rendered per language with [`Text::render`].
+pub enum Text<'a> {
+    PlainTextRequired,
+    SelectCategory {
+        categories: &'a str,
+    },
+    InvalidCategory,
+    LoadingCatalog,
+    SelectCity {
+        cities: &'a str,
+    },
+    InvalidCity {
+        city: &'a str,
+    },
+    CityNotFound,
+    SelectDistricts {
+        districts: &'a str,
+    },
+    DistrictNotFound {
+        districts: &'a str,
+    },
+    NoDistrictsNear {
+        city: &'a str,
+    },
+    SelectDealTypes {
+        deal_types: &'a str,
+    },
+    DealTypeNotFound {
+        deal_types: &'a str,
+    },
+    EnterRadius,
+    InvalidRadius {
+        max_km: f64,
+    },
+    EnterPriceRange,
+    InvalidPriceRange,
+    Searching,
+    SearchFailed,
+    NoFlatsFound,
+    FlatsFound {
+        count: usize,
+    },
+    Flat {
+        flat: &'a Flat,
+    },
+    Cancelled,
+    Help,
+    Unhandled,
+    SelectLanguage,
+    LanguageChanged,
+    Subscribed {
+        id: u64,
+    },
+    NoSubscriptions,
+    Subscriptions {
+        subscriptions: &'a [&'a Subscription],
+    },
+    Unsubscribed {
+        id: u64,
+    },
+    SubscriptionNotFound,
+    QuietHoursSet {
+        quiet_hours: QuietHours,
+        timezone: &'a str,
+    },
+    QuietHoursDisabled,
+    InvalidQuietHours,
+    TimezoneSet {
+        timezone: &'a str,
+    },
+    InvalidTimezone,
+    DeliveryModeSet {
+        delivery_mode: DeliveryMode,
+    },
+    InvalidDeliveryMode,
+    NewFlats {
+        count: usize,
+    },
+    Digest {
+        count: usize,
+    },
+    ExportUsage,
+    FeedUsage,
+    FeedLinks {
+        token: &'a str,
+    },
+    FilterUsage,
+    FilterSet {
+        id: u64,
+        criteria: &'a FlatCriteria,
+    },
+    SourcesUsage {
+        sources: &'a str,
+    },
+    SourcesSet {
+        id: u64,
+        sources: &'a str,
+    },
+    DealsUsage,
+    DealsSet {
+        id: u64,
+        min_discount: Option<u32>,
+    },
+    MarketUsage,
+    StatsNotFound,
+    MapUsage,
+    NothingToMap,
+    MarketStats {
+        stats: &'a MarketStats,
+    },
+    MarkupDrift {
+        url: &'a str,
+        selector: &'a str,
+        detail: &'a str,
+    },
+    AdminStats {
+        stats: &'a BotStats,
+    },
+    BroadcastUsage,
+    BroadcastQueued {
+        count: usize,
+    },
+    CatalogRefreshing,
+    CatalogRefreshed {
+        cities: usize,
+    },
+    CatalogRefreshFailed,
+    PollingPaused,
+    PollingResumed,
+    Users {
+        users: &'a [UserSummary],
+    },
+    BanUsage,
+    Banned {
+        chat_id: i64,
+        subscriptions: usize,
+    },
+    UnbanUsage,
+    Unbanned {
+        chat_id: i64,
+        subscriptions: usize,
+    },
+    NotBanned {
+        chat_id: i64,
+    },
+}
+
+impl Text<'_> {
+    pub fn render(&self, language: Language) -> String {
+        use Language::{En, Lv, Ru};
+        match self {
+            Text::PlainTextRequired => match language {
+                Lv => "Ziņojumam jābūt vienkāršam tekstam".to_string(),
+                Ru => "Сообщение должно быть обычным текстом".to_string(),
+                En => "Message should be a plain text".to_string(),
+            },
+            Text::SelectCity { cities } => match language {
+                Lv => format!("Sāksim! Lūdzu, izvēlieties pilsētu: \n\n{}", cities),
+                Ru => format!("Начнём! Пожалуйста, выберите город: \n\n{}", cities),
+                En => format!("Let's start! Please select a city: \n\n{}", cities),
+            },
+            Text::InvalidCity { city } => match language {
+                Lv => format!(
+                    "Pilsētas nosaukums '{}' nav derīgs. Lūdzu, mēģiniet vēlreiz!",
+                    city
+                ),
+                Ru => format!(
+                    "Название города '{}' неверно. Пожалуйста, попробуйте ещё раз!",
+                    city
+                ),
+                En => format!("City '{}' name is invalid. Please try again!", city),
+            },
+            Text::SelectCategory { categories } => match language {
+                Lv => format!("Ko jūs meklējat? \n\n{}", categories),
+                Ru => format!("Что вы ищете? \n\n{}", categories),
+                En => format!("What are you looking for? \n\n{}", categories),
+            },
+            Text::InvalidCategory => match language {
+                Lv => "Lūdzu, izvēlieties kategoriju no saraksta.".to_string(),
+                Ru => "Пожалуйста, выберите категорию из списка.".to_string(),
+                En => "Please select a category from the list.".to_string(),
+            },
+            Text::LoadingCatalog => match language {
+                Lv => "Ielādē pilsētas, tas var aizņemt pāris minūtes...".to_string(),
+                Ru => "Загружаю города, это может занять пару минут...".to_string(),
+                En => "Loading cities, this can take a couple of minutes...".to_string(),
+            },
+            Text::CityNotFound => match language {
+                Lv => "Pilsēta nav atrasta".to_string(),
+                Ru => "Город не найден".to_string(),
+                En => "City not found".to_string(),
+            },
+            Text::SelectDistricts { districts } => match language {
+                Lv => format!(
+                    "Lūdzu, izvēlieties vienu vai vairākus rajonus, atdalot tos ar komatiem, vai '{}'. Lai meklētu ap kādu vietu, nosūtiet atrašanās vietu: \n\n{}",
+                    language.whole_city_keyword(),
+                    districts
+                ),
+                Ru => format!(
+                    "Пожалуйста, выберите один или несколько районов через запятую или '{}'. Чтобы искать вокруг места, отправьте геопозицию: \n\n{}",
+                    language.whole_city_keyword(),
+                    districts
+                ),
+                En => format!(
+                    "Please select one or more districts separated by commas, or '{}'. To search around a place, share a location: \n\n{}",
+                    language.whole_city_keyword(),
+                    districts
+                ),
+            },
+            Text::DistrictNotFound { districts } => match language {
+                Lv => format!("Rajons nav atrasts: {}", districts),
+                Ru => format!("Район не найден: {}", districts),
+                En => format!("District not found: {}", districts),
+            },
+            Text::NoDistrictsNear { city } => match language {
+                Lv => format!(
+                    "Neviens zināmais pilsētas {} rajons nav tuvu šai vietai. Nosūtiet citu atrašanās vietu vai ievadiet rajonus.",
+                    city
+                ),
+                Ru => format!(
+                    "Ни один известный район города {} не находится рядом с этим местом. Отправьте другую геопозицию или введите районы.",
+                    city
+                ),
+                En => format!(
+                    "None of the known districts of {} is near this place. Send another location or enter districts.",
+                    city
+                ),
+            },
+            Text::SelectDealTypes { deal_types } => match language {
+                Lv => format!(
+                    "Lūdzu, izvēlieties vienu vai vairākus darījuma veidus, atdalot tos ar komatiem: \n\n{}",
+                    deal_types
+                ),
+                Ru => format!(
+                    "Пожалуйста, выберите один или несколько типов сделки через запятую: \n\n{}",
+                    deal_types
+                ),
+                En => format!(
+                    "Please select one or more deal types separated by commas: \n\n{}",
+                    deal_types
+                ),
+            },
+            Text::DealTypeNotFound { deal_types } => match language {
+                Lv => format!("Darījuma veids nav atrasts: {}", deal_types),
+                Ru => format!("Тип сделки не найден: {}", deal_types),
+                En => format!("Deal type not found: {}", deal_types),
+            },
+            Text::EnterRadius => match language {
+                Lv => "Lūdzu, ievadiet meklēšanas rādiusu kilometros, piemēram, 1.5".to_string(),
+                Ru => "Пожалуйста, введите радиус поиска в километрах, например, 1.5".to_string(),
+                En => "Please enter the search radius in kilometers, e.g. 1.5".to_string(),
+            },
+            Text::InvalidRadius { max_km } => match language {
+                Lv => format!("Rādiusam jābūt skaitlim no 0 līdz {} km", max_km),
+                Ru => format!("Радиус должен быть числом от 0 до {} км", max_km),
+                En => format!("The radius must be a number between 0 and {} km", max_km),
+            },
+            Text::EnterPriceRange => match language {
+                Lv => "Lūdzu, ievadiet cenu diapazonu šādā formātā: 'min_cena-max_cena'".to_string(),
+                Ru => "Пожалуйста, введите диапазон цен в формате: 'мин_цена-макс_цена'".to_string(),
+                En => "Please enter the price range using the following format: 'min_price-max_price'"
+                    .to_string(),
+            },
+            Text::InvalidPriceRange => match language {
+                Lv => "Cenu diapazons nav derīgs. Lūdzu, izmantojiet formātu: 'min_cena-max_cena'"
+                    .to_string(),
+                Ru => "Неверный диапазон цен. Пожалуйста, используйте формат: 'мин_цена-макс_цена'"
+                    .to_string(),
+                En => "Price range is invalid. Please use the following format: 'min_price-max_price'"
+                    .to_string(),
+            },
+            Text::Searching => match language {
+                Lv => "Meklējam dzīvokļus, lūdzu, uzgaidiet...".to_string(),
+                Ru => "Ищем квартиры, пожалуйста, подождите...".to_string(),
+                En => "Searching for flats, please wait...".to_string(),
+            },
+            Text::SearchFailed => match language {
+                Lv => "Neizdevās atrast dzīvokļus. Lūdzu, mēģiniet vēlāk.".to_string(),
+                Ru => "Не удалось выполнить поиск квартир. Пожалуйста, попробуйте позже.".to_string(),
+                En => "Failed to search flats. Please try again later.".to_string(),
+            },
+            Text::NoFlatsFound => match language {
+                Lv => "Pēc izvēlētajiem kritērijiem dzīvokļi netika atrasti.".to_string(),
+                Ru => "По выбранным критериям квартиры не найдены.".to_string(),
+                En => "No flats found for the selected criteria.".to_string(),
+            },
+            Text::FlatsFound { count } => match language {
+                Lv => format!("Atrasti dzīvokļi: {}", count),
+                Ru => format!("Найдено квартир: {}", count),
+                En => format!("Found {} flats:", count),
+            },
+            Text::Flat { flat } if flat.category != Category::Flats => {
+                let price = match language {
+                    Lv => "Cena",
+                    Ru => "Цена",
+                    En => "Price",
+                };
+                let mut lines = vec![flat.street_name.clone()];
+                lines.extend(
+                    flat.fields
+                        .iter()
+                        .map(|(name, value)| format!("{}: {}", name, value)),
+                );
+                lines.push(format!("{}: {}", price, flat.price));
+                lines.extend(Self::deal(flat, language));
+                lines.extend(Self::repost(flat, language));
+                lines.push(flat.url.clone());
+                lines.join("\n")
+            }
+            Text::Flat { flat } => {
+                let notes = Self::deal(flat, language)
+                    .into_iter()
+                    .chain(Self::repost(flat, language))
+                    .map(|line| format!("\n{}", line))
+                    .collect::<String>();
+                match language {
+                Lv => format!(
+                    "{}\nIstabas: {}, {} m², stāvs {}, {}\nCena: {}{}\n{}",
+                    flat.street_name,
+                    flat.rooms,
+                    flat.square_meters,
+                    flat.floor_text(),
+                    flat.series.name(language),
+                    flat.price,
+                    notes,
+                    flat.url
+                ),
+                Ru => format!(
+                    "{}\nКомнат: {}, {} м², этаж {}, {}\nЦена: {}{}\n{}",
+                    flat.street_name,
+                    flat.rooms,
+                    flat.square_meters,
+                    flat.floor_text(),
+                    flat.series.name(language),
+                    flat.price,
+                    notes,
+                    flat.url
+                ),
+                En => format!(
+                    "{}\n{} rooms, {} m², floor {}, {}\nPrice: {}{}\n{}",
+                    flat.street_name,
+                    flat.rooms,
+                    flat.square_meters,
+                    flat.floor_text(),
+                    flat.series.name(language),
+                    flat.price,
+                    notes,
+                    flat.url
+                ),
+                }
+            }
+            Text::Cancelled => match language {
+                Lv => "Dialogs atcelts.".to_string(),
+                Ru => "Диалог отменён.".to_string(),
+                En => "Cancelling the dialogue.".to_string(),
+            },
+            Text::Help => match language {
+                Lv => [
+                    "Pieejamās komandas:",
+                    "/start — Sākt dialogu.",
+                    "/help — Saņemt palīdzību.",
+                    "/cancel — Atcelt dialogu.",
+                    "/language — Mainīt valodu (lv, ru, en).",
+                    "/subscriptions — Parādīt saglabātos meklējumus.",
+                    "/unsubscribe <id> — Dzēst saglabāto meklējumu.",
+                    "/quiet 23:00-07:00 | off — Klusuma stundas.",
+                    "/timezone Europe/Riga — Laika josla.",
+                    "/digest instant | hourly | daily — Paziņojumu režīms.",
+                    "/export <id> [xlsx | csv | jsonl] — Eksportēt saglabāto meklējumu.",
+                    "/feed <id> — Saglabātā meklējuma Atom/RSS saites.",
+                    "/sources <id> <ss,city24> — Izvēlēties saglabātā meklējuma portālus.",
+                    "/filter <id> <rooms | area | floor | price> <no-līdz | off> — Sašaurināt saglabāto meklējumu.",
+                    "/filter <id> floor not-first | not-last, /filter <id> series 103,lt | off — Stāvs un sērija.",
+                    "/filter <id> <include | exclude | street | not-series> <vārdi, ... | off> — Atslēgvārdi un izslēgtās ielas vai sērijas.",
+                    "/filter <id> near <platums,garums> <km> | off — Sludinājumi rādiusā ap punktu, nezināmās ielās pēc rajona centra.",
+                    "/deals <id> <procenti | off> — Ziņot tikai par sludinājumiem zem mediānas €/m².",
+                    "/market <pilsēta> <rajons> — Rajona cenu statistika.",
+                    "/map <id> — Saglabātā meklējuma sludinājumi kartē.",
+                ]
+                .join("\n"),
+                Ru => [
+                    "Поддерживаются следующие команды:",
+                    "/start — Начать диалог.",
+                    "/help — Получить помощь.",
+                    "/cancel — Отменить диалог.",
+                    "/language — Сменить язык (lv, ru, en).",
+                    "/subscriptions — Показать сохранённые поиски.",
+                    "/unsubscribe <id> — Удалить сохранённый поиск.",
+                    "/quiet 23:00-07:00 | off — Тихие часы.",
+                    "/timezone Europe/Riga — Часовой пояс.",
+                    "/digest instant | hourly | daily — Режим уведомлений.",
+                    "/export <id> [xlsx | csv | jsonl] — Экспортировать сохранённый поиск.",
+                    "/feed <id> — Ссылки Atom/RSS сохранённого поиска.",
+                    "/sources <id> <ss,city24> — Выбрать порталы сохранённого поиска.",
+                    "/filter <id> <rooms | area | floor | price> <от-до | off> — Сузить сохранённый поиск.",
+                    "/filter <id> floor not-first | not-last, /filter <id> series 103,lt | off — Этаж и серия.",
+                    "/filter <id> <include | exclude | street | not-series> <слова, ... | off> — Ключевые слова и исключённые улицы или серии.",
+                    "/filter <id> near <широта,долгота> <км> | off — Объявления в радиусе от точки, на неизвестных улицах по центру района.",
+                    "/deals <id> <проценты | off> — Уведомлять только об объявлениях ниже медианы €/м².",
+                    "/market <город> <район> — Статистика цен района.",
+                    "/map <id> — Объявления сохранённого поиска на карте.",
+                ]
+                .join("\n"),
+                En => [
+                    "These commands are supported:",
+                    "/start — Start the dialogue.",
+                    "/help — Get help.",
+                    "/cancel — Cancel the dialogue.",
+                    "/language — Change the language (lv, ru, en).",
+                    "/subscriptions — List saved searches.",
+                    "/unsubscribe <id> — Remove a saved search.",
+                    "/quiet 23:00-07:00 | off — Quiet hours.",
+                    "/timezone Europe/Riga — Timezone.",
+                    "/digest instant | hourly | daily — Notification mode.",
+                    "/export <id> [xlsx | csv | jsonl] — Export a saved search.",
+                    "/feed <id> — Atom/RSS links of a saved search.",
+                    "/sources <id> <ss,city24> — Choose the portals of a saved search.",
+                    "/filter <id> <rooms | area | floor | price> <from-to | off> — Narrow a saved search.",
+                    "/filter <id> floor not-first | not-last, /filter <id> series 103,lt | off — Floor and building series.",
+                    "/filter <id> <include | exclude | street | not-series> <words, ... | off> — Keywords and excluded streets or series.",
+                    "/filter <id> near <latitude,longitude> <km> | off — Listings within a radius of a point, by the district centre on unknown streets.",
+                    "/deals <id> <percent | off> — Notify only on listings below the median €/m².",
+                    "/market <city> <district> — Price statistics of a district.",
+                    "/map <id> — Listings of a saved search on a map.",
+                ]
+                .join("\n"),
+            },
+            Text::Unhandled => match language {
+                Lv => format!(
+                    "Neapstrādāts ziņojums. {}",
+                    Text::Help.render(language)
+                ),
+                Ru => format!(
+                    "Необработанное сообщение. {}",
+                    Text::Help.render(language)
+                ),
+                En => format!("Unhandled message. {}", Text::Help.render(language)),
+            },
+            Text::SelectLanguage => match language {
+                Lv => "Lūdzu, izvēlieties valodu: /language lv, /language ru vai /language en"
+                    .to_string(),
+                Ru => "Пожалуйста, выберите язык: /language lv, /language ru или /language en"
+                    .to_string(),
+                En => "Please choose a language: /language lv, /language ru or /language en"
+                    .to_string(),
+            },
+            Text::LanguageChanged => match language {
+                Lv => "Valoda nomainīta uz latviešu.".to_string(),
+                Ru => "Язык изменён на русский.".to_string(),
+                En => "Language changed to English.".to_string(),
+            },
+            Text::Subscribed { id } => match language {
+                Lv => format!(
+                    "Meklējums saglabāts kā abonements #{}. Jūs saņemsiet paziņojumus par jauniem dzīvokļiem.",
+                    id
+                ),
+                Ru => format!(
+                    "Поиск сохранён как подписка #{}. Вы будете получать уведомления о новых квартирах.",
+                    id
+                ),
+                En => format!(
+                    "Search saved as subscription #{}. You will be notified about new flats.",
+                    id
+                ),
+            },
+            Text::NoSubscriptions => match language {
+                Lv => "Jums nav saglabātu meklējumu.".to_string(),
+                Ru => "У вас нет сохранённых поисков.".to_string(),
+                En => "You have no saved searches.".to_string(),
+            },
+            Text::Subscriptions { subscriptions } => {
+                let header = match language {
+                    Lv => "Jūsu abonementi:",
+                    Ru => "Ваши подписки:",
+                    En => "Your subscriptions:",
+                };
+                let entries = subscriptions
+                    .iter()
+                    .map(|subscription| {
+                        let criteria = &subscription.criteria;
+                        let mut districts = criteria.districts.iter().cloned().collect::<Vec<_>>();
+                        districts.sort();
+                        let mut deal_types = criteria.deal_types.iter().cloned().collect::<Vec<_>>();
+                        deal_types.sort();
+                        let sources = criteria
+                            .sources
+                            .iter()
+                            .map(|source| source.name())
+                            .collect::<Vec<_>>();
+                        let mut entry = format!(
+                            "#{} {}, {}: {} | {} | {} | {}",
+                            subscription.id,
+                            criteria.category.name(language),
+                            criteria.city,
+                            districts.join(", "),
+                            deal_types.join(", "),
+                            Self::filters(criteria, language),
+                            sources.join(", ")
+                        );
+                        if let Some(min_discount) = subscription.min_discount {
+                            entry.push_str(&match language {
+                                Lv => format!(" | ≥{}% zem mediānas", min_discount),
+                                Ru => format!(" | ≥{}% ниже медианы", min_discount),
+                                En => format!(" | ≥{}% below median", min_discount),
+                            });
+                        }
+                        entry
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("{}\n\n{}", header, entries)
+            }
+            Text::Unsubscribed { id } => match language {
+                Lv => format!("Abonements #{} dzēsts.", id),
+                Ru => format!("Подписка #{} удалена.", id),
+                En => format!("Subscription #{} removed.", id),
+            },
+            Text::SubscriptionNotFound => match language {
+                Lv => "Abonements nav atrasts. Lietojums: /unsubscribe <id>".to_string(),
+                Ru => "Подписка не найдена. Использование: /unsubscribe <id>".to_string(),
+                En => "Subscription not found. Usage: /unsubscribe <id>".to_string(),
+            },
+            Text::QuietHoursSet {
+                quiet_hours,
+                timezone,
+            } => {
+                let range = format!(
+                    "{}-{}",
+                    quiet_hours.start.format("%H:%M"),
+                    quiet_hours.end.format("%H:%M")
+                );
+                match language {
+                    Lv => format!("Klusuma stundas iestatītas: {} ({}).", range, timezone),
+                    Ru => format!("Тихие часы установлены: {} ({}).", range, timezone),
+                    En => format!("Quiet hours set to {} ({}).", range, timezone),
+                }
+            }
+            Text::QuietHoursDisabled => match language {
+                Lv => "Klusuma stundas izslēgtas.".to_string(),
+                Ru => "Тихие часы отключены.".to_string(),
+                En => "Quiet hours disabled.".to_string(),
+            },
+            Text::InvalidQuietHours => match language {
+                Lv => "Lūdzu, izmantojiet formātu /quiet 23:00-07:00 vai /quiet off".to_string(),
+                Ru => "Пожалуйста, используйте формат /quiet 23:00-07:00 или /quiet off".to_string(),
+                En => "Please use the format /quiet 23:00-07:00 or /quiet off".to_string(),
+            },
+            Text::TimezoneSet { timezone } => match language {
+                Lv => format!("Laika josla iestatīta: {}.", timezone),
+                Ru => format!("Часовой пояс установлен: {}.", timezone),
+                En => format!("Timezone set to {}.", timezone),
+            },
+            Text::InvalidTimezone => match language {
+                Lv => "Nezināma laika josla. Lūdzu, norādiet, piemēram, Europe/Riga.".to_string(),
+                Ru => "Неизвестный часовой пояс. Пожалуйста, укажите, например, Europe/Riga."
+                    .to_string(),
+                En => "Unknown timezone. Please use a name like Europe/Riga.".to_string(),
+            },
+            Text::DeliveryModeSet { delivery_mode } => match (language, delivery_mode) {
+                (Lv, DeliveryMode::Instant) => "Paziņojumi tiks sūtīti uzreiz.".to_string(),
+                (Lv, DeliveryMode::Hourly) => "Paziņojumi tiks apkopoti reizi stundā.".to_string(),
+                (Lv, DeliveryMode::Daily) => "Paziņojumi tiks apkopoti reizi dienā.".to_string(),
+                (Ru, DeliveryMode::Instant) => "Уведомления будут приходить сразу.".to_string(),
+                (Ru, DeliveryMode::Hourly) => {
+                    "Уведомления будут приходить сводкой раз в час.".to_string()
+                }
+                (Ru, DeliveryMode::Daily) => {
+                    "Уведомления будут приходить сводкой раз в день.".to_string()
+                }
+                (En, DeliveryMode::Instant) => "Notifications will be sent instantly.".to_string(),
+                (En, DeliveryMode::Hourly) => {
+                    "Notifications will be sent as an hourly digest.".to_string()
+                }
+                (En, DeliveryMode::Daily) => {
+                    "Notifications will be sent as a daily digest.".to_string()
+                }
+            },
+            Text::InvalidDeliveryMode => match language {
+                Lv => "Lūdzu, izmantojiet /digest instant, /digest hourly vai /digest daily"
+                    .to_string(),
+                Ru => "Пожалуйста, используйте /digest instant, /digest hourly или /digest daily"
+                    .to_string(),
+                En => "Please use /digest instant, /digest hourly or /digest daily".to_string(),
+            },
+            Text::NewFlats { count } => match language {
+                Lv => format!("Jauni dzīvokļi: {}", count),
+                Ru => format!("Новые квартиры: {}", count),
+                En => format!("New flats found: {}", count),
+            },
+            Text::Digest { count } => match language {
+                Lv => format!("Jūsu kopsavilkums — jauni dzīvokļi: {}", count),
+                Ru => format!("Ваша сводка — новых квартир: {}", count),
+                En => format!("Your digest — {} new flats:", count),
+            },
+            Text::ExportUsage => match language {
+                Lv => "Lietojums: /export <id> [xlsx | csv | jsonl], piemēram, /export 3 xlsx"
+                    .to_string(),
+                Ru => "Использование: /export <id> [xlsx | csv | jsonl], например, /export 3 xlsx"
+                    .to_string(),
+                En => "Usage: /export <id> [xlsx | csv | jsonl], e.g. /export 3 xlsx".to_string(),
+            },
+            Text::FeedUsage => match language {
+                Lv => "Lietojums: /feed <id>, piemēram, /feed 3".to_string(),
+                Ru => "Использование: /feed <id>, например, /feed 3".to_string(),
+                En => "Usage: /feed <id>, e.g. /feed 3".to_string(),
+            },
+            Text::FeedLinks { token } => match language {
+                Lv => format!(
+                    "Atom un RSS plūsmas bota HTTP adresē, nedalieties ar tām publiski:\n/feeds/{0}/atom.xml\n/feeds/{0}/rss.xml",
+                    token
+                ),
+                Ru => format!(
+                    "Ленты Atom и RSS по HTTP-адресу бота, не публикуйте их:\n/feeds/{0}/atom.xml\n/feeds/{0}/rss.xml",
+                    token
+                ),
+                En => format!(
+                    "Atom and RSS feeds on the bot's HTTP address, keep them private:\n/feeds/{0}/atom.xml\n/feeds/{0}/rss.xml",
+                    token
+                ),
+            },
+            Text::FilterUsage => {
+                let series = Series::KNOWN
+                    .iter()
+                    .map(|series| format!("{} ({})", series.code(), series.name(language)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                match language {
+                    Lv => format!(
+                        "Lietojums: /filter <id> <price | rooms | area | floor> <no-līdz | off>, piemēram, /filter 3 rooms 2-3\n/filter <id> floor not-first | not-last\n/filter <id> series <sērijas | off>, piemēram, /filter 3 series 103,lt\n/filter <id> <include | exclude | street | not-series> <vārdi, ... | off>, piemēram, /filter 3 exclude bez remonta, pirmais stāvs\n/filter <id> near <platums,garums> <km | off>, piemēram, /filter 3 near 56.9496,24.1052 2\nSērijas: {}",
+                        series
+                    ),
+                    Ru => format!(
+                        "Использование: /filter <id> <price | rooms | area | floor> <от-до | off>, например, /filter 3 rooms 2-3\n/filter <id> floor not-first | not-last\n/filter <id> series <серии | off>, например, /filter 3 series 103,lt\n/filter <id> <include | exclude | street | not-series> <слова, ... | off>, например, /filter 3 include балкон\n/filter <id> near <широта,долгота> <км | off>, например, /filter 3 near 56.9496,24.1052 2\nСерии: {}",
+                        series
+                    ),
+                    En => format!(
+                        "Usage: /filter <id> <price | rooms | area | floor> <from-to | off>, e.g. /filter 3 rooms 2-3\n/filter <id> floor not-first | not-last\n/filter <id> series <series | off>, e.g. /filter 3 series 103,lt\n/filter <id> <include | exclude | street | not-series> <words, ... | off>, e.g. /filter 3 include balkons\n/filter <id> near <latitude,longitude> <km | off>, e.g. /filter 3 near 56.9496,24.1052 2\nSeries: {}",
+                        series
+                    ),
+                }
+            }
+            Text::FilterSet { id, criteria } => {
+                let filters = Self::filters(criteria, language);
+                match language {
+                    Lv => format!("Abonementa #{} filtri: {}", id, filters),
+                    Ru => format!("Фильтры подписки #{}: {}", id, filters),
+                    En => format!("Filters of subscription #{}: {}", id, filters),
+                }
+            }
+            Text::SourcesUsage { sources } => match language {
+                Lv => format!(
+                    "Lietojums: /sources <id> <portāli>, piemēram, /sources 3 ss,city24\nPortāli: {}",
+                    sources
+                ),
+                Ru => format!(
+                    "Использование: /sources <id> <порталы>, например, /sources 3 ss,city24\nПорталы: {}",
+                    sources
+                ),
+                En => format!(
+                    "Usage: /sources <id> <portals>, e.g. /sources 3 ss,city24\nPortals: {}",
+                    sources
+                ),
+            },
+            Text::SourcesSet { id, sources } => match language {
+                Lv => format!("Abonements #{} tagad meklē: {}", id, sources),
+                Ru => format!("Подписка #{} теперь ищет на: {}", id, sources),
+                En => format!("Subscription #{} now searches {}", id, sources),
+            },
+            Text::DealsUsage => match language {
+                Lv => "Lietojums: /deals <id> <procenti | off>, piemēram, /deals 3 15 ziņos tikai par sludinājumiem vismaz 15% zem mediānas €/m²"
+                    .to_string(),
+                Ru => "Использование: /deals <id> <проценты | off>, например, /deals 3 15 — уведомлять только об объявлениях минимум на 15% ниже медианы €/м²"
+                    .to_string(),
+                En => "Usage: /deals <id> <percent | off>, e.g. /deals 3 15 notifies only on listings at least 15% below the median €/m²"
+                    .to_string(),
+            },
+            Text::DealsSet { id, min_discount: Some(min_discount) } => match language {
+                Lv => format!(
+                    "Abonements #{} ziņos tikai par sludinājumiem vismaz {}% zem mediānas €/m².",
+                    id, min_discount
+                ),
+                Ru => format!(
+                    "Подписка #{} будет уведомлять только об объявлениях минимум на {}% ниже медианы €/м².",
+                    id, min_discount
+                ),
+                En => format!(
+                    "Subscription #{} now notifies only on listings at least {}% below the median €/m².",
+                    id, min_discount
+                ),
+            },
+            Text::MarketUsage => match language {
+                Lv => "Lietojums: /market <pilsēta> <rajons>, piemēram, /market Rīga Centrs".to_string(),
+                Ru => "Использование: /market <город> <район>, например, /market Рига Центр".to_string(),
+                En => "Usage: /market <city> <district>, e.g. /market Rīga Centrs".to_string(),
+            },
+            Text::StatsNotFound => match language {
+                Lv => "Šajā rajonā vēl nav saglabātu dzīvokļu sludinājumu.".to_string(),
+                Ru => "По этому району ещё нет сохранённых объявлений о квартирах.".to_string(),
+                En => "No flat listings have been stored for this district yet.".to_string(),
+            },
+            Text::MarketStats { stats } => Self::market_stats(stats, language),
+            Text::MapUsage => match language {
+                Lv => "Lietojums: /map <id>, piemēram, /map 3".to_string(),
+                Ru => "Использование: /map <id>, например, /map 3".to_string(),
+                En => "Usage: /map <id>, e.g. /map 3".to_string(),
+            },
+            Text::NothingToMap => match language {
+                Lv => "Neviena atrastā sludinājuma adresi neizdevās atrast kartē.".to_string(),
+                Ru => "Ни один из найденных адресов не удалось найти на карте.".to_string(),
+                En => "None of the found listings could be located on a map.".to_string(),
+            },
+            Text::DealsSet { id, min_discount: None } => match language {
+                Lv => format!("Abonements #{} ziņos par visiem jaunajiem sludinājumiem.", id),
+                Ru => format!("Подписка #{} будет уведомлять обо всех новых объявлениях.", id),
+                En => format!("Subscription #{} now notifies on every new listing.", id),
+            },
+            Text::MarkupDrift {
+                url,
+                selector,
+                detail,
+            } => match language {
+                Lv => format!(
+                    "⚠️ ss.com lapas struktūra mainījusies\nSelektors: {}\n{}\n{}",
+                    selector, detail, url
+                ),
+                Ru => format!(
+                    "⚠️ Изменилась разметка ss.com\nСелектор: {}\n{}\n{}",
+                    selector, detail, url
+                ),
+                En => format!(
+                    "⚠️ ss.com markup changed\nSelector: {}\n{}\n{}",
+                    selector, detail, url
+                ),
+            },
+            Text::AdminStats { stats } => {
+                let last_scrape = stats
+                    .last_successful_scrape
+                    .map(|scraped_at| scraped_at.format("%Y-%m-%d %H:%M UTC").to_string())
+                    .unwrap_or_else(|| String::from("—"));
+                let labels = match language {
+                    Lv => [
+                        "Lietotāji",
+                        "Bloķēti",
+                        "Abonementi",
+                        "aktīvi",
+                        "Sludinājumi",
+                        "Pilsētas katalogā",
+                        "Pēdējā veiksmīgā ielāde",
+                        "Ielādētas lapas",
+                        "HTTP kļūdas",
+                        "Parsēšanas kļūdas",
+                        "Aptauja apturēta",
+                    ],
+                    Ru => [
+                        "Пользователи",
+                        "Заблокированы",
+                        "Подписки",
+                        "активны",
+                        "Объявления",
+                        "Городов в каталоге",
+                        "Последняя успешная загрузка",
+                        "Загружено страниц",
+                        "Ошибки HTTP",
+                        "Ошибки разбора",
+                        "Опрос приостановлен",
+                    ],
+                    En => [
+                        "Users",
+                        "Banned",
+                        "Subscriptions",
+                        "active",
+                        "Listings",
+                        "Cities in catalog",
+                        "Last successful scrape",
+                        "Pages fetched",
+                        "HTTP errors",
+                        "Parse failures",
+                        "Polling paused",
+                    ],
+                };
+                format!(
+                    "{}: {}\n{}: {}\n{}: {} ({}: {})\n{}: {}\n{}: {}\n{}: {}\n{}: {}\n{}: {}\n{}: {}\n{}: {}",
+                    labels[0],
+                    stats.chats,
+                    labels[1],
+                    stats.banned_chats,
+                    labels[2],
+                    stats.subscriptions,
+                    labels[3],
+                    stats.active_subscriptions,
+                    labels[4],
+                    stats.listings,
+                    labels[5],
+                    stats.catalog_cities,
+                    labels[6],
+                    last_scrape,
+                    labels[7],
+                    stats.pages_fetched,
+                    labels[8],
+                    stats.http_errors,
+                    labels[9],
+                    stats.parse_failures,
+                    labels[10],
+                    if stats.polling_paused { "✔" } else { "✘" }
+                )
+            }
+            Text::BroadcastUsage => match language {
+                Lv => "Lietojums: /broadcast <teksts>".to_string(),
+                Ru => "Использование: /broadcast <текст>".to_string(),
+                En => "Usage: /broadcast <text>".to_string(),
+            },
+            Text::BroadcastQueued { count } => match language {
+                Lv => format!("Ziņojums ievietots rindā {} čatiem.", count),
+                Ru => format!("Сообщение поставлено в очередь для {} чатов.", count),
+                En => format!("Message queued for {} chats.", count),
+            },
+            Text::CatalogRefreshing => match language {
+                Lv => "Atjaunoju katalogu, tas var aizņemt dažas minūtes...".to_string(),
+                Ru => "Обновляю каталог, это может занять несколько минут...".to_string(),
+                En => "Refreshing the catalog, this can take a few minutes...".to_string(),
+            },
+            Text::CatalogRefreshed { cities } => match language {
+                Lv => format!("Katalogs atjaunots, pilsētas: {}.", cities),
+                Ru => format!("Каталог обновлён, городов: {}.", cities),
+                En => format!("Catalog refreshed, {} cities.", cities),
+            },
+            Text::CatalogRefreshFailed => match language {
+                Lv => "Neizdevās atjaunot katalogu, iepriekšējais saglabāts.".to_string(),
+                Ru => "Не удалось обновить каталог, сохранён предыдущий.".to_string(),
+                En => "Failed to refresh the catalog, the previous one is kept.".to_string(),
+            },
+            Text::PollingPaused => match language {
+                Lv => "Abonementu aptauja apturēta. Atkārtojiet /pause_polling, lai atsāktu."
+                    .to_string(),
+                Ru => "Опрос подписок приостановлен. Повторите /pause_polling, чтобы возобновить."
+                    .to_string(),
+                En => "Polling paused. Send /pause_polling again to resume.".to_string(),
+            },
+            Text::PollingResumed => match language {
+                Lv => "Abonementu aptauja atsākta.".to_string(),
+                Ru => "Опрос подписок возобновлён.".to_string(),
+                En => "Polling resumed.".to_string(),
+            },
+            Text::Users { users } => {
+                if users.is_empty() {
+                    return match language {
+                        Lv => "Lietotāju vēl nav.".to_string(),
+                        Ru => "Пользователей пока нет.".to_string(),
+                        En => "No users yet.".to_string(),
+                    };
+                }
+                users
+                    .iter()
+                    .map(|user| {
+                        format!(
+                            "{} [{}] {}/{}{}",
+                            user.chat_id,
+                            user.language.map(|language| language.code()).unwrap_or("—"),
+                            user.active_subscriptions,
+                            user.subscriptions,
+                            if user.banned { " ⛔" } else { "" }
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            Text::BanUsage => match language {
+                Lv => "Lietojums: /ban <čata id>".to_string(),
+                Ru => "Использование: /ban <id чата>".to_string(),
+                En => "Usage: /ban <chat id>".to_string(),
+            },
+            Text::Banned {
+                chat_id,
+                subscriptions,
+            } => match language {
+                Lv => format!(
+                    "Čats {} bloķēts, apturēti abonementi: {}.",
+                    chat_id, subscriptions
+                ),
+                Ru => format!(
+                    "Чат {} заблокирован, приостановлено подписок: {}.",
+                    chat_id, subscriptions
+                ),
+                En => format!(
+                    "Chat {} banned, {} subscriptions paused.",
+                    chat_id, subscriptions
+                ),
+            },
+            Text::UnbanUsage => match language {
+                Lv => "Lietojums: /unban <čata id>".to_string(),
+                Ru => "Использование: /unban <id чата>".to_string(),
+                En => "Usage: /unban <chat id>".to_string(),
+            },
+            Text::Unbanned {
+                chat_id,
+                subscriptions,
+            } => match language {
+                Lv => format!(
+                    "Čats {} atbloķēts, atsākti abonementi: {}.",
+                    chat_id, subscriptions
+                ),
+                Ru => format!(
+                    "Чат {} разблокирован, возобновлено подписок: {}.",
+                    chat_id, subscriptions
+                ),
+                En => format!(
+                    "Chat {} unbanned, {} subscriptions resumed.",
+                    chat_id, subscriptions
+                ),
+            },
+            Text::NotBanned { chat_id } => match language {
+                Lv => format!("Čats {} nav bloķēts.", chat_id),
+                Ru => format!("Чат {} не заблокирован.", chat_id),
+                En => format!("Chat {} is not banned.", chat_id),
+            },
+        }
+    }
+
+    /// Counts, price quartiles, €/m² by rooms and the weekly trend of every deal type.
+    fn market_stats(stats: &MarketStats, language: Language) -> String {
+        let labels = match language {
+            Language::Lv => [
+                "sludinājumi",
+                "jauni šonedēļ",
+                "Cena",
+                "mediāna",
+                "€/m² pēc istabām",
+                "ist.",
+                "Tendence",
+                "nedēļās",
+            ],
+            Language::Ru => [
+                "объявлений",
+                "новых за неделю",
+                "Цена",
+                "медиана",
+                "€/м² по комнатам",
+                "комн.",
+                "Тенденция за",
+                "нед.",
+            ],
+            Language::En => [
+                "listings",
+                "new this week",
+                "Price",
+                "median",
+                "€/m² by rooms",
+                "rooms",
+                "Trend over",
+                "weeks",
+            ],
+        };
+        let [listings, new, price, median, by_rooms, rooms, trend, weeks] = labels;
+        let mut lines = vec![format!("{}, {}", stats.city, stats.district)];
+        for deal_type in &stats.deal_types {
+            lines.push(String::new());
+            lines.push(format!(
+                "{}: {} {}, {} {}",
+                deal_type.deal_type, deal_type.listings, listings, deal_type.new_listings, new
+            ));
+            if let Some([first, middle, third]) = deal_type.price_quartiles {
+                lines.push(format!(
+                    "{}: Q1 {:.0} €, {} {:.0} €, Q3 {:.0} €",
+                    price, first, median, middle, third
+                ));
+            }
+            if !deal_type.rooms.is_empty() {
+                lines.push(format!("{}:", by_rooms));
+                lines.extend(deal_type.rooms.iter().map(|room_stats| {
+                    let plus = if room_stats.rooms >= 4 { "+" } else { "" };
+                    format!(
+                        "  {}{} {}: {:.0} ({})",
+                        room_stats.rooms,
+                        plus,
+                        rooms,
+                        room_stats.median_per_square_meter,
+                        room_stats.listings
+                    )
+                }));
+            }
+            if let Some(trend_percent) = deal_type.trend_percent() {
+                lines.push(format!(
+                    "{} {} {}: {:+.1}%",
+                    trend,
+                    deal_type.weeks.len(),
+                    weeks,
+                    trend_percent
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// The price per square meter compared to the median of similar listings.
+    fn repost(flat: &Flat, language: Language) -> Option<String> {
+        let repost = flat.repost.as_ref()?;
+        let first_seen = repost.first_seen.format("%d.%m.%Y");
+        Some(match language {
+            Language::Lv => format!(
+                "Tās pašas fotogrāfijas kopš {} par {}: {}",
+                first_seen, repost.price, repost.url
+            ),
+            Language::Ru => format!(
+                "Те же фотографии с {} за {}: {}",
+                first_seen, repost.price, repost.url
+            ),
+            Language::En => format!(
+                "Same photos since {} at {}: {}",
+                first_seen, repost.price, repost.url
+            ),
+        })
+    }
+
+    fn deal(flat: &Flat, language: Language) -> Option<String> {
+        let deal = flat.deal.as_ref()?;
+        let percent = deal.percent_below_median.abs().round();
+        let price = deal.price_per_square_meter.round();
+        let median = deal.median_per_square_meter.round();
+        let below = deal.percent_below_median >= 0.0;
+        Some(match language {
+            Language::Lv => format!(
+                "{}% {} mediānas: {} pret {} €/m² ({} sludinājumi)",
+                percent,
+                if below { "zem" } else { "virs" },
+                price,
+                median,
+                deal.samples
+            ),
+            Language::Ru => format!(
+                "На {}% {} медианы: {} против {} €/м² (объявлений: {})",
+                percent,
+                if below { "ниже" } else { "выше" },
+                price,
+                median,
+                deal.samples
+            ),
+            Language::En => format!(
+                "{}% {} the median: {} vs {} €/m² ({} listings)",
+                percent,
+                if below { "below" } else { "above" },
+                price,
+                median,
+                deal.samples
+            ),
+        })
+    }
+
+    /// Price and the other ranges of a search, e.g. `0-90000 €, rooms 2-3, floor 2-`.
+    fn filters(criteria: &FlatCriteria, language: Language) -> String {
+        let range = |from: Option<u32>, to: Option<u32>| {
+            format!(
+                "{}-{}",
+                from.map(|from| from.to_string()).unwrap_or_default(),
+                to.map(|to| to.to_string()).unwrap_or_default()
+            )
+        };
+        let (rooms, area, floor, series) = match language {
+            Language::Lv => ("istabas", "platība", "stāvs", "sērija"),
+            Language::Ru => ("комнаты", "площадь", "этаж", "серия"),
+            Language::En => ("rooms", "area", "floor", "series"),
+        };
+        let (not_first_floor, not_last_floor) = match language {
+            Language::Lv => ("ne pirmais stāvs", "ne pēdējais stāvs"),
+            Language::Ru => ("не первый этаж", "не последний этаж"),
+            Language::En => ("not first floor", "not last floor"),
+        };
+        let (not_series, not_streets, with_keywords, without_keywords) = match language {
+            Language::Lv => ("ne sērija", "ne ielas", "ar", "bez"),
+            Language::Ru => ("не серия", "не улицы", "с", "без"),
+            Language::En => ("not series", "not streets", "with", "without"),
+        };
+        let within = match language {
+            Language::Lv => "rādiusā",
+            Language::Ru => "в радиусе",
+            Language::En => "within",
+        };
+        let mut filters = vec![format!("{}-{} €", criteria.price_from, criteria.price_to)];
+        let ranges = [
+            (rooms, criteria.rooms_from, criteria.rooms_to, ""),
+            (
+                area,
+                criteria.square_meters_from,
+                criteria.square_meters_to,
+                " m²",
+            ),
+            (floor, criteria.floor_from, criteria.floor_to, ""),
+        ];
+        for (name, from, to, unit) in ranges {
+            if from.is_some() || to.is_some() {
+                filters.push(format!("{} {}{}", name, range(from, to), unit));
+            }
+        }
+        if criteria.not_first_floor {
+            filters.push(not_first_floor.to_string());
+        }
+        if criteria.not_last_floor {
+            filters.push(not_last_floor.to_string());
+        }
+        if !criteria.series.is_empty() {
+            let names = criteria
+                .series
+                .iter()
+                .map(|series| series.name(language))
+                .collect::<Vec<_>>()
+                .join(" / ");
+            filters.push(format!("{} {}", series, names));
+        }
+        if !criteria.excluded_series.is_empty() {
+            let names = criteria
+                .excluded_series
+                .iter()
+                .map(|series| series.name(language))
+                .collect::<Vec<_>>()
+                .join(" / ");
+            filters.push(format!("{} {}", not_series, names));
+        }
+        let phrases = [
+            (not_streets, &criteria.excluded_streets),
+            (with_keywords, &criteria.include_keywords),
+            (without_keywords, &criteria.exclude_keywords),
+        ];
+        for (name, phrases) in phrases {
+            if !phrases.is_empty() {
+                let quoted = phrases
+                    .iter()
+                    .map(|phrase| format!("\"{}\"", phrase))
+                    .collect::<Vec<_>>()
+                    .join(" / ");
+                filters.push(format!("{} {}", name, quoted));
+            }
+        }
+        if let Some(area) = &criteria.area {
+            filters.push(format!(
+                "{} {} km ({:.5},{:.5})",
+                within, area.radius_km, area.center.latitude, area.center.longitude
+            ));
+        }
+        filters.join(", ")
+    }
+}
@@ -1,42 +1,186 @@
+pub mod alerts;
 pub mod asynchronous;
+pub mod config;
+pub mod export;
+pub mod feed;
 pub mod flats;
+pub mod geo;
+pub mod health;
+pub mod http;
+pub mod i18n;
 pub mod logger;
+pub mod metrics;
+pub mod notifications;
+pub mod photos;
+pub mod shutdown;
+pub mod sources;
+pub mod stats;
+pub mod storage;
+pub mod subscriptions;
 pub mod telegram;
 
-use core::panic;
+use std::process::ExitCode;
 use std::sync::Arc;
 
 use dotenv::dotenv;
 use logger::Logger;
-use tokio::{signal, sync::Mutex};
+use teloxide::Bot;
+use tokio::sync::{mpsc, Mutex};
+use tokio_util::sync::CancellationToken;
 
-pub fn init() -> Result<(), anyhow::Error> {
-    if let Err(error) = Logger::new() {
-        return Err(error);
-    }
+/// Exit code when the bot stopped but something was lost on the way down.
+const EXIT_UNCLEAN_SHUTDOWN: u8 = 2;
+
+/// Runs the bot until SIGINT or SIGTERM and returns the process exit code.
+pub fn init() -> Result<ExitCode, anyhow::Error> {
     dotenv().ok();
+    let config = config::Config::load()?;
+    Logger::init(&config.log_config, config.log_format)?;
     Logger::info("Logger initialized successfully");
-    let tokio_runtime = Arc::new(asynchronous::tokio::runtime::AppRuntime::new());
-    let flats_parser = flats::FlatsParser::new(Arc::clone(&tokio_runtime));
+    let tokio_runtime = Arc::new(asynchronous::tokio::runtime::AppRuntime::new(
+        config.worker_threads,
+    ));
+    let (drift_sender, drift_receiver) = mpsc::unbounded_channel();
+    let mut flats_parser = flats::FlatsParser::new(config.base_url.clone(), config.scrape_language);
+    flats_parser.set_drift_reporter(drift_sender, config.row_failure_threshold);
+    let flats_parser = Arc::new(Mutex::new(flats_parser));
+    let city24_parser =
+        sources::City24Parser::new(config.city24_api_url.clone(), config.scrape_language);
+    let geocoder = geo::Geocoder::load(config.geocoding_dataset.as_deref())?;
+    let listing_sources = sources::ListingSources::new(
+        vec![
+            Box::new(sources::SsSource::new(
+                Arc::clone(&flats_parser),
+                config.base_url.clone(),
+            )),
+            Box::new(city24_parser),
+        ],
+        Arc::new(geocoder),
+    );
+    let storage = Arc::new(Mutex::new(storage::Storage::load(&config.storage_path)?));
+
+    let bot = Bot::new(config.telegram_token.clone().unwrap_or_default());
+    let (message_queue, message_queue_worker) =
+        telegram::queue::MessageQueue::new(bot.clone(), Arc::clone(&storage));
+    let shutdown = CancellationToken::new();
+    let queue_drain = CancellationToken::new();
+    let message_queue_handle = tokio_runtime
+        .runtime
+        .spawn(message_queue_worker.run(queue_drain.clone()));
+    let drift_alerter = alerts::DriftAlerter::new(
+        drift_receiver,
+        message_queue.clone(),
+        Arc::clone(&storage),
+        config.admin_chat_ids.clone(),
+    );
+    tokio_runtime.runtime.spawn(drift_alerter.run());
 
     let mut telegram_bot = telegram::FlatsBotTelegram::new(
         Arc::clone(&tokio_runtime),
-        Arc::new(Mutex::new(flats_parser)),
+        bot,
+        Arc::clone(&flats_parser),
+        listing_sources.clone(),
+        Arc::clone(&storage),
+        message_queue.clone(),
+        config.admin_chat_ids.clone(),
     );
     telegram_bot.init()?;
 
-    let bot_tokio = Arc::clone(&tokio_runtime);
-    bot_tokio.runtime.spawn(async move {
-        let _qwe = telegram_bot.run().await;
+    let http_handle = config.http_address().map(|address| {
+        let http_server = http::HttpServer::new(
+            address,
+            Arc::clone(&flats_parser),
+            listing_sources.clone(),
+            Arc::clone(&storage),
+            config.scrape_stale_after(),
+            config.http_token.clone(),
+        );
+        let shutdown = shutdown.clone();
+        tokio_runtime.runtime.spawn(async move {
+            if let Err(error) = http_server.run(shutdown).await {
+                Logger::error(format!("HTTP API stopped: {}", error).as_str());
+            }
+        })
+    });
+
+    let notifier = notifications::Notifier::new(message_queue, Arc::clone(&storage));
+    let poller = subscriptions::SubscriptionPoller::new(
+        listing_sources,
+        Arc::clone(&storage),
+        notifier,
+        (config.photos_per_listing > 0)
+            .then(|| photos::PhotoHasher::new(config.photos_per_listing)),
+        config.poll_interval(),
+        shutdown.clone(),
+    );
+    let poller_handle = tokio_runtime.runtime.spawn(async move {
+        poller.run().await;
     });
 
-    let blocking_tokio = Arc::clone(&tokio_runtime);
-    blocking_tokio.runtime.block_on(async {
-        if let Err(res) = signal::ctrl_c().await {
-            Logger::error(&format!("Failed to catch ctrl-c signal: {}", res));
-            panic!("Failed to catch ctrl-c signal: {}", res);
-        };
+    let bot_shutdown = shutdown.clone();
+    let mut bot_handle = tokio_runtime.runtime.spawn(async move {
+        if let Err(error) = telegram_bot.run(bot_shutdown).await {
+            Logger::error(format!("Telegram dispatcher failed: {}", error).as_str());
+        }
     });
 
-    Ok(())
+    let clean = tokio_runtime.runtime.block_on(async move {
+        let mut clean = true;
+        tokio::select! {
+            signal = shutdown::wait_for_signal() => match signal {
+                Ok(name) => Logger::info(format!("Received {}, shutting down", name).as_str()),
+                Err(error) => {
+                    Logger::error(format!("Failed to listen for signals: {}", error).as_str());
+                    clean = false;
+                }
+            },
+            _ = &mut bot_handle => {
+                Logger::error("Telegram dispatcher stopped unexpectedly, shutting down");
+                clean = false;
+            }
+        }
+        shutdown.cancel();
+
+        if !bot_handle.is_finished() {
+            clean &= shutdown::join_task(
+                "Telegram dispatcher",
+                bot_handle,
+                shutdown::TASK_STOP_TIMEOUT,
+            )
+            .await;
+        }
+        if let Some(http_handle) = http_handle {
+            clean &=
+                shutdown::join_task("HTTP API", http_handle, shutdown::TASK_STOP_TIMEOUT).await;
+        }
+        clean &= shutdown::join_task(
+            "Subscription poller",
+            poller_handle,
+            shutdown::POLL_STOP_TIMEOUT,
+        )
+        .await;
+
+        // nothing enqueues messages anymore, send what is left
+        queue_drain.cancel();
+        clean &= shutdown::join_task(
+            "Outbound message queue",
+            message_queue_handle,
+            shutdown::QUEUE_DRAIN_TIMEOUT,
+        )
+        .await;
+
+        if let Err(error) = storage.lock().await.save() {
+            Logger::error(format!("Failed to flush storage: {}", error).as_str());
+            clean = false;
+        }
+        clean
+    });
+
+    if clean {
+        Logger::info("Shut down cleanly");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Logger::warn("Shut down with errors");
+        Ok(ExitCode::from(EXIT_UNCLEAN_SHUTDOWN))
+    }
 }
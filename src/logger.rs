@@ -1,29 +1,120 @@
 use anyhow::Error;
+use log::Level;
 use log4rs;
+use serde::Deserialize;
+use std::fmt::Display;
+use std::future::Future;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+static NEXT_CORRELATION_ID: AtomicU64 = AtomicU64::new(1);
+
+tokio::task_local! {
+    static CONTEXT: LogContext;
+}
+
+/// How structured fields are written, must match the encoder in the log4rs config.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Fields are appended to the message as `key=value`.
+    #[default]
+    Text,
+    /// Fields go to the MDC, which the log4rs `json` encoder writes as an object.
+    Json,
+}
+
+/// Fields attached to every record logged by a task, e.g. the correlation id of a polling cycle.
+#[derive(Debug, Clone, Default)]
+pub struct LogContext {
+    fields: Vec<(&'static str, String)>,
+}
+
+impl LogContext {
+    /// Context of the current task, empty outside of [`LogContext::scope`].
+    pub fn current() -> Self {
+        CONTEXT.try_with(Clone::clone).unwrap_or_default()
+    }
+
+    /// The current context with a fresh `correlation_id` like `poll-42`.
+    pub fn correlated(prefix: &str) -> Self {
+        let id = NEXT_CORRELATION_ID.fetch_add(1, Ordering::Relaxed);
+        Self::current().with("correlation_id", format!("{}-{}", prefix, id))
+    }
+
+    pub fn with(mut self, key: &'static str, value: impl Display) -> Self {
+        self.fields.retain(|(field, _)| *field != key);
+        self.fields.push((key, value.to_string()));
+        self
+    }
+
+    /// Runs `future` with these fields attached to everything it logs.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CONTEXT.scope(self, future).await
+    }
+}
 
 pub struct Logger {
     pub log: log4rs::Handle,
 }
 
 impl Logger {
-    pub fn new() -> Result<(), Error> {
-        log4rs::init_file("log4rs.yaml", Default::default())?;
+    pub fn init(config_path: &Path, format: LogFormat) -> Result<(), Error> {
+        log4rs::init_file(config_path, Default::default())?;
+        let _ = FORMAT.set(format);
         Ok(())
     }
 
     pub fn error(message: &str) {
-        log::error!(target: "base", "{message}");
+        Self::log(Level::Error, message, &[]);
     }
 
     pub fn warn(message: &str) {
-        log::warn!(target: "base", "{message}");
+        Self::log(Level::Warn, message, &[]);
     }
 
     pub fn info(message: &str) {
-        log::info!(target: "base", "{message}");
+        Self::log(Level::Info, message, &[]);
     }
 
     pub fn debug(message: &str) {
-        log::debug!(target: "base", "{message}");
+        Self::log(Level::Debug, message, &[]);
+    }
+
+    /// Logs `message` with `fields` on top of the task's [`LogContext`].
+    pub fn log(level: Level, message: &str, fields: &[(&'static str, &dyn Display)]) {
+        if !log::log_enabled!(target: "base", level) {
+            return;
+        }
+        let mut context = LogContext::current();
+        for (key, value) in fields {
+            context = context.with(key, value);
+        }
+        if context.fields.is_empty() {
+            log::log!(target: "base", level, "{message}");
+            return;
+        }
+
+        match FORMAT.get().copied().unwrap_or_default() {
+            LogFormat::Text => {
+                let fields = context
+                    .fields
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                log::log!(target: "base", level, "{message} | {fields}");
+            }
+            LogFormat::Json => {
+                // the MDC is thread local, so it only lives for this synchronous call
+                log_mdc::extend(context.fields.iter().map(|(key, value)| (*key, value)));
+                log::log!(target: "base", level, "{message}");
+                for (key, _) in &context.fields {
+                    log_mdc::remove(*key);
+                }
+            }
+        }
     }
 }
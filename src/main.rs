@@ -1,5 +1,11 @@
-fn main() {
-    if let Err(err) = flats_bot::init() {
-        panic!("Failed to initialize and run the bot: {}", err);
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    match flats_bot::init() {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("Failed to initialize and run the bot: {}", err);
+            ExitCode::FAILURE
+        }
     }
 }
@@ -0,0 +1,127 @@
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+
+/// Process wide metrics, exposed in the Prometheus text format on `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    /// Labelled with the portal, e.g. `ss` or `city24`.
+    pub pages_fetched: IntCounterVec,
+    /// Labelled with the HTTP status code, or `network` when no response arrived.
+    pub http_errors: IntCounterVec,
+    /// Labelled with the selector that matched nothing.
+    pub parse_failures: IntCounterVec,
+    pub scrape_duration: Histogram,
+    /// Listings matched by each subscription on its last poll.
+    pub subscription_listings: IntGaugeVec,
+    pub notifications_sent: IntCounter,
+    pub notifications_failed: IntCounter,
+    /// Chats with at least one active subscription.
+    pub active_chats: IntGauge,
+}
+
+static METRICS: LazyLock<Metrics> =
+    LazyLock::new(|| Metrics::new().expect("Failed to register metrics"));
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some(String::from("flats_bot")), None)?;
+
+        let pages_fetched = IntCounterVec::new(
+            Opts::new("pages_fetched_total", "Pages fetched by portal"),
+            &["source"],
+        )?;
+        let http_errors = IntCounterVec::new(
+            Opts::new("http_errors_total", "Failed page requests by status"),
+            &["status"],
+        )?;
+        let parse_failures = IntCounterVec::new(
+            Opts::new(
+                "parse_failures_total",
+                "Pages where an expected element was missing, by selector",
+            ),
+            &["selector"],
+        )?;
+        let scrape_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "scrape_duration_seconds",
+                "Time spent fetching a single page",
+            )
+            .buckets(vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]),
+        )?;
+        let subscription_listings = IntGaugeVec::new(
+            Opts::new(
+                "subscription_listings",
+                "Listings found on the last poll of a subscription",
+            ),
+            &["subscription"],
+        )?;
+        let notifications_sent =
+            IntCounter::new("notifications_sent_total", "Telegram messages delivered")?;
+        let notifications_failed = IntCounter::new(
+            "notifications_failed_total",
+            "Telegram messages dropped after retries",
+        )?;
+        let active_chats = IntGauge::new(
+            "active_chats",
+            "Chats with at least one active subscription",
+        )?;
+
+        registry.register(Box::new(pages_fetched.clone()))?;
+        registry.register(Box::new(http_errors.clone()))?;
+        registry.register(Box::new(parse_failures.clone()))?;
+        registry.register(Box::new(scrape_duration.clone()))?;
+        registry.register(Box::new(subscription_listings.clone()))?;
+        registry.register(Box::new(notifications_sent.clone()))?;
+        registry.register(Box::new(notifications_failed.clone()))?;
+        registry.register(Box::new(active_chats.clone()))?;
+
+        Ok(Self {
+            registry,
+            pages_fetched,
+            http_errors,
+            parse_failures,
+            scrape_duration,
+            subscription_listings,
+            notifications_sent,
+            notifications_failed,
+            active_chats,
+        })
+    }
+
+    /// Drops the per-subscription series of a removed subscription.
+    pub fn forget_subscription(&self, id: u64) {
+        // the subscription may never have been polled
+        let _ = self
+            .subscription_listings
+            .remove_label_values(&[id.to_string().as_str()]);
+    }
+
+    pub fn parse_failure(&self, selector: &str) {
+        self.parse_failures.with_label_values(&[selector]).inc();
+    }
+
+    /// Sum of a labelled counter over all its labels.
+    pub fn total(counter: &IntCounterVec) -> u64 {
+        counter
+            .collect()
+            .iter()
+            .flat_map(|family| family.get_metric())
+            .map(|metric| metric.get_counter().get_value() as u64)
+            .sum()
+    }
+
+    /// Encodes every registered metric in the Prometheus text exposition format.
+    pub fn render(&self) -> Result<String, anyhow::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
@@ -0,0 +1,255 @@
+use crate::flats::Flat;
+use crate::i18n::{Language, Text};
+use crate::logger::Logger;
+use crate::storage::Storage;
+use crate::telegram::queue::MessageQueue;
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+
+pub const DEFAULT_TIMEZONE: &str = "Europe/Riga";
+/// Maximum amount of flats sent in a single message.
+pub const FLATS_PER_MESSAGE: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeliveryMode {
+    #[default]
+    Instant,
+    Hourly,
+    Daily,
+}
+
+impl DeliveryMode {
+    pub fn from_name(name: &str) -> Option<DeliveryMode> {
+        match name.trim().to_lowercase().as_str() {
+            "instant" => Some(DeliveryMode::Instant),
+            "hourly" => Some(DeliveryMode::Hourly),
+            "daily" => Some(DeliveryMode::Daily),
+            _ => None,
+        }
+    }
+
+    fn period(&self) -> Duration {
+        match self {
+            DeliveryMode::Instant => Duration::zero(),
+            DeliveryMode::Hourly => Duration::hours(1),
+            DeliveryMode::Daily => Duration::days(1),
+        }
+    }
+}
+
+/// Local time window in which no messages are sent, may wrap around midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    /// Parses a range like `23:00-07:00`.
+    pub fn parse(text: &str) -> Option<QuietHours> {
+        let (start, end) = text.split_once('-')?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+        if start == end {
+            return None;
+        }
+        Some(QuietHours { start, end })
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSettings {
+    pub language: Option<Language>,
+    pub timezone: String,
+    pub quiet_hours: Option<QuietHours>,
+    pub delivery_mode: DeliveryMode,
+    /// Matches held back by quiet hours or digest mode.
+    pub pending: Vec<Flat>,
+    pub last_digest: Option<DateTime<Utc>>,
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        Self {
+            language: None,
+            timezone: DEFAULT_TIMEZONE.to_string(),
+            quiet_hours: None,
+            delivery_mode: DeliveryMode::default(),
+            pending: Vec::new(),
+            last_digest: None,
+        }
+    }
+}
+
+impl ChatSettings {
+    pub fn tz(&self) -> Tz {
+        self.timezone
+            .parse::<Tz>()
+            .unwrap_or(chrono_tz::Europe::Riga)
+    }
+
+    pub fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        let local_time = now.with_timezone(&self.tz()).time();
+        self.quiet_hours
+            .is_some_and(|quiet_hours| quiet_hours.contains(local_time))
+    }
+
+    pub fn delivers_instantly(&self, now: DateTime<Utc>) -> bool {
+        self.delivery_mode == DeliveryMode::Instant && !self.is_quiet(now)
+    }
+
+    pub fn is_digest_due(&self, now: DateTime<Utc>) -> bool {
+        if self.pending.is_empty() || self.is_quiet(now) {
+            return false;
+        }
+        match self.last_digest {
+            Some(last_digest) => now - last_digest >= self.delivery_mode.period(),
+            None => true,
+        }
+    }
+}
+
+/// Delivers new matches to chats, holding them back during quiet hours and in digest mode.
+pub struct Notifier {
+    message_queue: MessageQueue,
+    storage: Arc<Mutex<Storage>>,
+}
+
+impl Notifier {
+    pub fn new(message_queue: MessageQueue, storage: Arc<Mutex<Storage>>) -> Self {
+        Self {
+            message_queue,
+            storage,
+        }
+    }
+
+    pub async fn notify(&self, chat_id: i64, flats: Vec<Flat>) -> Result<(), anyhow::Error> {
+        if flats.is_empty() {
+            return Ok(());
+        }
+        let now = Utc::now();
+        let settings = {
+            let mut storage = self.storage.lock().await;
+            let settings = storage.chat_settings_mut(chat_id);
+            if !settings.delivers_instantly(now) {
+                settings.pending.extend(flats);
+                storage.save()?;
+                return Ok(());
+            }
+            settings.clone()
+        };
+        let language = settings.language.unwrap_or_default();
+        self.send_flats(
+            chat_id,
+            Text::NewFlats { count: flats.len() }.render(language),
+            &flats,
+            language,
+        )
+    }
+
+    /// Sends a single digest to every chat whose queued matches are due.
+    pub async fn flush_digests(&self) -> Result<(), anyhow::Error> {
+        let now = Utc::now();
+        let digests = {
+            let mut storage = self.storage.lock().await;
+            let banned_chats = storage.data.banned_chats.clone();
+            let mut digests = Vec::new();
+            for (chat_id, settings) in storage.data.chats.iter_mut() {
+                if !settings.is_digest_due(now) {
+                    continue;
+                }
+                // listings queued before a ban are dropped rather than delivered after an unban
+                if banned_chats.contains(chat_id) {
+                    settings.last_digest = Some(now);
+                    settings.pending.clear();
+                    continue;
+                }
+                settings.last_digest = Some(now);
+                let flats = std::mem::take(&mut settings.pending);
+                digests.push((*chat_id, settings.language.unwrap_or_default(), flats));
+            }
+            if !digests.is_empty() {
+                storage.save()?;
+            }
+            digests
+        };
+
+        for (chat_id, language, flats) in digests {
+            let header = Text::Digest { count: flats.len() }.render(language);
+            if let Err(error) = self.send_flats(chat_id, header, &flats, language) {
+                Logger::error(
+                    format!("Failed to send digest to chat {}: {}", chat_id, error).as_str(),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn send_flats(
+        &self,
+        chat_id: i64,
+        header: String,
+        flats: &[Flat],
+        language: Language,
+    ) -> Result<(), anyhow::Error> {
+        let chat_id = ChatId(chat_id);
+        self.message_queue.send(chat_id, header)?;
+        for chunk in flats.chunks(FLATS_PER_MESSAGE) {
+            let text = chunk
+                .iter()
+                .map(|flat| Text::Flat { flat }.render(language))
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            self.message_queue.send(chat_id, text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(text, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn quiet_hours_wrap_around_midnight() {
+        let quiet_hours = QuietHours::parse("23:00-07:00").unwrap();
+        assert!(quiet_hours.contains(time("23:00")));
+        assert!(quiet_hours.contains(time("00:30")));
+        assert!(quiet_hours.contains(time("06:59")));
+        assert!(!quiet_hours.contains(time("07:00")));
+        assert!(!quiet_hours.contains(time("12:00")));
+        assert!(!quiet_hours.contains(time("22:59")));
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let quiet_hours = QuietHours::parse("13:00-15:30").unwrap();
+        assert!(quiet_hours.contains(time("13:00")));
+        assert!(quiet_hours.contains(time("15:29")));
+        assert!(!quiet_hours.contains(time("15:30")));
+        assert!(!quiet_hours.contains(time("00:00")));
+    }
+
+    #[test]
+    fn quiet_hours_reject_invalid_ranges() {
+        assert_eq!(QuietHours::parse("07:00-07:00"), None);
+        assert_eq!(QuietHours::parse("25:00-07:00"), None);
+        assert_eq!(QuietHours::parse("23:00"), None);
+    }
+}
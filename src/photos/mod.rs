@@ -0,0 +1,220 @@
+use crate::flats::Flat;
+use crate::sources::ListingSources;
+use chrono::{DateTime, Utc};
+use image::imageops::FilterType;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Hashes at most this many bits apart are the same photo, rescaling and recompression flip a
+/// few of them.
+const MAX_HASH_DISTANCE: u32 = 6;
+/// Nearly uniform images, e.g. "no photo" placeholders, hash alike without being the same.
+const MIN_HASH_BITS: u32 = 8;
+/// Listings are the same flat when they share this many photos, or all photos of the one
+/// with fewer.
+const MIN_MATCHING_PHOTOS: usize = 2;
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(20);
+const MAX_PHOTO_BYTES: usize = 10 * 1024 * 1024;
+
+/// 64-bit difference hash of a photo, close hashes mean the same photo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PhotoHash(pub u64);
+
+impl PhotoHash {
+    /// Compares the brightness of neighbouring pixels of a 9×8 grayscale thumbnail, which
+    /// survives resizing, recompression and small color changes.
+    pub fn of(bytes: &[u8]) -> Result<PhotoHash, anyhow::Error> {
+        let thumbnail = image::load_from_memory(bytes)?
+            .resize_exact(9, 8, FilterType::Triangle)
+            .to_luma8();
+        let mut hash = 0u64;
+        for y in 0..8 {
+            for x in 0..8 {
+                hash <<= 1;
+                if thumbnail.get_pixel(x, y)[0] < thumbnail.get_pixel(x + 1, y)[0] {
+                    hash |= 1;
+                }
+            }
+        }
+        Ok(PhotoHash(hash))
+    }
+
+    /// Number of differing bits.
+    pub fn distance(&self, other: &PhotoHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+
+    pub fn matches(&self, other: &PhotoHash) -> bool {
+        self.distance(other) <= MAX_HASH_DISTANCE
+    }
+
+    /// Whether the photo has enough detail to tell it apart from others.
+    pub fn is_distinctive(&self) -> bool {
+        (MIN_HASH_BITS..=64 - MIN_HASH_BITS).contains(&self.0.count_ones())
+    }
+}
+
+/// Whether two listings show the same flat, e.g. an ad reposted to move it up or an agency
+/// copying a private ad.
+pub fn same_photos(photos: &[PhotoHash], other_photos: &[PhotoHash]) -> bool {
+    let distinctive = |photos: &[PhotoHash]| {
+        photos
+            .iter()
+            .filter(|photo| photo.is_distinctive())
+            .copied()
+            .collect::<Vec<_>>()
+    };
+    // a single shared photo may well be a stock shot of the building or the neighbourhood
+    let (photos, other_photos) = (distinctive(photos), distinctive(other_photos));
+    let matching = photos
+        .iter()
+        .filter(|photo| other_photos.iter().any(|other| photo.matches(other)))
+        .count();
+    matching >= MIN_MATCHING_PHOTOS
+}
+
+/// An earlier listing with the same photos under another url.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Repost {
+    pub url: String,
+    pub price: String,
+    pub first_seen: DateTime<Utc>,
+}
+
+/// Downloads and hashes the photos of listings.
+pub struct PhotoHasher {
+    request_client: Client,
+    photos_per_listing: usize,
+}
+
+impl PhotoHasher {
+    pub fn new(photos_per_listing: usize) -> Self {
+        Self {
+            request_client: Client::new(),
+            photos_per_listing,
+        }
+    }
+
+    /// Hashes of the search result image and the first photos of the listing page, each photo
+    /// once. Fails when the listing page or any of the photos could not be loaded, so partial
+    /// hashes are never stored.
+    pub async fn hash_listing(
+        &self,
+        listing_sources: &ListingSources,
+        flat: &Flat,
+    ) -> Result<Vec<PhotoHash>, anyhow::Error> {
+        let details = listing_sources.fetch_details(&flat.url).await?;
+        let mut urls = vec![flat.image_url.clone()];
+        urls.extend(details.photo_urls);
+
+        let mut hashes: Vec<PhotoHash> = Vec::new();
+        for url in urls.iter().filter(|url| !url.is_empty()) {
+            if hashes.len() >= self.photos_per_listing {
+                break;
+            }
+            let hash = self
+                .hash_photo(url)
+                .await
+                .map_err(|error| anyhow::anyhow!("Failed to hash photo {}: {}", url, error))?;
+            // the search result image is a thumbnail of the first photo
+            if !hashes.iter().any(|known| known.matches(&hash)) {
+                hashes.push(hash);
+            }
+        }
+        Ok(hashes)
+    }
+
+    async fn hash_photo(&self, url: &str) -> Result<PhotoHash, anyhow::Error> {
+        let bytes = self
+            .request_client
+            .get(url)
+            .timeout(DOWNLOAD_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        if bytes.len() > MAX_PHOTO_BYTES {
+            return Err(anyhow::anyhow!("Photo has {} bytes", bytes.len()));
+        }
+        // decoding a full size photo takes a while
+        tokio::task::spawn_blocking(move || PhotoHash::of(&bytes)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageOutputFormat, RgbImage};
+    use std::io::Cursor;
+
+    /// A photo-like image of random 8×8 blocks, the same seed draws the same image.
+    fn blocky_image(seed: u32) -> DynamicImage {
+        let mut state = seed;
+        let mut blocks = [[0u8; 8]; 8];
+        for value in blocks.iter_mut().flatten() {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            *value = (state >> 16) as u8;
+        }
+        DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+            let value = blocks[(y / 8) as usize][(x / 8) as usize];
+            image::Rgb([value, value / 2, 255 - value])
+        }))
+    }
+
+    fn hash(image: &DynamicImage, format: ImageOutputFormat) -> PhotoHash {
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), format)
+            .expect("image encodes");
+        PhotoHash::of(&bytes).expect("image decodes")
+    }
+
+    #[test]
+    fn distance_counts_differing_bits() {
+        assert_eq!(PhotoHash(0).distance(&PhotoHash(0)), 0);
+        assert_eq!(PhotoHash(0b1011).distance(&PhotoHash(0b0001)), 2);
+        assert_eq!(PhotoHash(u64::MAX).distance(&PhotoHash(0)), 64);
+        assert!(PhotoHash(0xFF).matches(&PhotoHash(0xFF << 3)));
+        assert!(!PhotoHash(0xFF).matches(&PhotoHash(0xFF << 4)));
+    }
+
+    #[test]
+    fn rescaled_and_recompressed_photos_hash_alike() {
+        let photo = blocky_image(1);
+        let original = hash(&photo, ImageOutputFormat::Png);
+        assert!(original.is_distinctive());
+        let thumbnail = photo.resize(40, 40, FilterType::Triangle);
+        assert!(original.matches(&hash(&thumbnail, ImageOutputFormat::Jpeg(70))));
+        let other = hash(&blocky_image(2), ImageOutputFormat::Png);
+        assert!(!original.matches(&other));
+    }
+
+    #[test]
+    fn uniform_photos_are_not_distinctive() {
+        let placeholder =
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, image::Rgb([200; 3])));
+        assert!(!hash(&placeholder, ImageOutputFormat::Png).is_distinctive());
+    }
+
+    #[test]
+    fn same_photos_needs_two_shared_photos() {
+        let (a, b, c) = (
+            PhotoHash(0x0F0F_0F0F_0F0F_0F0F),
+            PhotoHash(0x00FF_00FF_00FF_00FF),
+            PhotoHash(0x3333_3333_3333_3333),
+        );
+        // one flipped bit, e.g. after recompression
+        let a_copy = PhotoHash(a.0 ^ 1);
+        assert!(same_photos(&[a, b, c], &[b, a_copy]));
+        assert!(!same_photos(&[a, b], &[a, c]));
+        // a single shared photo may be a stock shot, even when it is all a listing has
+        assert!(!same_photos(&[a], &[c, a_copy]));
+        assert!(!same_photos(&[a], &[a_copy]));
+        assert!(!same_photos(&[], &[a]));
+        // placeholders do not count as shared photos
+        assert!(!same_photos(&[PhotoHash(0), a], &[PhotoHash(0), c]));
+    }
+}
@@ -0,0 +1,49 @@
+use crate::logger::Logger;
+use std::future::Future;
+use std::time::Duration;
+use tokio::signal;
+
+/// Time given to the dispatcher and the HTTP API to finish in-flight requests.
+pub const TASK_STOP_TIMEOUT: Duration = Duration::from_secs(15);
+/// A poll stops after its current subscription, which can crawl several pages.
+pub const POLL_STOP_TIMEOUT: Duration = Duration::from_secs(120);
+/// Time given to the outbound queue to deliver what is left under the rate limits.
+pub const QUEUE_DRAIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Waits for SIGINT or SIGTERM and returns the name of the signal received.
+pub async fn wait_for_signal() -> Result<&'static str, anyhow::Error> {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = signal::ctrl_c() => result.map(|_| "SIGINT").map_err(anyhow::Error::from),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        signal::ctrl_c().await?;
+        Ok("Ctrl-C")
+    }
+}
+
+/// Waits for a shutting down task, returns `false` when it failed or took longer than `timeout`.
+pub async fn join_task<T, F>(name: &str, task: F, timeout: Duration) -> bool
+where
+    F: Future<Output = Result<T, tokio::task::JoinError>>,
+{
+    match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(_)) => {
+            Logger::info(format!("{} stopped", name).as_str());
+            true
+        }
+        Ok(Err(error)) => {
+            Logger::error(format!("{} failed while stopping: {}", name, error).as_str());
+            false
+        }
+        Err(_) => {
+            Logger::error(format!("{} did not stop within {:?}", name, timeout).as_str());
+            false
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use super::{ListingSource, Source, SourceFuture};
+use crate::flats::{Category, Flat, FlatCriteria, FlatDetails, Series};
+use crate::health::health;
+use crate::i18n::Language;
+use crate::logger::Logger;
+use crate::metrics::metrics;
+use log::Level;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::PoisonError;
+use std::time::Instant;
+
+/// Listing pages are served by the website, the data by its JSON API.
+const WEB_URL: &str = "https://www.city24.lv";
+/// city24 covers the Baltics, `2` is the country code of Latvia.
+const LATVIA_COUNTRY_CODE: &str = "2";
+const ITEMS_PER_PAGE: u32 = 50;
+/// Upper bound of pages crawled per search, city24 sorts the newest listings first.
+const MAX_SEARCH_PAGES: u32 = 10;
+
+/// city24.lv deal types with the ss.com deal type names they correspond to.
+const DEAL_TYPES: [(&str, [&str; 2]); 2] = [
+    ("sale", ["pārdod", "продают"]),
+    ("rent", ["izīrē", "сдают"]),
+];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Realty {
+    friendly_id: String,
+    price: Option<f64>,
+    property_size: Option<f64>,
+    room_count: Option<u32>,
+    address: Address,
+    attributes: HashMap<String, serde_json::Value>,
+    main_image: Option<Image>,
+    description: String,
+    images: Vec<Image>,
+    date_published: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Address {
+    street_name: String,
+    house_number: String,
+    city_name: String,
+    district_name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Image {
+    url: String,
+}
+
+/// A city or district found by the address lookup.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct AddressMatch {
+    id: u64,
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// city24.lv listings through the portal's public JSON API.
+pub struct City24Parser {
+    api_url: String,
+    language: Language,
+    request_client: Client,
+    /// Address ids by kind and lowercased name, `None` for names city24 does not know.
+    address_ids: std::sync::Mutex<HashMap<(&'static str, String), Option<u64>>>,
+}
+
+impl City24Parser {
+    pub fn new(api_url: String, language: Language) -> Self {
+        Self {
+            api_url,
+            language,
+            request_client: Client::new(),
+            address_ids: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn locale(&self) -> &'static str {
+        match self.language {
+            Language::Ru => "ru_RU",
+            Language::Lv | Language::En => "lv_LV",
+        }
+    }
+
+    fn unit_type(category: Category) -> &'static str {
+        match category {
+            Category::Flats => "Apartment",
+            Category::Houses => "House",
+            Category::Land => "Land",
+            Category::Premises => "Commercial",
+            Category::Garages => "Garage",
+        }
+    }
+
+    /// Deal type name as ss.com shows it in the scrape language.
+    fn deal_type_name(&self, transaction_type: &str) -> String {
+        DEAL_TYPES
+            .iter()
+            .find(|(code, _)| *code == transaction_type)
+            .map(|(_, names)| match self.language {
+                Language::Ru => names[1],
+                Language::Lv | Language::En => names[0],
+            })
+            .unwrap_or(transaction_type)
+            .to_string()
+    }
+
+    /// city24 deal types matching the selected ss.com ones, every deal type when none is selected.
+    fn transaction_types(deal_types: &HashSet<String>) -> Vec<&'static str> {
+        DEAL_TYPES
+            .iter()
+            .filter(|(_, names)| {
+                deal_types.is_empty()
+                    || deal_types.iter().any(|deal_type| {
+                        let deal_type = deal_type.to_lowercase();
+                        names.iter().any(|name| deal_type.contains(name))
+                    })
+            })
+            .map(|(code, _)| *code)
+            .collect()
+    }
+
+    async fn fetch_json<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, anyhow::Error> {
+        let metrics = metrics();
+        let _timer = metrics.scrape_duration.start_timer();
+        let started_at = Instant::now();
+        let full_url = format!("{}/{}/{}", self.api_url, self.locale(), path);
+        let res = match self.request_client.get(&full_url).query(query).send().await {
+            Ok(res) => res,
+            Err(error) => {
+                metrics.http_errors.with_label_values(&["network"]).inc();
+                return Err(error.into());
+            }
+        };
+        if !res.status().is_success() {
+            metrics
+                .http_errors
+                .with_label_values(&[res.status().as_str()])
+                .inc();
+            Logger::log(
+                Level::Warn,
+                "Page request failed",
+                &[
+                    ("url", &full_url),
+                    ("status", &res.status().as_u16()),
+                    ("duration_ms", &started_at.elapsed().as_millis()),
+                ],
+            );
+            return Err(anyhow::anyhow!(
+                "Failed to get successful response from {}",
+                full_url
+            ));
+        }
+        let res = res.json::<T>().await?;
+        metrics
+            .pages_fetched
+            .with_label_values(&[Source::City24.code()])
+            .inc();
+        health().record_scrape();
+        Logger::log(
+            Level::Debug,
+            "Page fetched",
+            &[
+                ("url", &full_url),
+                ("duration_ms", &started_at.elapsed().as_millis()),
+            ],
+        );
+        Ok(res)
+    }
+
+    /// Id of a city or district (`kind`) named as on ss.com, looked up once per name.
+    async fn address_id(
+        &self,
+        kind: &'static str,
+        name: &str,
+    ) -> Result<Option<u64>, anyhow::Error> {
+        let key = (kind, name.trim().to_lowercase());
+        let known = self
+            .address_ids
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&key)
+            .copied();
+        if let Some(id) = known {
+            return Ok(id);
+        }
+        let query = [
+            ("cc", LATVIA_COUNTRY_CODE.to_string()),
+            ("q", name.trim().to_string()),
+        ];
+        let id = self
+            .fetch_json::<Vec<AddressMatch>>("addresses", &query)
+            .await?
+            .into_iter()
+            .find(|address| address.kind == kind && address.name.to_lowercase() == key.1)
+            .map(|address| address.id);
+        self.address_ids
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, id);
+        Ok(id)
+    }
+
+    /// Search filters narrowing the results to the city and districts of `criteria`. Fails
+    /// when city24 does not know the city; districts it does not know are filtered by name
+    /// afterwards instead.
+    async fn address_filters(
+        &self,
+        criteria: &FlatCriteria,
+    ) -> Result<Vec<(&'static str, String)>, anyhow::Error> {
+        let Some(city_id) = self.address_id("city", &criteria.city).await? else {
+            return Err(anyhow::anyhow!("city24 has no city '{}'", criteria.city));
+        };
+        let mut filters = vec![("address[city][]", city_id.to_string())];
+        let mut district_filters = Vec::new();
+        for district in &criteria.districts {
+            let Some(district_id) = self.address_id("district", district).await? else {
+                return Ok(filters);
+            };
+            district_filters.push(("address[district][]", district_id.to_string()));
+        }
+        filters.extend(district_filters);
+        Ok(filters)
+    }
+
+    /// Crawls search result pages until a short page or [`MAX_SEARCH_PAGES`].
+    async fn fetch_realties(
+        &self,
+        category: Category,
+        transaction_type: &str,
+        filters: &[(&'static str, String)],
+    ) -> Result<Vec<Realty>, anyhow::Error> {
+        let mut realties = Vec::new();
+        for page in 1..=MAX_SEARCH_PAGES {
+            let mut query = vec![
+                ("address[cc]", LATVIA_COUNTRY_CODE.to_string()),
+                ("tsType", transaction_type.to_string()),
+                ("unitType", Self::unit_type(category).to_string()),
+                ("itemsPerPage", ITEMS_PER_PAGE.to_string()),
+                ("page", page.to_string()),
+            ];
+            query.extend_from_slice(filters);
+            let page_realties = self
+                .fetch_json::<Vec<Realty>>("search/realties", &query)
+                .await?;
+            let is_last_page = page_realties.len() < ITEMS_PER_PAGE as usize;
+            realties.extend(page_realties);
+            if is_last_page {
+                break;
+            }
+        }
+        Ok(realties)
+    }
+
+    fn listing_url(friendly_id: &str) -> String {
+        format!("{}/real-estate/{}", WEB_URL, friendly_id)
+    }
+
+    fn attribute(realty: &Realty, name: &str) -> Option<String> {
+        match realty.attributes.get(name)? {
+            serde_json::Value::String(value) => Some(value.clone()),
+            serde_json::Value::Number(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    fn to_flat(realty: Realty, category: Category, deal_type: String) -> Flat {
+        let number = |name: &str| {
+            Self::attribute(&realty, name)
+                .and_then(|value| value.parse::<u32>().ok())
+                .unwrap_or_default()
+        };
+        let (floor, total_floors) = (number("FLOOR"), number("TOTAL_FLOORS"));
+        let street_name = [realty.address.street_name, realty.address.house_number]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        Flat {
+            street_name,
+            price: realty
+                .price
+                .map(|price| format!("{} €", price.round() as u64))
+                .unwrap_or_default(),
+            url: Self::listing_url(&realty.friendly_id),
+            image_url: realty.main_image.map(|image| image.url).unwrap_or_default(),
+            rooms: realty.room_count.unwrap_or_default(),
+            square_meters: realty.property_size.unwrap_or_default().round() as u32,
+            floor,
+            total_floors,
+            series: Series::default(),
+            summary: realty.description,
+            city: realty.address.city_name,
+            district: realty.address.district_name,
+            deal_type,
+            location: None,
+            deal: None,
+            repost: None,
+            category,
+            source: Source::City24,
+            fields: BTreeMap::new(),
+        }
+    }
+}
+
+impl ListingSource for City24Parser {
+    fn source(&self) -> Source {
+        Source::City24
+    }
+
+    fn owns_url(&self, url: &str) -> bool {
+        url.starts_with(WEB_URL)
+    }
+
+    /// Matches the ss.com city and district names case-insensitively against the listing address.
+    fn search<'a>(&'a self, criteria: &'a FlatCriteria) -> SourceFuture<'a, Vec<Flat>> {
+        Box::pin(async move {
+            let city = criteria.city.to_lowercase();
+            let districts = criteria
+                .districts
+                .iter()
+                .map(|district| district.to_lowercase())
+                .collect::<HashSet<_>>();
+            let mut filters = self.address_filters(criteria).await?;
+            filters.push(("price[gte]", criteria.price_from.to_string()));
+            if criteria.price_to < u32::MAX {
+                filters.push(("price[lte]", criteria.price_to.to_string()));
+            }
+
+            let mut flats = Vec::new();
+            for transaction_type in Self::transaction_types(&criteria.deal_types) {
+                let realties = self
+                    .fetch_realties(criteria.category, transaction_type, &filters)
+                    .await?;
+                flats.extend(
+                    realties
+                        .into_iter()
+                        .filter(|realty| {
+                            realty.address.city_name.to_lowercase() == city
+                                && (districts.is_empty()
+                                    || districts
+                                        .contains(&realty.address.district_name.to_lowercase()))
+                        })
+                        .map(|realty| {
+                            Self::to_flat(
+                                realty,
+                                criteria.category,
+                                self.deal_type_name(transaction_type),
+                            )
+                        })
+                        .filter(|flat| criteria.matches(flat)),
+                );
+            }
+            Ok(flats)
+        })
+    }
+
+    fn fetch_details<'a>(&'a self, url: &'a str) -> SourceFuture<'a, FlatDetails> {
+        Box::pin(async move {
+            let Some(friendly_id) = url
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .filter(|id| !id.is_empty())
+            else {
+                return Err(anyhow::anyhow!("Not a city24 listing url: {}", url));
+            };
+            let realty = self
+                .fetch_json::<Realty>(&format!("realties/{}", friendly_id), &[])
+                .await?;
+            let attributes = realty
+                .attributes
+                .keys()
+                .filter_map(|name| Some((name.clone(), Self::attribute(&realty, name)?)))
+                .collect();
+            Ok(FlatDetails {
+                url: url.to_string(),
+                price: realty
+                    .price
+                    .map(|price| format!("{} €", price.round() as u64))
+                    .unwrap_or_default(),
+                description: realty.description,
+                attributes,
+                photo_urls: realty.images.into_iter().map(|image| image.url).collect(),
+                published: realty.date_published,
+            })
+        })
+    }
+}
@@ -0,0 +1,275 @@
+mod city24;
+mod ss;
+
+pub use city24::City24Parser;
+pub use ss::SsSource;
+
+use crate::flats::{Flat, FlatCriteria, FlatDetails};
+use crate::geo::Geocoder;
+use crate::logger::Logger;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Real estate portal a listing comes from.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    #[default]
+    Ss,
+    City24,
+}
+
+impl Source {
+    pub const ALL: [Source; 2] = [Source::Ss, Source::City24];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Source::Ss => "ss",
+            Source::City24 => "city24",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Source> {
+        let code = code.trim().to_lowercase();
+        Source::ALL
+            .into_iter()
+            .find(|source| source.code() == code || format!("{}.lv", source.code()) == code)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Ss => "ss.com",
+            Source::City24 => "city24.lv",
+        }
+    }
+}
+
+/// Boxed future returned by [`ListingSource`] methods, which keeps the trait object safe.
+pub type SourceFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, anyhow::Error>> + Send + 'a>>;
+
+/// A portal the bot can search and fetch listing details from.
+pub trait ListingSource: Send + Sync {
+    fn source(&self) -> Source;
+
+    /// Whether `url` is a listing of this portal.
+    fn owns_url(&self, url: &str) -> bool;
+
+    /// Listings matching `criteria`; city, district and deal type names are the ss.com ones.
+    fn search<'a>(&'a self, criteria: &'a FlatCriteria) -> SourceFuture<'a, Vec<Flat>>;
+
+    fn fetch_details<'a>(&'a self, url: &'a str) -> SourceFuture<'a, FlatDetails>;
+}
+
+/// Every configured portal, searched together for criteria targeting several of them.
+#[derive(Clone)]
+pub struct ListingSources {
+    sources: Arc<Vec<Box<dyn ListingSource>>>,
+    geocoder: Arc<Geocoder>,
+}
+
+impl ListingSources {
+    pub fn new(sources: Vec<Box<dyn ListingSource>>, geocoder: Arc<Geocoder>) -> Self {
+        Self {
+            sources: Arc::new(sources),
+            geocoder,
+        }
+    }
+
+    pub fn geocoder(&self) -> &Geocoder {
+        &self.geocoder
+    }
+
+    /// Searches every source of `criteria` and merges the results, listings posted on several
+    /// portals are kept once. Results are geocoded and narrowed to the criteria area. Fails
+    /// only when every source failed.
+    pub async fn search(&self, criteria: &FlatCriteria) -> Result<Vec<Flat>, anyhow::Error> {
+        let mut results: Vec<Vec<Flat>> = Vec::new();
+        let mut last_error = None;
+        for source in &criteria.sources {
+            let Some(listing_source) = self
+                .sources
+                .iter()
+                .find(|listing_source| listing_source.source() == *source)
+            else {
+                Logger::warn(format!("{} is not configured", source.name()).as_str());
+                continue;
+            };
+            match listing_source.search(criteria).await {
+                Ok(flats) => results.push(flats),
+                Err(error) => {
+                    Logger::error(
+                        format!("Failed to search {}: {}", source.name(), error).as_str(),
+                    );
+                    last_error = Some(error);
+                }
+            }
+        }
+        if let Some(error) = last_error.filter(|_| results.is_empty()) {
+            return Err(error);
+        }
+        if results.is_empty() {
+            return Err(anyhow::anyhow!(
+                "None of the selected portals is configured"
+            ));
+        }
+        let mut flats = Self::merge(results);
+        for flat in &mut flats {
+            flat.location = self.geocoder.locate(flat);
+        }
+        flats.retain(|flat| criteria.matches_area(flat));
+        Ok(flats)
+    }
+
+    /// [`Self::search`] narrowed by the keywords of `criteria` too, for one-off searches. The
+    /// poller checks keywords of new listings only, their details are fetched once.
+    pub async fn search_matching(
+        &self,
+        criteria: &FlatCriteria,
+    ) -> Result<Vec<Flat>, anyhow::Error> {
+        let flats = self.search(criteria).await?;
+        Ok(self.filter_by_text(criteria, flats).await)
+    }
+
+    /// Details of a listing on whichever portal its url belongs to.
+    pub async fn fetch_details(&self, url: &str) -> Result<FlatDetails, anyhow::Error> {
+        let Some(listing_source) = self
+            .sources
+            .iter()
+            .find(|listing_source| listing_source.owns_url(url))
+        else {
+            return Err(anyhow::anyhow!("No portal serves {}", url));
+        };
+        listing_source.fetch_details(url).await
+    }
+
+    /// Applies the keyword rules of `criteria` to the summary and the fetched description,
+    /// listings whose details cannot be fetched are judged by the summary alone.
+    pub async fn filter_by_text(&self, criteria: &FlatCriteria, flats: Vec<Flat>) -> Vec<Flat> {
+        if !criteria.has_keywords() {
+            return flats;
+        }
+        let mut matching = Vec::new();
+        for flat in flats {
+            let description = match self.fetch_details(&flat.url).await {
+                Ok(details) => details.description,
+                Err(error) => {
+                    Logger::error(
+                        format!("Failed to fetch details of {}: {}", flat.url, error).as_str(),
+                    );
+                    String::new()
+                }
+            };
+            if criteria.matches_text(&format!("{}\n{}", flat.summary, description)) {
+                matching.push(flat);
+            }
+        }
+        matching
+    }
+
+    /// Concatenates results in source order, dropping urls and listings seen earlier.
+    fn merge(results: Vec<Vec<Flat>>) -> Vec<Flat> {
+        let mut seen_urls: HashSet<String> = HashSet::new();
+        let mut seen_listings = HashSet::new();
+        results
+            .into_iter()
+            .flatten()
+            .filter(|flat| {
+                seen_urls.insert(flat.url.clone()) && seen_listings.insert(flat.dedup_key())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// A portal listing a flat per description, the description is on its details page.
+    struct FakeSource {
+        descriptions: Vec<&'static str>,
+    }
+
+    impl ListingSource for FakeSource {
+        fn source(&self) -> Source {
+            Source::Ss
+        }
+
+        fn owns_url(&self, _url: &str) -> bool {
+            true
+        }
+
+        fn search<'a>(&'a self, _criteria: &'a FlatCriteria) -> SourceFuture<'a, Vec<Flat>> {
+            let flats = self
+                .descriptions
+                .iter()
+                .enumerate()
+                .map(|(index, _)| Flat {
+                    url: format!("/msg/{}.html", index),
+                    street_name: format!("Brīvības {}", index + 1),
+                    ..Flat::default()
+                })
+                .collect();
+            Box::pin(async move { Ok(flats) })
+        }
+
+        fn fetch_details<'a>(&'a self, url: &'a str) -> SourceFuture<'a, FlatDetails> {
+            let index = url
+                .trim_start_matches("/msg/")
+                .trim_end_matches(".html")
+                .parse::<usize>()
+                .unwrap_or_default();
+            let details = FlatDetails {
+                url: url.to_string(),
+                price: String::new(),
+                description: self.descriptions[index].to_string(),
+                attributes: BTreeMap::new(),
+                photo_urls: Vec::new(),
+                published: None,
+            };
+            Box::pin(async move { Ok(details) })
+        }
+    }
+
+    #[tokio::test]
+    async fn search_matching_leaves_out_excluded_keywords() {
+        let listing_sources = ListingSources::new(
+            vec![Box::new(FakeSource {
+                descriptions: vec!["Pēc kapitālā remonta", "Pārdod dzīvokli bez remonta"],
+            })],
+            Arc::new(Geocoder::default()),
+        );
+        let criteria: FlatCriteria = serde_json::from_value(serde_json::json!({
+            "city": "Rīga",
+            "districts": ["Centrs"],
+            "deal_types": ["Pārdod"],
+            "price_from": 0,
+            "price_to": u32::MAX,
+            "exclude_keywords": ["bez remonta"],
+        }))
+        .expect("criteria are valid");
+
+        let flats = listing_sources
+            .search_matching(&criteria)
+            .await
+            .expect("the fake source never fails");
+        let urls = flats
+            .iter()
+            .map(|flat| flat.url.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(urls, ["/msg/0.html"]);
+        assert_eq!(
+            listing_sources
+                .search(&criteria)
+                .await
+                .map(|flats| flats.len())
+                .ok(),
+            Some(2)
+        );
+    }
+}
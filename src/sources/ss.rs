@@ -0,0 +1,39 @@
+use super::{ListingSource, Source, SourceFuture};
+use crate::flats::{Flat, FlatCriteria, FlatDetails, FlatsParser};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// ss.com listings through the parser shared with the catalog dialogs.
+pub struct SsSource {
+    flats_parser: Arc<Mutex<FlatsParser>>,
+    url_base: String,
+}
+
+impl SsSource {
+    /// `url_base` is the one the parser was created with, listing urls start with it.
+    pub fn new(flats_parser: Arc<Mutex<FlatsParser>>, url_base: String) -> Self {
+        Self {
+            flats_parser,
+            url_base,
+        }
+    }
+}
+
+impl ListingSource for SsSource {
+    fn source(&self) -> Source {
+        Source::Ss
+    }
+
+    /// Relative urls are ss.com paths.
+    fn owns_url(&self, url: &str) -> bool {
+        url.starts_with(&self.url_base) || url.starts_with('/')
+    }
+
+    fn search<'a>(&'a self, criteria: &'a FlatCriteria) -> SourceFuture<'a, Vec<Flat>> {
+        Box::pin(FlatsParser::search(&self.flats_parser, criteria))
+    }
+
+    fn fetch_details<'a>(&'a self, url: &'a str) -> SourceFuture<'a, FlatDetails> {
+        Box::pin(FlatsParser::fetch_details(&self.flats_parser, url))
+    }
+}
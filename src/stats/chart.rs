@@ -0,0 +1,113 @@
+use super::{DealTypeStats, MarketStats};
+use crate::logger::Logger;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+use plotters::prelude::*;
+use std::sync::LazyLock;
+
+const CHART_WIDTH: u32 = 800;
+const PANEL_HEIGHT: u32 = 320;
+/// Deal types charted, the least listed ones are only reported as text.
+const MAX_PANELS: usize = 2;
+/// Chart text is drawn with a system font resolved through fontconfig.
+const FONT_FAMILY: &str = "sans-serif";
+
+/// Whether the host has a font to label the chart with, slim containers often have none.
+static FONT_AVAILABLE: LazyLock<bool> = LazyLock::new(|| {
+    let available = (FONT_FAMILY, 20).into_font().box_size("€/m²").is_ok();
+    if !available {
+        Logger::warn("No sans-serif font installed, market stats are sent as text only");
+    }
+    available
+});
+
+/// PNG with the weekly median €/m² of every listed deal type, `None` without any prices or
+/// without a font to draw the labels with.
+pub fn render_chart(stats: &MarketStats) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    if !*FONT_AVAILABLE {
+        return Ok(None);
+    }
+    let deal_types = stats
+        .deal_types
+        .iter()
+        .filter(|deal_type| {
+            deal_type
+                .weeks
+                .iter()
+                .any(|week| week.median_per_square_meter.is_some())
+        })
+        .take(MAX_PANELS)
+        .collect::<Vec<_>>();
+    if deal_types.is_empty() {
+        return Ok(None);
+    }
+
+    let (width, height) = (CHART_WIDTH, PANEL_HEIGHT * deal_types.len() as u32);
+    let mut pixels = vec![0u8; (width * height * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, (width, height)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let panels = root.split_evenly((deal_types.len(), 1));
+        for (panel, deal_type) in panels.iter().zip(deal_types) {
+            let caption = format!(
+                "{}, {}: {}",
+                stats.city, stats.district, deal_type.deal_type
+            );
+            draw_panel(panel, &caption, deal_type)?;
+        }
+        root.present()?;
+    }
+
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(&pixels, width, height, ColorType::Rgb8)?;
+    Ok(Some(png))
+}
+
+fn draw_panel(
+    panel: &DrawingArea<BitMapBackend, plotters::coord::Shift>,
+    caption: &str,
+    deal_type: &DealTypeStats,
+) -> Result<(), anyhow::Error> {
+    let points = deal_type
+        .weeks
+        .iter()
+        .enumerate()
+        .filter_map(|(index, week)| Some((index as i32, week.median_per_square_meter?)))
+        .collect::<Vec<_>>();
+    let (min, max) = points
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(min, max), (_, price)| {
+            (min.min(*price), max.max(*price))
+        });
+    // keeps a flat line off the chart borders
+    let padding = ((max - min) * 0.1).max(max * 0.05).max(1.0);
+    let last_week = (deal_type.weeks.len() as i32 - 1).max(1);
+
+    let mut chart = ChartBuilder::on(panel)
+        .caption(caption, (FONT_FAMILY, 20))
+        .margin(12)
+        .margin_right(30)
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0..last_week, (min - padding)..(max + padding))?;
+    chart
+        .configure_mesh()
+        .x_labels(deal_type.weeks.len())
+        .x_label_formatter(&|index| {
+            deal_type
+                .weeks
+                .get(*index as usize)
+                .map(|week| week.start.format("%d.%m").to_string())
+                .unwrap_or_default()
+        })
+        .y_desc("€/m²")
+        .y_label_formatter(&|price| format!("{:.0}", price))
+        .draw()?;
+    chart.draw_series(LineSeries::new(points.clone(), BLUE.stroke_width(2)))?;
+    chart.draw_series(
+        points
+            .into_iter()
+            .map(|point| Circle::new(point, 4, BLUE.filled())),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use super::{median, price_per_square_meter, quantile};
+use crate::flats::Category;
+use crate::storage::StoredListing;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::collections::BTreeMap;
+
+/// Listings not seen for longer than this are no longer on the market.
+const MARKET_WINDOW_DAYS: i64 = 30;
+/// Weeks the price trend covers.
+const TREND_WEEKS: i64 = 8;
+/// Flats with this many rooms or more are counted together.
+const MAX_ROOMS: u32 = 4;
+
+/// Stored flats of a single district, one entry per deal type as sale and rent prices differ.
+#[derive(Debug, Clone)]
+pub struct MarketStats {
+    pub city: String,
+    pub district: String,
+    /// The most listed deal type first.
+    pub deal_types: Vec<DealTypeStats>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DealTypeStats {
+    pub deal_type: String,
+    /// Listings seen during the last 30 days.
+    pub listings: usize,
+    /// Of them first seen during the last week.
+    pub new_listings: usize,
+    /// First quartile, median and third quartile of the listed prices.
+    pub price_quartiles: Option<[f64; 3]>,
+    pub rooms: Vec<RoomStats>,
+    /// Oldest week first.
+    pub weeks: Vec<WeekStats>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RoomStats {
+    /// `4` stands for four rooms or more.
+    pub rooms: u32,
+    pub listings: usize,
+    pub median_per_square_meter: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct WeekStats {
+    pub start: NaiveDate,
+    /// Listings on the market at some point of the week.
+    pub listings: usize,
+    pub median_per_square_meter: Option<f64>,
+}
+
+impl MarketStats {
+    /// Statistics of the stored flats of a district as [`find_location`] resolves it, `None`
+    /// when none were seen there.
+    pub fn from_listings<'a>(
+        listings: impl IntoIterator<Item = &'a StoredListing>,
+        city: &str,
+        district: &str,
+        now: DateTime<Utc>,
+    ) -> Option<MarketStats> {
+        let mut by_deal_type: BTreeMap<&str, Vec<&StoredListing>> = BTreeMap::new();
+        for listing in listings {
+            let flat = &listing.flat;
+            if flat.category == Category::Flats && flat.city == city && flat.district == district {
+                by_deal_type
+                    .entry(flat.deal_type.as_str())
+                    .or_default()
+                    .push(listing);
+            }
+        }
+        if by_deal_type.is_empty() {
+            return None;
+        }
+
+        let mut deal_types = by_deal_type
+            .into_iter()
+            .map(|(deal_type, listings)| DealTypeStats::new(deal_type, &listings, now))
+            .filter(|stats| stats.listings > 0 || stats.weeks.iter().any(|week| week.listings > 0))
+            .collect::<Vec<_>>();
+        deal_types.sort_by_key(|stats| std::cmp::Reverse(stats.listings));
+        Some(MarketStats {
+            city: city.to_string(),
+            district: district.to_string(),
+            deal_types,
+        })
+    }
+}
+
+impl DealTypeStats {
+    fn new(deal_type: &str, listings: &[&StoredListing], now: DateTime<Utc>) -> DealTypeStats {
+        let on_market = listings
+            .iter()
+            .filter(|listing| listing.last_seen >= now - Duration::days(MARKET_WINDOW_DAYS))
+            .collect::<Vec<_>>();
+        let new_listings = on_market
+            .iter()
+            .filter(|listing| listing.first_seen >= now - Duration::weeks(1))
+            .count();
+
+        let mut prices = on_market
+            .iter()
+            .filter_map(|listing| listing.flat.price_value())
+            .map(f64::from)
+            .collect::<Vec<_>>();
+        prices.sort_by(f64::total_cmp);
+        let price_quartiles = match (
+            quantile(&prices, 0.25),
+            median(&prices),
+            quantile(&prices, 0.75),
+        ) {
+            (Some(first), Some(median), Some(third)) => Some([first, median, third]),
+            _ => None,
+        };
+
+        let mut by_rooms: BTreeMap<u32, Vec<f64>> = BTreeMap::new();
+        for listing in &on_market {
+            if let (rooms @ 1.., Some(price)) = (
+                listing.flat.rooms.min(MAX_ROOMS),
+                price_per_square_meter(&listing.flat),
+            ) {
+                by_rooms.entry(rooms).or_default().push(price);
+            }
+        }
+        let rooms = by_rooms
+            .into_iter()
+            .filter_map(|(rooms, mut prices)| {
+                prices.sort_by(f64::total_cmp);
+                Some(RoomStats {
+                    rooms,
+                    listings: prices.len(),
+                    median_per_square_meter: median(&prices)?,
+                })
+            })
+            .collect();
+
+        DealTypeStats {
+            deal_type: deal_type.to_string(),
+            listings: on_market.len(),
+            new_listings,
+            price_quartiles,
+            rooms,
+            weeks: Self::weeks(listings, now),
+        }
+    }
+
+    fn weeks(listings: &[&StoredListing], now: DateTime<Utc>) -> Vec<WeekStats> {
+        (0..TREND_WEEKS)
+            .rev()
+            .map(|weeks_ago| {
+                let end = now - Duration::weeks(weeks_ago);
+                let start = end - Duration::weeks(1);
+                let on_market = listings
+                    .iter()
+                    .filter(|listing| listing.first_seen < end && listing.last_seen >= start)
+                    .collect::<Vec<_>>();
+                let mut prices = on_market
+                    .iter()
+                    .filter_map(|listing| price_per_square_meter(&listing.flat))
+                    .collect::<Vec<_>>();
+                prices.sort_by(f64::total_cmp);
+                WeekStats {
+                    start: start.date_naive(),
+                    listings: on_market.len(),
+                    median_per_square_meter: median(&prices),
+                }
+            })
+            .collect()
+    }
+
+    /// Change of the weekly median €/m² between the first and the last week with listings.
+    pub fn trend_percent(&self) -> Option<f64> {
+        let mut medians = self
+            .weeks
+            .iter()
+            .filter_map(|week| week.median_per_square_meter);
+        let first = medians.next()?;
+        let last = medians.next_back()?;
+        Some((last / first - 1.0) * 100.0)
+    }
+}
+
+/// Resolves a query like `rīga centrs` or `Rīga, Centrs` to the city and district names of
+/// a stored flat; both may contain spaces, so the whole query is compared.
+pub fn find_location<'a>(
+    listings: impl IntoIterator<Item = &'a StoredListing>,
+    query: &str,
+) -> Option<(String, String)> {
+    let normalize = |text: &str| {
+        text.replace(',', " ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    };
+    let query = normalize(query);
+    listings
+        .into_iter()
+        .map(|listing| &listing.flat)
+        .filter(|flat| flat.category == Category::Flats && !flat.district.is_empty())
+        .find(|flat| normalize(&format!("{} {}", flat.city, flat.district)) == query)
+        .map(|flat| (flat.city.clone(), flat.district.clone()))
+}
@@ -0,0 +1,239 @@
+mod chart;
+mod market;
+
+pub use chart::render_chart;
+pub use market::{find_location, DealTypeStats, MarketStats, RoomStats, WeekStats};
+
+use crate::flats::{Category, Flat, Series};
+use crate::storage::StoredListing;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Listings not seen for longer than this no longer describe the market.
+const BASELINE_WINDOW_DAYS: i64 = 90;
+/// A median of fewer listings is too noisy to score against.
+const MIN_SAMPLES: usize = 5;
+
+/// Which listings a median was taken from, from the most to the least similar ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Baseline {
+    /// Same district, deal type, series and room count.
+    SeriesAndRooms,
+    /// Same district, deal type and room count.
+    Rooms,
+    /// Same district and deal type.
+    District,
+}
+
+impl Baseline {
+    const ALL: [Baseline; 3] = [
+        Baseline::SeriesAndRooms,
+        Baseline::Rooms,
+        Baseline::District,
+    ];
+}
+
+/// Price per square meter of a listing compared to the median of similar ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DealScore {
+    pub price_per_square_meter: f64,
+    pub median_per_square_meter: f64,
+    /// Positive when cheaper than the median, e.g. `12.5` for 12.5% below it.
+    pub percent_below_median: f64,
+    pub samples: usize,
+    pub baseline: Baseline,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct GroupKey {
+    baseline: Baseline,
+    category: Category,
+    city: String,
+    district: String,
+    deal_type: String,
+    series: Option<Series>,
+    rooms: Option<u32>,
+}
+
+impl GroupKey {
+    fn of(flat: &Flat, baseline: Baseline) -> GroupKey {
+        GroupKey {
+            baseline,
+            category: flat.category,
+            city: flat.city.clone(),
+            district: flat.district.clone(),
+            deal_type: flat.deal_type.clone(),
+            series: (baseline == Baseline::SeriesAndRooms).then(|| flat.series.clone()),
+            rooms: (baseline != Baseline::District).then_some(flat.rooms),
+        }
+    }
+}
+
+/// Sorted €/m² of recently seen stored listings, grouped on every [`Baseline`] level.
+pub struct PriceBaselines {
+    groups: HashMap<GroupKey, Vec<f64>>,
+}
+
+impl PriceBaselines {
+    pub fn from_listings<'a>(
+        listings: impl IntoIterator<Item = &'a StoredListing>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let oldest = now - Duration::days(BASELINE_WINDOW_DAYS);
+        let mut groups: HashMap<GroupKey, Vec<f64>> = HashMap::new();
+        for listing in listings {
+            // listings stored before the district was recorded cannot be grouped
+            if listing.last_seen < oldest || listing.flat.district.is_empty() {
+                continue;
+            }
+            let Some(price_per_square_meter) = price_per_square_meter(&listing.flat) else {
+                continue;
+            };
+            for baseline in Baseline::ALL {
+                groups
+                    .entry(GroupKey::of(&listing.flat, baseline))
+                    .or_default()
+                    .push(price_per_square_meter);
+            }
+        }
+        for prices in groups.values_mut() {
+            prices.sort_by(f64::total_cmp);
+        }
+        Self { groups }
+    }
+
+    /// Scores against the most similar listings there are enough of, `None` without any.
+    pub fn score(&self, flat: &Flat) -> Option<DealScore> {
+        let price_per_square_meter = price_per_square_meter(flat)?;
+        let (baseline, prices) = Baseline::ALL.into_iter().find_map(|baseline| {
+            let prices = self.groups.get(&GroupKey::of(flat, baseline))?;
+            (prices.len() >= MIN_SAMPLES).then_some((baseline, prices))
+        })?;
+        let median_per_square_meter = median(prices)?;
+        Some(DealScore {
+            price_per_square_meter,
+            median_per_square_meter,
+            percent_below_median: (1.0 - price_per_square_meter / median_per_square_meter) * 100.0,
+            samples: prices.len(),
+            baseline,
+        })
+    }
+}
+
+/// Listed price divided by the area, `None` when either is missing.
+pub fn price_per_square_meter(flat: &Flat) -> Option<f64> {
+    let price = flat.price_value()?;
+    (flat.square_meters > 0 && price > 0).then(|| price as f64 / flat.square_meters as f64)
+}
+
+/// Median of sorted values, `None` when there are none.
+pub fn median(sorted: &[f64]) -> Option<f64> {
+    quantile(sorted, 0.5)
+}
+
+/// Linearly interpolated quantile of sorted values, e.g. `0.25` for the first quartile.
+pub fn quantile(sorted: &[f64], q: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let position = q.clamp(0.0, 1.0) * last as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(series: Series, rooms: u32, square_meters: u32, price: u32) -> Flat {
+        Flat {
+            city: String::from("Rīga"),
+            district: String::from("Centrs"),
+            deal_type: String::from("Pārdod"),
+            series,
+            rooms,
+            square_meters,
+            price: format!("{} €", price),
+            ..Flat::default()
+        }
+    }
+
+    fn listing(flat: Flat, last_seen: DateTime<Utc>) -> StoredListing {
+        StoredListing {
+            flat,
+            first_seen: last_seen,
+            last_seen,
+            price_history: Vec::new(),
+            photo_hashes: None,
+        }
+    }
+
+    #[test]
+    fn median_interpolates_even_counts() {
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&[7.0]), Some(7.0));
+        assert_eq!(median(&[1.0, 2.0, 10.0]), Some(2.0));
+        assert_eq!(median(&[1.0, 2.0, 4.0, 10.0]), Some(3.0));
+    }
+
+    #[test]
+    fn quantile_interpolates_between_values() {
+        let sorted = [10.0, 20.0, 30.0, 40.0, 50.0];
+        assert_eq!(quantile(&sorted, 0.0), Some(10.0));
+        assert_eq!(quantile(&sorted, 0.25), Some(20.0));
+        assert_eq!(quantile(&sorted, 0.75), Some(40.0));
+        assert_eq!(quantile(&sorted, 1.0), Some(50.0));
+        assert_eq!(quantile(&[10.0, 20.0], 0.25), Some(12.5));
+        assert_eq!(quantile(&sorted, 2.0), Some(50.0));
+        assert_eq!(quantile(&[], 0.5), None);
+    }
+
+    #[test]
+    fn score_uses_the_most_similar_baseline_with_enough_samples() {
+        let now = Utc::now();
+        // five 2-room 119. series flats at 1000-1400 €/m², five 3-room ones at 2000 €/m²
+        let mut listings = (0..5)
+            .map(|step| listing(flat(Series::S119, 2, 50, 50000 + step * 5000), now))
+            .collect::<Vec<_>>();
+        listings.extend((0..5).map(|_| listing(flat(Series::S602, 3, 50, 100000), now)));
+        let baselines = PriceBaselines::from_listings(&listings, now);
+
+        let score = baselines
+            .score(&flat(Series::S119, 2, 50, 54000))
+            .expect("enough samples");
+        assert_eq!(score.baseline, Baseline::SeriesAndRooms);
+        assert_eq!(score.samples, 5);
+        assert_eq!(score.median_per_square_meter, 1200.0);
+        assert_eq!(score.price_per_square_meter, 1080.0);
+        assert!((score.percent_below_median - 10.0).abs() < 1e-9);
+
+        // no other 2-room 602. flats, only the room count matches
+        let score = baselines
+            .score(&flat(Series::S602, 2, 50, 60000))
+            .expect("enough samples");
+        assert_eq!(score.baseline, Baseline::Rooms);
+
+        // no other 1-room flats, the whole district is the baseline
+        let score = baselines
+            .score(&flat(Series::S119, 1, 25, 50000))
+            .expect("enough samples");
+        assert_eq!(score.baseline, Baseline::District);
+        assert_eq!(score.samples, 10);
+        assert!(score.percent_below_median < 0.0);
+    }
+
+    #[test]
+    fn score_ignores_old_and_unpriced_listings() {
+        let now = Utc::now();
+        let old = now - Duration::days(BASELINE_WINDOW_DAYS + 1);
+        let mut listings = (0..5)
+            .map(|_| listing(flat(Series::S119, 2, 50, 50000), old))
+            .collect::<Vec<_>>();
+        listings.extend((0..5).map(|_| listing(flat(Series::S119, 2, 0, 50000), now)));
+        let baselines = PriceBaselines::from_listings(&listings, now);
+        assert!(baselines.score(&flat(Series::S119, 2, 50, 50000)).is_none());
+        assert!(PriceBaselines::from_listings(&[], now)
+            .score(&flat(Series::S119, 2, 0, 50000))
+            .is_none());
+    }
+}
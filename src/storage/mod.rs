@@ -0,0 +1,347 @@
+use crate::flats::{Category, Flat, FlatCriteria};
+use crate::notifications::ChatSettings;
+use crate::photos::{self, PhotoHash, Repost};
+use crate::subscriptions::Subscription;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageData {
+    pub chats: HashMap<i64, ChatSettings>,
+    pub subscriptions: Vec<Subscription>,
+    pub next_subscription_id: u64,
+    /// Every listing seen while polling, keyed by url.
+    pub listings: HashMap<String, StoredListing>,
+    /// Chats ignored by the bot, set with the admin `/ban` command.
+    pub banned_chats: HashSet<i64>,
+    /// Set with the admin `/pause_polling` command, digests are still delivered.
+    pub polling_paused: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricePoint {
+    pub seen_at: DateTime<Utc>,
+    pub price: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredListing {
+    pub flat: Flat,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// A new point is added only when the listed price changes.
+    pub price_history: Vec<PricePoint>,
+    /// `None` until the photos were hashed, see [`PhotoHasher`](crate::photos::PhotoHasher).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub photo_hashes: Option<Vec<PhotoHash>>,
+}
+
+/// Bot state persisted as a single JSON document.
+pub struct Storage {
+    path: PathBuf,
+    pub data: StorageData,
+    /// Urls of the hashed listings by category and city, the candidates of
+    /// [`Storage::find_repost`].
+    hashed_listings: HashMap<(Category, String), Vec<String>>,
+}
+
+impl Storage {
+    /// Loads the storage file, starting with empty data when it does not exist yet.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref().to_path_buf();
+        let data = if path.exists() {
+            let raw_json = fs::read_to_string(&path)?;
+            serde_json::from_str(&raw_json).map_err(|error| {
+                anyhow::anyhow!("Failed to parse storage file {:?}: {}", path, error)
+            })?
+        } else {
+            StorageData::default()
+        };
+        let mut hashed_listings: HashMap<(Category, String), Vec<String>> = HashMap::new();
+        for (url, listing) in &data.listings {
+            if listing.photo_hashes.is_some() {
+                hashed_listings
+                    .entry((listing.flat.category, listing.flat.city.clone()))
+                    .or_default()
+                    .push(url.clone());
+            }
+        }
+        Ok(Self {
+            path,
+            data,
+            hashed_listings,
+        })
+    }
+
+    /// Writes the data to a temporary file first so a crash never leaves a truncated file.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(&self.data)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// Verifies the storage directory is still writable, used by the health checks.
+    pub fn check_writable(&self) -> Result<(), anyhow::Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let probe_path = self.path.with_extension("json.probe");
+        fs::write(&probe_path, b"")?;
+        fs::remove_file(&probe_path)?;
+        Ok(())
+    }
+
+    pub fn chat_settings(&self, chat_id: i64) -> ChatSettings {
+        self.data.chats.get(&chat_id).cloned().unwrap_or_default()
+    }
+
+    pub fn chat_settings_mut(&mut self, chat_id: i64) -> &mut ChatSettings {
+        self.data.chats.entry(chat_id).or_default()
+    }
+
+    pub fn add_subscription(
+        &mut self,
+        chat_id: i64,
+        criteria: FlatCriteria,
+        seen_urls: HashSet<String>,
+    ) -> u64 {
+        self.data.next_subscription_id += 1;
+        let id = self.data.next_subscription_id;
+        self.data.subscriptions.push(Subscription {
+            id,
+            chat_id,
+            criteria,
+            seen_urls,
+            rejected_urls: HashSet::new(),
+            active: true,
+            min_discount: None,
+            feed_token: Subscription::new_feed_token(),
+            created_at: Utc::now(),
+        });
+        id
+    }
+
+    pub fn chat_subscriptions(&self, chat_id: i64) -> Vec<&Subscription> {
+        self.data
+            .subscriptions
+            .iter()
+            .filter(|subscription| subscription.chat_id == chat_id)
+            .collect()
+    }
+
+    pub fn chat_subscription_mut(&mut self, chat_id: i64, id: u64) -> Option<&mut Subscription> {
+        self.data
+            .subscriptions
+            .iter_mut()
+            .find(|subscription| subscription.chat_id == chat_id && subscription.id == id)
+    }
+
+    /// Pauses or resumes every subscription of a chat, returns how many were changed.
+    pub fn set_chat_subscriptions_active(&mut self, chat_id: i64, active: bool) -> usize {
+        let mut changed = 0;
+        for subscription in self.data.subscriptions.iter_mut() {
+            if subscription.chat_id == chat_id && subscription.active != active {
+                subscription.active = active;
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Every chat that changed a setting or saved a search.
+    pub fn known_chat_ids(&self) -> BTreeSet<i64> {
+        self.data
+            .chats
+            .keys()
+            .copied()
+            .chain(
+                self.data
+                    .subscriptions
+                    .iter()
+                    .map(|subscription| subscription.chat_id),
+            )
+            .collect()
+    }
+
+    pub fn is_banned(&self, chat_id: i64) -> bool {
+        self.data.banned_chats.contains(&chat_id)
+    }
+
+    /// Bans a chat and pauses its subscriptions, returns how many were paused.
+    pub fn ban_chat(&mut self, chat_id: i64) -> usize {
+        self.data.banned_chats.insert(chat_id);
+        self.set_chat_subscriptions_active(chat_id, false)
+    }
+
+    /// Lifts a ban and resumes the chat's subscriptions, returns how many were resumed or
+    /// `None` when the chat was not banned.
+    pub fn unban_chat(&mut self, chat_id: i64) -> Option<usize> {
+        if !self.data.banned_chats.remove(&chat_id) {
+            return None;
+        }
+        Some(self.set_chat_subscriptions_active(chat_id, true))
+    }
+
+    /// Upserts scraped listings, extending the price history when a price changed.
+    pub fn record_listings(&mut self, flats: &[Flat], seen_at: DateTime<Utc>) {
+        for flat in flats {
+            let listing = self
+                .data
+                .listings
+                .entry(flat.url.clone())
+                .or_insert_with(|| StoredListing {
+                    flat: flat.clone(),
+                    first_seen: seen_at,
+                    last_seen: seen_at,
+                    price_history: Vec::new(),
+                    photo_hashes: None,
+                });
+            let price_changed = listing
+                .price_history
+                .last()
+                .is_none_or(|point| point.price != flat.price);
+            if price_changed {
+                listing.price_history.push(PricePoint {
+                    seen_at,
+                    price: flat.price.clone(),
+                });
+            }
+            listing.flat = flat.clone();
+            listing.last_seen = seen_at;
+        }
+    }
+
+    pub fn photo_hashes(&self, url: &str) -> Option<&[PhotoHash]> {
+        self.data.listings.get(url)?.photo_hashes.as_deref()
+    }
+
+    pub fn set_photo_hashes(&mut self, url: &str, hashes: Vec<PhotoHash>) {
+        let Some(listing) = self.data.listings.get_mut(url) else {
+            return;
+        };
+        if listing.photo_hashes.replace(hashes).is_none() {
+            self.hashed_listings
+                .entry((listing.flat.category, listing.flat.city.clone()))
+                .or_default()
+                .push(url.to_string());
+        }
+    }
+
+    /// The first seen listing in the same category and city under another url with the same
+    /// photos.
+    pub fn find_repost(&self, flat: &Flat, hashes: &[PhotoHash]) -> Option<Repost> {
+        let candidates = self
+            .hashed_listings
+            .get(&(flat.category, flat.city.clone()))?;
+        candidates
+            .iter()
+            .filter(|other_url| **other_url != flat.url)
+            .filter_map(|other_url| Some((other_url, self.data.listings.get(other_url)?)))
+            .filter(|(_, listing)| {
+                listing
+                    .photo_hashes
+                    .as_deref()
+                    .is_some_and(|other_hashes| photos::same_photos(hashes, other_hashes))
+            })
+            .min_by_key(|(_, listing)| listing.first_seen)
+            .map(|(other_url, listing)| Repost {
+                url: other_url.clone(),
+                price: listing.flat.price.clone(),
+                first_seen: listing.first_seen,
+            })
+    }
+
+    /// Removes a subscription owned by the chat, returns `false` when there is none.
+    pub fn remove_subscription(&mut self, chat_id: i64, id: u64) -> bool {
+        let subscriptions_count = self.data.subscriptions.len();
+        self.data
+            .subscriptions
+            .retain(|subscription| !(subscription.chat_id == chat_id && subscription.id == id));
+        subscriptions_count != self.data.subscriptions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(url: &str, city: &str) -> Flat {
+        Flat {
+            url: url.to_string(),
+            city: city.to_string(),
+            price: String::from("85 000 €"),
+            ..Flat::default()
+        }
+    }
+
+    #[test]
+    fn find_repost_looks_in_the_same_city_only() {
+        let mut storage = empty_storage();
+        let photos = vec![
+            PhotoHash(0x0F0F_0F0F_0F0F_0F0F),
+            PhotoHash(0x00FF_00FF_00FF_00FF),
+        ];
+        let now = Utc::now();
+        let earlier = now - chrono::Duration::days(3);
+        storage.record_listings(&[flat("https://ss.com/a", "Rīga")], earlier);
+        storage.record_listings(&[flat("https://ss.com/b", "Jūrmala")], earlier);
+        storage.record_listings(&[flat("https://ss.com/c", "Rīga")], now);
+        for url in ["https://ss.com/a", "https://ss.com/b", "https://ss.com/c"] {
+            storage.set_photo_hashes(url, photos.clone());
+        }
+
+        let repost = storage
+            .find_repost(&flat("https://ss.com/c", "Rīga"), &photos)
+            .expect("a is the original");
+        assert_eq!(repost.url, "https://ss.com/a");
+        assert_eq!(repost.first_seen, earlier);
+        assert!(storage
+            .find_repost(&flat("https://ss.com/b", "Jūrmala"), &photos)
+            .is_none());
+        assert!(storage
+            .find_repost(
+                &flat("https://ss.com/d", "Rīga"),
+                &[PhotoHash(0x3333_3333_3333_3333)]
+            )
+            .is_none());
+    }
+
+    fn empty_storage() -> Storage {
+        Storage::load(std::env::temp_dir().join("flats-bot-missing.json"))
+            .expect("missing storage starts empty")
+    }
+
+    #[test]
+    fn unban_resumes_the_subscriptions_paused_by_the_ban() {
+        let mut storage = empty_storage();
+        let criteria: FlatCriteria = serde_json::from_value(serde_json::json!({
+            "city": "Rīga",
+            "districts": [],
+            "deal_types": [],
+            "price_from": 0,
+            "price_to": u32::MAX,
+        }))
+        .expect("criteria are valid");
+        storage.add_subscription(7, criteria.clone(), HashSet::new());
+        storage.add_subscription(7, criteria, HashSet::new());
+
+        assert_eq!(storage.unban_chat(7), None);
+        assert_eq!(storage.ban_chat(7), 2);
+        assert!(storage.is_banned(7));
+        assert_eq!(storage.unban_chat(7), Some(2));
+        assert!(!storage.is_banned(7));
+        assert!(storage
+            .data
+            .subscriptions
+            .iter()
+            .all(|subscription| subscription.active));
+    }
+}
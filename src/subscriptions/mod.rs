@@ -0,0 +1,352 @@
+use crate::flats::{Flat, FlatCriteria};
+use crate::logger::{LogContext, Logger};
+use crate::metrics::metrics;
+use crate::notifications::Notifier;
+use crate::photos::PhotoHasher;
+use crate::sources::ListingSources;
+use crate::stats::PriceBaselines;
+use crate::storage::Storage;
+use chrono::{DateTime, Utc};
+use log::Level;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// How often queued digests are checked, independent of the polling interval.
+const DIGEST_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const FEED_TOKEN_LENGTH: usize = 32;
+/// Earlier listings hashed per poll, spreads the photo downloads of a large search over polls.
+const PHOTO_BACKFILL_PER_POLL: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: u64,
+    pub chat_id: i64,
+    pub criteria: FlatCriteria,
+    /// Listing urls already reported to the chat.
+    pub seen_urls: HashSet<String>,
+    /// New listings left out by keywords or the minimal discount, they are not checked again.
+    #[serde(default)]
+    pub rejected_urls: HashSet<String>,
+    /// Cleared when the chat blocks the bot, restored on the next `/start`.
+    #[serde(default = "Subscription::default_active")]
+    pub active: bool,
+    /// Only listings at least this many percent below the median €/m² are reported.
+    #[serde(default)]
+    pub min_discount: Option<u32>,
+    /// Secret part of the feed urls, subscriptions saved without one get a new token.
+    #[serde(default = "Subscription::new_feed_token")]
+    pub feed_token: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Subscription {
+    fn default_active() -> bool {
+        true
+    }
+
+    pub fn new_feed_token() -> String {
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(FEED_TOKEN_LENGTH)
+            .map(char::from)
+            .collect()
+    }
+}
+
+/// Periodically re-runs every saved search and hands new listings to the [`Notifier`].
+pub struct SubscriptionPoller {
+    listing_sources: ListingSources,
+    storage: Arc<Mutex<Storage>>,
+    notifier: Notifier,
+    /// Unset when repost detection is disabled.
+    photo_hasher: Option<PhotoHasher>,
+    poll_interval: Duration,
+    shutdown: CancellationToken,
+}
+
+impl SubscriptionPoller {
+    pub fn new(
+        listing_sources: ListingSources,
+        storage: Arc<Mutex<Storage>>,
+        notifier: Notifier,
+        photo_hasher: Option<PhotoHasher>,
+        poll_interval: Duration,
+        shutdown: CancellationToken,
+    ) -> Self {
+        Self {
+            listing_sources,
+            storage,
+            notifier,
+            photo_hasher,
+            poll_interval,
+            shutdown,
+        }
+    }
+
+    /// Polls until `shutdown` is cancelled, a running poll stops after its current subscription.
+    pub async fn run(&self) {
+        let mut poll_ticker = tokio::time::interval(self.poll_interval);
+        let mut digest_ticker = tokio::time::interval(DIGEST_CHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = self.shutdown.cancelled() => break,
+                _ = poll_ticker.tick() => self.poll_subscriptions().await,
+                _ = digest_ticker.tick() => {
+                    if let Err(error) = self.notifier.flush_digests().await {
+                        Logger::error(format!("Failed to flush digests: {}", error).as_str());
+                    }
+                }
+            }
+        }
+    }
+
+    /// One polling cycle, everything it logs shares a `poll-<n>` correlation id.
+    async fn poll_subscriptions(&self) {
+        let subscriptions = {
+            let storage = self.storage.lock().await;
+            if storage.data.polling_paused {
+                return;
+            }
+            storage.data.subscriptions.clone()
+        };
+        LogContext::correlated("poll")
+            .scope(async {
+                let started_at = Instant::now();
+                Logger::info("Polling cycle started");
+                for subscription in subscriptions {
+                    if self.shutdown.is_cancelled() {
+                        break;
+                    }
+                    if !subscription.active {
+                        continue;
+                    }
+                    LogContext::current()
+                        .with("subscription_id", subscription.id)
+                        .with("chat_id", subscription.chat_id)
+                        .scope(self.poll_subscription(subscription))
+                        .await;
+                }
+                Logger::log(
+                    Level::Info,
+                    "Polling cycle finished",
+                    &[("duration_ms", &started_at.elapsed().as_millis())],
+                );
+            })
+            .await;
+    }
+
+    async fn poll_subscription(&self, subscription: Subscription) {
+        let started_at = Instant::now();
+        let flats = match self.listing_sources.search(&subscription.criteria).await {
+            Ok(flats) => flats,
+            Err(error) => {
+                Logger::error(
+                    format!("Failed to poll subscription {}: {}", subscription.id, error).as_str(),
+                );
+                return;
+            }
+        };
+
+        metrics()
+            .subscription_listings
+            .with_label_values(&[subscription.id.to_string().as_str()])
+            .set(flats.len() as i64);
+
+        let new_flats = flats
+            .iter()
+            .filter(|flat| {
+                !subscription.seen_urls.contains(&flat.url)
+                    && !subscription.rejected_urls.contains(&flat.url)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        Logger::log(
+            Level::Info,
+            "Subscription polled",
+            &[
+                ("listings", &flats.len()),
+                ("new_listings", &new_flats.len()),
+                ("duration_ms", &started_at.elapsed().as_millis()),
+            ],
+        );
+
+        let baselines = {
+            let mut storage = self.storage.lock().await;
+            // taken before recording so new listings are not compared to themselves
+            let baselines =
+                PriceBaselines::from_listings(storage.data.listings.values(), Utc::now());
+            storage.record_listings(&flats, Utc::now());
+            if let Err(error) = storage.save() {
+                Logger::error(format!("Failed to save storage: {}", error).as_str());
+            }
+            // the subscription may have been removed while the search was running
+            if !storage
+                .data
+                .subscriptions
+                .iter()
+                .any(|stored| stored.id == subscription.id)
+            {
+                metrics().forget_subscription(subscription.id);
+                return;
+            }
+            baselines
+        };
+        let new_urls = new_flats
+            .iter()
+            .map(|flat| flat.url.clone())
+            .collect::<HashSet<_>>();
+        self.backfill_photo_hashes(&flats, &new_urls).await;
+        let new_flats = self
+            .listing_sources
+            .filter_by_text(&subscription.criteria, new_flats)
+            .await
+            .into_iter()
+            .map(|flat| Flat {
+                deal: baselines.score(&flat),
+                ..flat
+            })
+            .filter(|flat| {
+                subscription.min_discount.is_none_or(|min_discount| {
+                    flat.deal
+                        .as_ref()
+                        .is_some_and(|deal| deal.percent_below_median >= f64::from(min_discount))
+                })
+            })
+            .collect::<Vec<_>>();
+        let new_flats = self.mark_reposts(new_flats).await;
+
+        // only the delivered listings are seen, the feeds and exports list them
+        {
+            let mut storage = self.storage.lock().await;
+            let Some(stored) = storage
+                .data
+                .subscriptions
+                .iter_mut()
+                .find(|stored| stored.id == subscription.id)
+            else {
+                return;
+            };
+            let mut rejected_urls = new_urls;
+            for flat in &new_flats {
+                rejected_urls.remove(&flat.url);
+                stored.seen_urls.insert(flat.url.clone());
+            }
+            stored.rejected_urls.extend(rejected_urls);
+            if let Err(error) = storage.save() {
+                Logger::error(format!("Failed to save storage: {}", error).as_str());
+            }
+        }
+        if new_flats.is_empty() {
+            return;
+        }
+
+        Logger::info(
+            format!(
+                "Found {} new flats for subscription {}",
+                new_flats.len(),
+                subscription.id
+            )
+            .as_str(),
+        );
+        if let Err(error) = self.notifier.notify(subscription.chat_id, new_flats).await {
+            Logger::error(
+                format!("Failed to notify chat {}: {}", subscription.chat_id, error).as_str(),
+            );
+        }
+    }
+
+    /// Hashes the photos of listings recorded without them, e.g. the ones shown when the
+    /// subscription was created, so later reposts of them are recognised. New listings are
+    /// left to [`Self::mark_reposts`].
+    async fn backfill_photo_hashes(&self, flats: &[Flat], new_urls: &HashSet<String>) {
+        let Some(photo_hasher) = &self.photo_hasher else {
+            return;
+        };
+        let unhashed = {
+            let storage = self.storage.lock().await;
+            flats
+                .iter()
+                .filter(|flat| {
+                    !new_urls.contains(&flat.url) && storage.photo_hashes(&flat.url).is_none()
+                })
+                .take(PHOTO_BACKFILL_PER_POLL)
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        if unhashed.is_empty() {
+            return;
+        }
+        for flat in &unhashed {
+            // left unhashed on failure, a later poll retries
+            match photo_hasher.hash_listing(&self.listing_sources, flat).await {
+                Ok(hashes) => self
+                    .storage
+                    .lock()
+                    .await
+                    .set_photo_hashes(&flat.url, hashes),
+                Err(error) => Logger::warn(
+                    format!("Failed to hash the photos of {}: {}", flat.url, error).as_str(),
+                ),
+            }
+        }
+        if let Err(error) = self.storage.lock().await.save() {
+            Logger::error(format!("Failed to save storage: {}", error).as_str());
+        }
+    }
+
+    /// Sets [`Flat::repost`] from the photo hashes, hashing each listing once. Suspected
+    /// reposts are still delivered, the note lets the chat judge; listings whose photos could
+    /// not be hashed are delivered unmarked and hashed again by a later poll.
+    async fn mark_reposts(&self, flats: Vec<Flat>) -> Vec<Flat> {
+        let Some(photo_hasher) = &self.photo_hasher else {
+            return flats;
+        };
+        let mut marked = Vec::new();
+        for flat in flats {
+            let known_hashes = {
+                let storage = self.storage.lock().await;
+                storage.photo_hashes(&flat.url).map(<[_]>::to_vec)
+            };
+            let hashes = match known_hashes {
+                Some(hashes) => hashes,
+                None => match photo_hasher
+                    .hash_listing(&self.listing_sources, &flat)
+                    .await
+                {
+                    Ok(hashes) => {
+                        self.storage
+                            .lock()
+                            .await
+                            .set_photo_hashes(&flat.url, hashes.clone());
+                        hashes
+                    }
+                    Err(error) => {
+                        Logger::warn(
+                            format!("Failed to hash the photos of {}: {}", flat.url, error)
+                                .as_str(),
+                        );
+                        marked.push(flat);
+                        continue;
+                    }
+                },
+            };
+            let repost = self.storage.lock().await.find_repost(&flat, &hashes);
+            if let Some(repost) = &repost {
+                Logger::info(
+                    format!("{} looks like a repost of {}", flat.url, repost.url).as_str(),
+                );
+            }
+            marked.push(Flat { repost, ..flat });
+        }
+        if let Err(error) = self.storage.lock().await.save() {
+            Logger::error(format!("Failed to save storage: {}", error).as_str());
+        }
+        marked
+    }
+}
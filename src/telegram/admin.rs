@@ -0,0 +1,289 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use super::{BotDependencies, HandlerResult};
+use crate::flats::FlatsParser;
+use crate::health::health;
+use crate::i18n::{Language, Text};
+use crate::logger::Logger;
+use crate::metrics::{metrics, Metrics};
+use chrono::{DateTime, Utc};
+use teloxide::{prelude::*, utils::command::BotCommands};
+
+/// Users listed per message, keeps `/users` below the Telegram message size limit.
+const USERS_PER_MESSAGE: usize = 50;
+
+/// Commands available only to the chats listed in `admin_chat_ids`.
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "snake_case", description = "Admin commands:")]
+pub enum AdminCommand {
+    #[command(description = "Users, subscriptions and scrape health.")]
+    Stats,
+    #[command(description = "Send a message to every user.")]
+    Broadcast(String),
+    #[command(description = "Reload cities, districts and deal types.")]
+    RefreshCatalog,
+    #[command(description = "Pause or resume polling of saved searches.")]
+    PausePolling,
+    #[command(description = "List users.")]
+    Users,
+    #[command(description = "Ban a chat by its id.")]
+    Ban(String),
+    #[command(description = "Lift the ban of a chat and resume its subscriptions.")]
+    Unban(String),
+}
+
+pub struct BotStats {
+    pub chats: usize,
+    pub banned_chats: usize,
+    pub subscriptions: usize,
+    pub active_subscriptions: usize,
+    pub listings: usize,
+    pub catalog_cities: usize,
+    pub last_successful_scrape: Option<DateTime<Utc>>,
+    pub pages_fetched: u64,
+    pub http_errors: u64,
+    pub parse_failures: u64,
+    pub polling_paused: bool,
+}
+
+pub struct UserSummary {
+    pub chat_id: i64,
+    pub language: Option<Language>,
+    pub subscriptions: usize,
+    pub active_subscriptions: usize,
+    pub banned: bool,
+}
+
+pub(super) fn is_admin(msg: Message, dependencies: Arc<BotDependencies>) -> bool {
+    dependencies.admin_chat_ids.contains(&msg.chat.id.0)
+}
+
+pub(super) async fn stats(
+    dependencies: Arc<BotDependencies>,
+    language: Language,
+    msg: Message,
+) -> HandlerResult {
+    let stats = {
+        let storage = dependencies.storage.lock().await;
+        let metrics = metrics();
+        BotStats {
+            chats: storage.known_chat_ids().len(),
+            banned_chats: storage.data.banned_chats.len(),
+            subscriptions: storage.data.subscriptions.len(),
+            active_subscriptions: storage
+                .data
+                .subscriptions
+                .iter()
+                .filter(|subscription| subscription.active)
+                .count(),
+            listings: storage.data.listings.len(),
+            catalog_cities: health().catalog_cities(),
+            last_successful_scrape: health().last_successful_scrape(),
+            pages_fetched: Metrics::total(&metrics.pages_fetched),
+            http_errors: Metrics::total(&metrics.http_errors),
+            parse_failures: Metrics::total(&metrics.parse_failures),
+            polling_paused: storage.data.polling_paused,
+        }
+    };
+    dependencies.message_queue.send(
+        msg.chat.id,
+        Text::AdminStats { stats: &stats }.render(language),
+    )?;
+    Ok(())
+}
+
+pub(super) async fn broadcast(
+    dependencies: Arc<BotDependencies>,
+    language: Language,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    let text = text.trim();
+    if text.is_empty() {
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::BroadcastUsage.render(language))?;
+        return Ok(());
+    }
+
+    let chat_ids = {
+        let storage = dependencies.storage.lock().await;
+        storage
+            .known_chat_ids()
+            .into_iter()
+            .filter(|chat_id| !storage.is_banned(*chat_id))
+            .collect::<Vec<_>>()
+    };
+    for chat_id in &chat_ids {
+        dependencies
+            .message_queue
+            .send(ChatId(*chat_id), text.to_string())?;
+    }
+    Logger::info(
+        format!(
+            "Admin {} broadcast a message to {} chats",
+            msg.chat.id,
+            chat_ids.len()
+        )
+        .as_str(),
+    );
+    dependencies.message_queue.send(
+        msg.chat.id,
+        Text::BroadcastQueued {
+            count: chat_ids.len(),
+        }
+        .render(language),
+    )?;
+    Ok(())
+}
+
+pub(super) async fn refresh_catalog(
+    dependencies: Arc<BotDependencies>,
+    language: Language,
+    msg: Message,
+) -> HandlerResult {
+    dependencies
+        .message_queue
+        .send(msg.chat.id, Text::CatalogRefreshing.render(language))?;
+    let text = match FlatsParser::refresh_catalogs(&dependencies.flats_parser).await {
+        Ok(()) => {
+            let cities: usize = {
+                let flats_parser = dependencies.flats_parser.lock().await;
+                flats_parser.catalogs.values().map(HashSet::len).sum()
+            };
+            Logger::info(format!("Catalog refreshed, {} cities", cities).as_str());
+            Text::CatalogRefreshed { cities }.render(language)
+        }
+        Err(error) => {
+            Logger::error(format!("Failed to refresh catalog: {}", error).as_str());
+            Text::CatalogRefreshFailed.render(language)
+        }
+    };
+    dependencies.message_queue.send(msg.chat.id, text)?;
+    Ok(())
+}
+
+pub(super) async fn pause_polling(
+    dependencies: Arc<BotDependencies>,
+    language: Language,
+    msg: Message,
+) -> HandlerResult {
+    let paused = {
+        let mut storage = dependencies.storage.lock().await;
+        storage.data.polling_paused = !storage.data.polling_paused;
+        storage.save()?;
+        storage.data.polling_paused
+    };
+    let text = if paused {
+        Text::PollingPaused
+    } else {
+        Text::PollingResumed
+    };
+    dependencies
+        .message_queue
+        .send(msg.chat.id, text.render(language))?;
+    Ok(())
+}
+
+pub(super) async fn users(
+    dependencies: Arc<BotDependencies>,
+    language: Language,
+    msg: Message,
+) -> HandlerResult {
+    let users = {
+        let storage = dependencies.storage.lock().await;
+        storage
+            .known_chat_ids()
+            .into_iter()
+            .map(|chat_id| {
+                let subscriptions = storage.chat_subscriptions(chat_id);
+                UserSummary {
+                    chat_id,
+                    language: storage.chat_settings(chat_id).language,
+                    subscriptions: subscriptions.len(),
+                    active_subscriptions: subscriptions
+                        .iter()
+                        .filter(|subscription| subscription.active)
+                        .count(),
+                    banned: storage.is_banned(chat_id),
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    if users.is_empty() {
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::Users { users: &[] }.render(language))?;
+        return Ok(());
+    }
+    for chunk in users.chunks(USERS_PER_MESSAGE) {
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::Users { users: chunk }.render(language))?;
+    }
+    Ok(())
+}
+
+pub(super) async fn ban(
+    dependencies: Arc<BotDependencies>,
+    language: Language,
+    chat_id: String,
+    msg: Message,
+) -> HandlerResult {
+    let Ok(chat_id) = chat_id.trim().parse::<i64>() else {
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::BanUsage.render(language))?;
+        return Ok(());
+    };
+    let paused = {
+        let mut storage = dependencies.storage.lock().await;
+        let paused = storage.ban_chat(chat_id);
+        storage.save()?;
+        paused
+    };
+    Logger::info(format!("Admin {} banned chat {}", msg.chat.id, chat_id).as_str());
+    dependencies.message_queue.send(
+        msg.chat.id,
+        Text::Banned {
+            chat_id,
+            subscriptions: paused,
+        }
+        .render(language),
+    )?;
+    Ok(())
+}
+
+pub(super) async fn unban(
+    dependencies: Arc<BotDependencies>,
+    language: Language,
+    chat_id: String,
+    msg: Message,
+) -> HandlerResult {
+    let Ok(chat_id) = chat_id.trim().parse::<i64>() else {
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::UnbanUsage.render(language))?;
+        return Ok(());
+    };
+    let resumed = {
+        let mut storage = dependencies.storage.lock().await;
+        let resumed = storage.unban_chat(chat_id);
+        storage.save()?;
+        resumed
+    };
+    let text = match resumed {
+        Some(resumed) => {
+            Logger::info(format!("Admin {} unbanned chat {}", msg.chat.id, chat_id).as_str());
+            Text::Unbanned {
+                chat_id,
+                subscriptions: resumed,
+            }
+            .render(language)
+        }
+        None => Text::NotBanned { chat_id }.render(language),
+    };
+    dependencies.message_queue.send(msg.chat.id, text)?;
+    Ok(())
+}
@@ -1,20 +1,55 @@
+pub mod admin;
+pub mod queue;
+
+use std::collections::{BTreeSet, HashSet};
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::asynchronous::tokio::runtime::AppRuntime;
-use crate::{flats::FlatsParser, logger::Logger};
+use crate::export::{self, ExportFormat};
+use crate::flats::{Category, City, FilterKind, FlatCriteria, FlatsParser, Series};
+use crate::geo::{self, Area, Coordinates, Geocoder, DISTRICT_MARGIN_KM};
+use crate::health::health;
+use crate::i18n::{Language, Text};
+use crate::logger::{LogContext, Logger};
+use crate::metrics::metrics;
+use crate::notifications::{DeliveryMode, QuietHours, FLATS_PER_MESSAGE};
+use crate::sources::{ListingSources, Source};
+use crate::stats::{self, MarketStats};
+use crate::storage::Storage;
+use admin::AdminCommand;
+use chrono::Utc;
 use dptree::case;
+use queue::MessageQueue;
 use teloxide::dispatching::{dialogue, UpdateHandler};
 use teloxide::{dispatching::dialogue::InMemStorage, prelude::*, utils::command::BotCommands};
 
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// How often to retry stopping a dispatcher that is still connecting to Telegram.
+const DISPATCHER_SHUTDOWN_RETRY: Duration = Duration::from_millis(200);
+/// Larger areas are better served by picking districts.
+const MAX_RADIUS_KM: f64 = 20.0;
+/// Stored listings not found by a poll for this long are left off the maps as taken down.
+const MAP_WINDOW_HOURS: i64 = 24;
 
 pub struct FlatsBotTelegram {
     pub flats_parser: Arc<Mutex<FlatsParser>>,
+    listing_sources: ListingSources,
+    storage: Arc<Mutex<Storage>>,
+    message_queue: MessageQueue,
+    admin_chat_ids: Vec<i64>,
     tokio_runtime: Arc<AppRuntime>,
     bot: Bot,
 }
 struct BotDependencies {
     flats_parser: Arc<Mutex<FlatsParser>>,
+    listing_sources: ListingSources,
+    storage: Arc<Mutex<Storage>>,
+    message_queue: MessageQueue,
+    admin_chat_ids: Vec<i64>,
 }
 
 type MyDialogue = Dialogue<State, InMemStorage<State>>;
@@ -24,20 +59,35 @@ type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 pub enum State {
     #[default]
     Start,
-    ReceiveCityName,
-    ReceiveDistrictName {
-        city_name: String, // please keep name and href
+    ReceiveCategory,
+    ReceiveCityName {
+        category: Category,
+    },
+    ReceiveDistrictNames {
+        category: Category,
+        city_name: String,
+    },
+    /// A location was shared instead of picking districts.
+    ReceiveRadius {
+        category: Category,
+        city_name: String,
+        center: Coordinates,
     },
-    ReceiveDealType {
-        city_name: String,     // please keep name and href
-        district_name: String, // please keep name and href
+    ReceiveDealTypes {
+        category: Category,
+        city_name: String,
+        district_names: Vec<String>,
+        area: Option<Area>,
     },
     ReceivePriceRange {
+        category: Category,
         city_name: String,
-        district_name: String,
-        deal_type: String,
+        district_names: Vec<String>,
+        deal_types: Vec<String>,
+        area: Option<Area>,
     },
 }
+
 #[derive(BotCommands, Clone)]
 #[command(
     rename_rule = "lowercase",
@@ -50,25 +100,62 @@ enum Command {
     Help,
     #[command(description = "Cancel the dialogue.")]
     Cancel,
+    #[command(description = "Change the language: lv, ru or en.")]
+    Language(String),
+    #[command(description = "List saved searches.")]
+    Subscriptions,
+    #[command(description = "Remove a saved search.")]
+    Unsubscribe(String),
+    #[command(description = "Set quiet hours, e.g. 23:00-07:00, or off.")]
+    Quiet(String),
+    #[command(description = "Set the timezone, e.g. Europe/Riga.")]
+    Timezone(String),
+    #[command(description = "Choose instant, hourly or daily notifications.")]
+    Digest(String),
+    #[command(description = "Export a saved search as a file.")]
+    Export(String),
+    #[command(description = "Show the feed links of a saved search.")]
+    Feed(String),
+    #[command(description = "Choose the portals of a saved search, e.g. 3 ss,city24.")]
+    Sources(String),
+    #[command(description = "Narrow a saved search, e.g. 3 rooms 2-3 or 3 floor off.")]
+    Filter(String),
+    #[command(
+        description = "Only notify on listings X% below the median price per m², e.g. 3 15."
+    )]
+    Deals(String),
+    #[command(description = "Prices of a district from stored listings, e.g. Rīga Centrs.")]
+    Market(String),
+    #[command(description = "Show the listings of a saved search on a map.")]
+    Map(String),
 }
 
 impl FlatsBotTelegram {
-    pub fn new(tokio_runtime: Arc<AppRuntime>, flats_parser: Arc<Mutex<FlatsParser>>) -> Self {
-        let bot = Bot::from_env();
+    pub fn new(
+        tokio_runtime: Arc<AppRuntime>,
+        bot: Bot,
+        flats_parser: Arc<Mutex<FlatsParser>>,
+        listing_sources: ListingSources,
+        storage: Arc<Mutex<Storage>>,
+        message_queue: MessageQueue,
+        admin_chat_ids: Vec<i64>,
+    ) -> Self {
         Self {
             tokio_runtime,
             flats_parser,
+            listing_sources,
+            storage,
+            message_queue,
+            admin_chat_ids,
             bot,
         }
     }
 
     pub fn init(&mut self) -> Result<(), anyhow::Error> {
-        let cities_parsing_res: Result<(), anyhow::Error> =
-            self.tokio_runtime.runtime.block_on(async {
-                let mut parser = self.flats_parser.lock().await;
-                parser.parse_global_data().await?;
-                Ok(())
-            });
+        let cities_parsing_res: Result<(), anyhow::Error> = self
+            .tokio_runtime
+            .runtime
+            .block_on(async { FlatsParser::refresh_catalogs(&self.flats_parser).await });
         cities_parsing_res?;
         Logger::info("Cities and districts parsed successfully");
         Ok(())
@@ -81,88 +168,300 @@ impl FlatsBotTelegram {
                     .branch(case![Command::Help].endpoint(Self::help_message))
                     .branch(case![Command::Start].endpoint(Self::start)),
             )
-            .branch(case![Command::Cancel].endpoint(Self::cancel));
+            .branch(case![Command::Cancel].endpoint(Self::cancel))
+            .branch(case![Command::Language(code)].endpoint(Self::change_language))
+            .branch(case![Command::Subscriptions].endpoint(Self::list_subscriptions))
+            .branch(case![Command::Unsubscribe(id)].endpoint(Self::unsubscribe))
+            .branch(case![Command::Quiet(range)].endpoint(Self::set_quiet_hours))
+            .branch(case![Command::Timezone(timezone)].endpoint(Self::set_timezone))
+            .branch(case![Command::Digest(mode)].endpoint(Self::set_delivery_mode))
+            .branch(case![Command::Export(args)].endpoint(Self::export_subscription))
+            .branch(case![Command::Feed(id)].endpoint(Self::feed_links))
+            .branch(case![Command::Sources(args)].endpoint(Self::set_sources))
+            .branch(case![Command::Filter(args)].endpoint(Self::set_filter))
+            .branch(case![Command::Deals(args)].endpoint(Self::set_min_discount))
+            .branch(case![Command::Market(location)].endpoint(Self::market_stats))
+            .branch(case![Command::Map(id)].endpoint(Self::subscription_map));
 
-        let message_handler = Update::filter_message()
+        let admin_command_handler = teloxide::filter_command::<AdminCommand, _>()
+            .filter(admin::is_admin)
+            .branch(case![AdminCommand::Stats].endpoint(admin::stats))
+            .branch(case![AdminCommand::Broadcast(text)].endpoint(admin::broadcast))
+            .branch(case![AdminCommand::RefreshCatalog].endpoint(admin::refresh_catalog))
+            .branch(case![AdminCommand::PausePolling].endpoint(admin::pause_polling))
+            .branch(case![AdminCommand::Users].endpoint(admin::users))
+            .branch(case![AdminCommand::Ban(chat_id)].endpoint(admin::ban))
+            .branch(case![AdminCommand::Unban(chat_id)].endpoint(admin::unban));
+
+        // admin commands come before the ban check, an admin who banned their own chat can
+        // still lift the ban
+        let user_handler = dptree::filter_async(Self::is_not_banned)
             .branch(command_handler)
-            .branch(dptree::case![State::ReceiveCityName].endpoint(Self::recieve_city_name))
+            .branch(dptree::case![State::ReceiveCategory].endpoint(Self::receive_category))
             .branch(
-                dptree::case![State::ReceiveDistrictName { city_name }]
-                    .endpoint(Self::receive_district_name),
+                dptree::case![State::ReceiveCityName { category }]
+                    .endpoint(Self::recieve_city_name),
             )
             .branch(
-                dptree::case![State::ReceiveDealType {
+                dptree::case![State::ReceiveDistrictNames {
+                    category,
+                    city_name
+                }]
+                .endpoint(Self::receive_district_names),
+            )
+            .branch(
+                dptree::case![State::ReceiveRadius {
+                    category,
+                    city_name,
+                    center
+                }]
+                .endpoint(Self::receive_radius),
+            )
+            .branch(
+                dptree::case![State::ReceiveDealTypes {
+                    category,
+                    city_name,
+                    district_names,
+                    area
+                }]
+                .endpoint(Self::receive_deal_types),
+            )
+            .branch(
+                dptree::case![State::ReceivePriceRange {
+                    category,
                     city_name,
-                    district_name
+                    district_names,
+                    deal_types,
+                    area
                 }]
-                .endpoint(Self::receive_deal_type),
+                .endpoint(Self::receive_price_range),
             )
             .branch(dptree::entry().endpoint(Self::unhandled_message));
 
-        dialogue::enter::<Update, InMemStorage<State>, State, _>().branch(message_handler)
+        let message_handler = Update::filter_message()
+            .map_async(Self::chat_language)
+            .branch(admin_command_handler)
+            .branch(user_handler);
+
+        dialogue::enter::<Update, InMemStorage<State>, State, _>()
+            .inspect(|| health().record_update())
+            .branch(message_handler)
     }
 
-    pub async fn run(&mut self) -> Result<(), anyhow::Error> {
+    /// Dispatches updates until `shutdown` is cancelled, then lets in-flight handlers finish.
+    pub async fn run(&mut self, shutdown: CancellationToken) -> Result<(), anyhow::Error> {
         let dependencies = Arc::new(BotDependencies {
             flats_parser: self.flats_parser.clone(),
+            listing_sources: self.listing_sources.clone(),
+            storage: self.storage.clone(),
+            message_queue: self.message_queue.clone(),
+            admin_chat_ids: self.admin_chat_ids.clone(),
         });
 
-        Dispatcher::builder(self.bot.clone(), self.create_schema())
+        let mut dispatcher = Dispatcher::builder(self.bot.clone(), self.create_schema())
             .dependencies(dptree::deps![dependencies, InMemStorage::<State>::new()])
-            .enable_ctrlc_handler()
-            .build()
-            .dispatch()
-            .await;
+            .build();
+        let shutdown_token = dispatcher.shutdown_token();
+
+        health().set_dispatcher_running(true);
+        // long polling wakes the dispatcher at least every 10 seconds even without updates,
+        // so a stale heartbeat means it is wedged
+        let mut inner_dispatch = std::pin::pin!(dispatcher.dispatch());
+        let dispatch = std::future::poll_fn(|context| {
+            health().record_dispatcher_heartbeat();
+            inner_dispatch.as_mut().poll(context)
+        });
+        tokio::pin!(dispatch);
+        tokio::select! {
+            _ = &mut dispatch => {}
+            _ = shutdown.cancelled() => {
+                // the token refuses to shut down a dispatcher that has not started yet
+                while shutdown_token.shutdown().is_err() {
+                    tokio::select! {
+                        _ = &mut dispatch => break,
+                        _ = tokio::time::sleep(DISPATCHER_SHUTDOWN_RETRY) => {}
+                    }
+                }
+                dispatch.await;
+            }
+        }
+        health().set_dispatcher_running(false);
 
         Ok(())
     }
 
-    async fn help_message(bot: Bot, msg: Message) -> HandlerResult {
-        let help_text = Command::descriptions();
-        bot.send_message(msg.chat.id, help_text.to_string()).await?;
+    /// Banned chats are ignored altogether.
+    async fn is_not_banned(msg: Message, dependencies: Arc<BotDependencies>) -> bool {
+        !dependencies.storage.lock().await.is_banned(msg.chat.id.0)
+    }
+
+    /// Splits a comma separated selection like `Centrs, Teika` into trimmed items.
+    fn parse_selection(text: &str) -> Vec<String> {
+        let mut items = text
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>();
+        items.sort();
+        items.dedup();
+        items
+    }
+
+    /// Language chosen with `/language`, otherwise derived from the user's Telegram settings
+    /// and saved on first contact so notifications use it too.
+    async fn chat_language(dependencies: Arc<BotDependencies>, msg: Message) -> Language {
+        let mut storage = dependencies.storage.lock().await;
+        if let Some(language) = storage.chat_settings(msg.chat.id.0).language {
+            return language;
+        }
+        let Some(detected) = msg
+            .from()
+            .and_then(|user| user.language_code.as_deref())
+            .and_then(Language::from_code)
+        else {
+            return Language::default();
+        };
+        storage.chat_settings_mut(msg.chat.id.0).language = Some(detected);
+        if let Err(error) = storage.save() {
+            Logger::error(format!("Failed to save storage: {}", error).as_str());
+        }
+        detected
+    }
+
+    async fn help_message(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        msg: Message,
+    ) -> HandlerResult {
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::Help.render(language))?;
+        Ok(())
+    }
+
+    async fn change_language(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        code: String,
+        msg: Message,
+    ) -> HandlerResult {
+        let Some(new_language) = Language::from_code(&code) else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::SelectLanguage.render(language))?;
+            return Ok(());
+        };
+        {
+            let mut storage = dependencies.storage.lock().await;
+            storage.chat_settings_mut(msg.chat.id.0).language = Some(new_language);
+            storage.save()?;
+        }
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::LanguageChanged.render(new_language))?;
         Ok(())
     }
 
     async fn start(
         dependencies: Arc<BotDependencies>,
-        bot: Bot,
+        language: Language,
+        dialogue: MyDialogue,
+        msg: Message,
+    ) -> HandlerResult {
+        {
+            // the chat is reachable again after having blocked the bot
+            let mut storage = dependencies.storage.lock().await;
+            if storage.set_chat_subscriptions_active(msg.chat.id.0, true) > 0 {
+                storage.save()?;
+            }
+        }
+        let categories = Category::ALL
+            .iter()
+            .map(|category| format!("• {}", category.name(language)))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        dependencies.message_queue.send(
+            msg.chat.id,
+            Text::SelectCategory {
+                categories: &categories,
+            }
+            .render(language),
+        )?;
+        dialogue.update(State::ReceiveCategory).await?;
+        Ok(())
+    }
+
+    async fn receive_category(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
         dialogue: MyDialogue,
         msg: Message,
     ) -> HandlerResult {
+        let Some(category) = msg.text().and_then(Category::from_name) else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::InvalidCategory.render(language))?;
+            return Ok(());
+        };
+
+        let loaded = {
+            let flats_parser = dependencies.flats_parser.lock().await;
+            flats_parser.is_category_loaded(category)
+        };
+        if !loaded {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::LoadingCatalog.render(language))?;
+        }
+        if let Err(error) =
+            FlatsParser::ensure_category_loaded(&dependencies.flats_parser, category).await
+        {
+            Logger::error(
+                format!("Failed to load {} catalog: {}", category.code(), error).as_str(),
+            );
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::SearchFailed.render(language))?;
+            return Ok(());
+        }
         let flats_parser = dependencies.flats_parser.lock().await;
-        let mut cities = flats_parser
-            .cities
+        let cities = flats_parser
+            .cities(category)
             .iter()
             .map(|city| format!("• {}", city.name))
-            .collect::<Vec<_>>();
-
-        cities.sort();
-        let cities = cities.join("\n");
+            .collect::<Vec<_>>()
+            .join("\n");
 
-        let greeting_message = format!("Let's start! Please select a city: \n\n{}", cities);
-        bot.send_message(msg.chat.id, greeting_message).await?;
-        dialogue.update(State::ReceiveCityName).await?;
+        dependencies.message_queue.send(
+            msg.chat.id,
+            Text::SelectCity { cities: &cities }.render(language),
+        )?;
+        dialogue.update(State::ReceiveCityName { category }).await?;
         Ok(())
     }
 
     async fn recieve_city_name(
         dependencies: Arc<BotDependencies>,
-        bot: Bot,
+        language: Language,
         dialogue: MyDialogue,
+        category: Category,
         msg: Message,
     ) -> HandlerResult {
         let Some(city_name): Option<&str> = msg.text() else {
-            bot.send_message(msg.chat.id, "Message should be a plain text").await?;
-            return Ok(())
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::PlainTextRequired.render(language))?;
+            return Ok(());
         };
         let flats_parser = dependencies.flats_parser.lock().await;
-        let city_info = flats_parser
-            .cities
-            .iter()
-            .find(|city| city.name.eq(city_name));
-        let Some(city_info) = city_info else {
-            bot.send_message(msg.chat.id, format!("City '{}' name is invalid. Please try again!", city_name)).await?;
-            return Ok(())
+        let Some(city_info) = flats_parser.find_city(category, city_name) else {
+            dependencies.message_queue.send(
+                msg.chat.id,
+                Text::InvalidCity { city: city_name }.render(language),
+            )?;
+            return Ok(());
         };
         let mut districts = city_info
             .districts
@@ -171,13 +470,16 @@ impl FlatsBotTelegram {
             .collect::<Vec<_>>();
         districts.sort();
         let districts = districts.join("\n");
-        bot.send_message(
+        dependencies.message_queue.send(
             msg.chat.id,
-            format!("Please select a district: \n\n{}", districts),
-        )
-        .await?;
+            Text::SelectDistricts {
+                districts: &districts,
+            }
+            .render(language),
+        )?;
         dialogue
-            .update(State::ReceiveDistrictName {
+            .update(State::ReceiveDistrictNames {
+                category,
                 city_name: city_name.into(),
             })
             .await?;
@@ -185,149 +487,1281 @@ impl FlatsBotTelegram {
         Ok(())
     }
 
-    async fn receive_district_name(
+    async fn receive_district_names(
         dependencies: Arc<BotDependencies>,
-        bot: Bot,
+        language: Language,
         dialogue: MyDialogue,
-        city_name: String,
+        (category, city_name): (Category, String),
         msg: Message,
     ) -> HandlerResult {
-        let Some(district_name): Option<&str> = msg.text() else {
-            bot.send_message(msg.chat.id, "Message should be a plain text").await?;
-            return Ok(())
+        if let Some(center) = Self::shared_location(&msg) {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::EnterRadius.render(language))?;
+            dialogue
+                .update(State::ReceiveRadius {
+                    category,
+                    city_name,
+                    center,
+                })
+                .await?;
+            return Ok(());
+        }
+        let Some(text): Option<&str> = msg.text() else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::PlainTextRequired.render(language))?;
+            return Ok(());
         };
-        // here i should scrape deal types
         let flats_parser = dependencies.flats_parser.lock().await;
-        let city_option = flats_parser
-            .cities
+        let Some(city) = flats_parser.find_city(category, &city_name) else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::CityNotFound.render(language))?;
+            return Ok(());
+        };
+
+        let is_whole_city = Language::ALL
+            .iter()
+            .any(|known| text.trim().to_lowercase() == known.whole_city_keyword());
+        let district_names = if is_whole_city {
+            Self::all_districts(city)
+        } else {
+            Self::parse_selection(text)
+        };
+
+        let unknown_districts = district_names
             .iter()
-            .find(|city| city.name.eq(&city_name));
+            .filter(|name| {
+                !city
+                    .districts
+                    .iter()
+                    .any(|district| district.name.eq(*name))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if district_names.is_empty() || !unknown_districts.is_empty() {
+            dependencies.message_queue.send(
+                msg.chat.id,
+                Text::DistrictNotFound {
+                    districts: &unknown_districts.join(", "),
+                }
+                .render(language),
+            )?;
+            return Ok(());
+        }
+
+        dependencies.message_queue.send(
+            msg.chat.id,
+            Text::SelectDealTypes {
+                deal_types: &Self::deal_type_options(city, &district_names),
+            }
+            .render(language),
+        )?;
+
+        dialogue
+            .update(State::ReceiveDealTypes {
+                category,
+                city_name,
+                district_names,
+                area: None,
+            })
+            .await?;
 
-        let Some(city) = city_option else {
-            bot.send_message(msg.chat.id, "City not found").await?;
+        Ok(())
+    }
+
+    /// Searches the districts that may reach into the area, the area decides which listings
+    /// match. A place no known district is near asks for the location or districts again.
+    async fn receive_radius(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        dialogue: MyDialogue,
+        (category, city_name, center): (Category, String, Coordinates),
+        msg: Message,
+    ) -> HandlerResult {
+        let Some(text): Option<&str> = msg.text() else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::PlainTextRequired.render(language))?;
+            return Ok(());
+        };
+        let radius_km = text
+            .trim()
+            .trim_end_matches("km")
+            .trim()
+            .replace(',', ".")
+            .parse::<f64>()
+            .ok()
+            .filter(|radius_km| *radius_km > 0.0 && *radius_km <= MAX_RADIUS_KM);
+        let Some(radius_km) = radius_km else {
+            dependencies.message_queue.send(
+                msg.chat.id,
+                Text::InvalidRadius {
+                    max_km: MAX_RADIUS_KM,
+                }
+                .render(language),
+            )?;
             return Ok(());
         };
 
-        let city = city.clone();
-        let Some(district) = city.districts.iter().find(|district| district.name.eq(district_name)) else {
-            bot.send_message(msg.chat.id, "District not found").await?;
-            return Ok(())
+        let flats_parser = dependencies.flats_parser.lock().await;
+        let Some(city) = flats_parser.find_city(category, &city_name) else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::CityNotFound.render(language))?;
+            return Ok(());
         };
+        let area = Area { center, radius_km };
+        let Some(district_names) =
+            Self::districts_near(dependencies.listing_sources.geocoder(), city, &area)
+        else {
+            dependencies.message_queue.send(
+                msg.chat.id,
+                Text::NoDistrictsNear { city: &city.name }.render(language),
+            )?;
+            dialogue
+                .update(State::ReceiveDistrictNames {
+                    category,
+                    city_name,
+                })
+                .await?;
+            return Ok(());
+        };
+        dependencies.message_queue.send(
+            msg.chat.id,
+            Text::SelectDealTypes {
+                deal_types: &Self::deal_type_options(city, &district_names),
+            }
+            .render(language),
+        )?;
+
+        dialogue
+            .update(State::ReceiveDealTypes {
+                category,
+                city_name,
+                district_names,
+                area: Some(area),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// A shared location or venue, or coordinates typed as `56.9496,24.1052`.
+    fn shared_location(msg: &Message) -> Option<Coordinates> {
+        let location = msg
+            .location()
+            .or_else(|| msg.venue().map(|venue| &venue.location));
+        if let Some(location) = location {
+            return Some(Coordinates {
+                latitude: location.latitude,
+                longitude: location.longitude,
+            });
+        }
+        msg.text().and_then(Coordinates::parse)
+    }
+
+    fn all_districts(city: &City) -> Vec<String> {
+        let mut all_districts = city
+            .districts
+            .iter()
+            .map(|district| district.name.clone())
+            .collect::<Vec<_>>();
+        all_districts.sort();
+        all_districts
+    }
+
+    /// Districts of `city` that may reach into `area`, so only they are scraped. Districts
+    /// without a known centroid are kept; `None` when no known one is near, e.g. for a place
+    /// outside the city.
+    fn districts_near(geocoder: &Geocoder, city: &City, area: &Area) -> Option<Vec<String>> {
+        let mut is_any_near = false;
+        let near_districts = Self::all_districts(city)
+            .into_iter()
+            .filter(|district| match geocoder.district(&city.name, district) {
+                Some(coordinates) => {
+                    let is_near = area.center.distance_km(&coordinates)
+                        <= area.radius_km + DISTRICT_MARGIN_KM;
+                    is_any_near |= is_near;
+                    is_near
+                }
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        is_any_near.then_some(near_districts)
+    }
 
-        let deal_options = district
-            .deal_types
+    /// Bullet list of the deal types listed in the selected districts.
+    fn deal_type_options(city: &City, district_names: &[String]) -> String {
+        let selected_districts = district_names.iter().cloned().collect::<HashSet<_>>();
+        city.deal_type_names(&selected_districts)
             .iter()
             .map(|deal_type| format!("• {}", deal_type))
             .collect::<Vec<_>>()
-            .join("\n");
+            .join("\n")
+    }
 
-        bot.send_message(
-            msg.chat.id,
-            format!("Please select a deal type: \n\n{}", deal_options),
-        )
-        .await?;
+    async fn receive_deal_types(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        dialogue: MyDialogue,
+        (category, city_name, district_names, area): (Category, String, Vec<String>, Option<Area>),
+        msg: Message,
+    ) -> HandlerResult {
+        let Some(text): Option<&str> = msg.text() else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::PlainTextRequired.render(language))?;
+            return Ok(());
+        };
+
+        let flats_parser = dependencies.flats_parser.lock().await;
+        let Some(city) = flats_parser.find_city(category, &city_name) else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::CityNotFound.render(language))?;
+            return Ok(());
+        };
+
+        let selected_districts = district_names.iter().cloned().collect::<HashSet<_>>();
+        let available_deal_types = city.deal_type_names(&selected_districts);
+        let deal_types = Self::parse_selection(text);
+        let unknown_deal_types = deal_types
+            .iter()
+            .filter(|deal_type| !available_deal_types.contains(deal_type))
+            .cloned()
+            .collect::<Vec<_>>();
+        if deal_types.is_empty() || !unknown_deal_types.is_empty() {
+            dependencies.message_queue.send(
+                msg.chat.id,
+                Text::DealTypeNotFound {
+                    deal_types: &unknown_deal_types.join(", "),
+                }
+                .render(language),
+            )?;
+            return Ok(());
+        }
+
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::EnterPriceRange.render(language))?;
 
         dialogue
-            .update(State::ReceiveDealType {
-                city_name: city_name.into(),
-                district_name: district_name.to_string(),
+            .update(State::ReceivePriceRange {
+                category,
+                city_name,
+                district_names,
+                deal_types,
+                area,
             })
             .await?;
 
-        println!("dialogue: {:?}", dialogue);
         Ok(())
     }
 
-    async fn receive_deal_type(
+    async fn receive_price_range(
         dependencies: Arc<BotDependencies>,
-        bot: Bot,
+        language: Language,
         dialogue: MyDialogue,
+        (category, city_name, district_names, deal_types, area): (
+            Category,
+            String,
+            Vec<String>,
+            Vec<String>,
+            Option<Area>,
+        ),
         msg: Message,
     ) -> HandlerResult {
-        let Some(deal_type): Option<&str> = msg.text() else {
-            bot.send_message(msg.chat.id, "Message should be a plain text").await?;
-            return Ok(())
+        let Some(text): Option<&str> = msg.text() else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::PlainTextRequired.render(language))?;
+            return Ok(());
         };
-        let Some(state) = dialogue.get().await? else {
-            bot.send_message(msg.chat.id, "Dialogue not found").await?;
-            return Ok(())
+        let price_range = text.split_once('-').and_then(|(from, to)| {
+            Some((
+                from.trim().parse::<u32>().ok()?,
+                to.trim().parse::<u32>().ok()?,
+            ))
+        });
+        let Some((price_from, price_to)) = price_range.filter(|(from, to)| from <= to) else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::InvalidPriceRange.render(language))?;
+            return Ok(());
+        };
+
+        let flat_criteria = FlatCriteria {
+            category,
+            sources: FlatCriteria::default_sources(),
+            rooms_from: None,
+            rooms_to: None,
+            square_meters_from: None,
+            square_meters_to: None,
+            floor_from: None,
+            floor_to: None,
+            not_first_floor: false,
+            not_last_floor: false,
+            series: BTreeSet::new(),
+            excluded_series: BTreeSet::new(),
+            excluded_streets: BTreeSet::new(),
+            include_keywords: BTreeSet::new(),
+            exclude_keywords: BTreeSet::new(),
+            area,
+            city: city_name,
+            districts: district_names.into_iter().collect(),
+            deal_types: deal_types.into_iter().collect(),
+            price_from,
+            price_to,
         };
+        LogContext::correlated("search")
+            .with("chat_id", msg.chat.id)
+            .scope(Self::search_and_subscribe(
+                dependencies,
+                language,
+                dialogue,
+                flat_criteria,
+                msg,
+            ))
+            .await
+    }
 
-        let (city_name, district_name) = if let State::ReceiveDealType {
-            city_name,
-            district_name,
-        } = state
+    /// Runs the first search of a new subscription and saves it.
+    async fn search_and_subscribe(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        dialogue: MyDialogue,
+        flat_criteria: FlatCriteria,
+        msg: Message,
+    ) -> HandlerResult {
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::Searching.render(language))?;
+        let flats = match dependencies
+            .listing_sources
+            .search_matching(&flat_criteria)
+            .await
         {
-            (city_name, district_name)
+            Ok(flats) => flats,
+            Err(error) => {
+                Logger::error(format!("Failed to search flats: {}", error).as_str());
+                dependencies
+                    .message_queue
+                    .send(msg.chat.id, Text::SearchFailed.render(language))?;
+                return Ok(());
+            }
+        };
+        if flats.is_empty() {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::NoFlatsFound.render(language))?;
         } else {
-            bot.send_message(msg.chat.id, "Unexpected state.").await?;
-            return Ok(());
+            dependencies.message_queue.send(
+                msg.chat.id,
+                Text::FlatsFound { count: flats.len() }.render(language),
+            )?;
+            for chunk in flats.chunks(FLATS_PER_MESSAGE) {
+                let text = chunk
+                    .iter()
+                    .map(|flat| Text::Flat { flat }.render(language))
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                dependencies.message_queue.send(msg.chat.id, text)?;
+            }
+        }
+
+        let seen_urls = flats.iter().map(|flat| flat.url.clone()).collect();
+        let subscription_id = {
+            let mut storage = dependencies.storage.lock().await;
+            storage.record_listings(&flats, chrono::Utc::now());
+            let id = storage.add_subscription(msg.chat.id.0, flat_criteria, seen_urls);
+            storage.save()?;
+            id
         };
+        dependencies.message_queue.send(
+            msg.chat.id,
+            Text::Subscribed {
+                id: subscription_id,
+            }
+            .render(language),
+        )?;
 
-        let flats_parser = dependencies.flats_parser.lock().await;
-        let city_option = flats_parser
-            .cities
+        dialogue.exit().await?;
+        Ok(())
+    }
+
+    async fn list_subscriptions(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        msg: Message,
+    ) -> HandlerResult {
+        let text = {
+            let storage = dependencies.storage.lock().await;
+            let subscriptions = storage.chat_subscriptions(msg.chat.id.0);
+            if subscriptions.is_empty() {
+                Text::NoSubscriptions.render(language)
+            } else {
+                Text::Subscriptions {
+                    subscriptions: &subscriptions,
+                }
+                .render(language)
+            }
+        };
+        dependencies.message_queue.send(msg.chat.id, text)?;
+        Ok(())
+    }
+
+    async fn unsubscribe(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        id: String,
+        msg: Message,
+    ) -> HandlerResult {
+        let removed_id = match id.trim().trim_start_matches('#').parse::<u64>() {
+            Ok(id) => {
+                let mut storage = dependencies.storage.lock().await;
+                let removed = storage.remove_subscription(msg.chat.id.0, id);
+                storage.save()?;
+                if removed {
+                    metrics().forget_subscription(id);
+                }
+                removed.then_some(id)
+            }
+            Err(_) => None,
+        };
+        let text = match removed_id {
+            Some(id) => Text::Unsubscribed { id }.render(language),
+            None => Text::SubscriptionNotFound.render(language),
+        };
+        dependencies.message_queue.send(msg.chat.id, text)?;
+        Ok(())
+    }
+
+    /// Portals added to a saved search are searched once right away, so their current listings
+    /// are not reported as new.
+    async fn set_sources(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        args: String,
+        msg: Message,
+    ) -> HandlerResult {
+        let usage = Text::SourcesUsage {
+            sources: &Source::ALL
+                .iter()
+                .map(|source| source.code())
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let Some((id, codes)) = args.trim().split_once(char::is_whitespace) else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, usage.render(language))?;
+            return Ok(());
+        };
+        let Ok(id) = id.trim_start_matches('#').parse::<u64>() else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, usage.render(language))?;
+            return Ok(());
+        };
+        let sources = Self::parse_selection(codes)
             .iter()
-            .find(|city| city.name.eq(&city_name));
+            .map(|code| Source::from_code(code))
+            .collect::<Option<BTreeSet<_>>>()
+            .filter(|sources| !sources.is_empty());
+        let Some(sources) = sources else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, usage.render(language))?;
+            return Ok(());
+        };
+
+        let criteria = {
+            let storage = dependencies.storage.lock().await;
+            storage
+                .chat_subscriptions(msg.chat.id.0)
+                .into_iter()
+                .find(|subscription| subscription.id == id)
+                .map(|subscription| subscription.criteria.clone())
+        };
+        let Some(criteria) = criteria else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::SubscriptionNotFound.render(language))?;
+            return Ok(());
+        };
+
+        let added_criteria = FlatCriteria {
+            sources: sources.difference(&criteria.sources).copied().collect(),
+            ..criteria
+        };
+        let added_flats = if added_criteria.sources.is_empty() {
+            Vec::new()
+        } else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::Searching.render(language))?;
+            match dependencies
+                .listing_sources
+                .search_matching(&added_criteria)
+                .await
+            {
+                Ok(flats) => flats,
+                Err(error) => {
+                    Logger::error(
+                        format!(
+                            "Failed to search new sources of subscription {}: {}",
+                            id, error
+                        )
+                        .as_str(),
+                    );
+                    dependencies
+                        .message_queue
+                        .send(msg.chat.id, Text::SearchFailed.render(language))?;
+                    return Ok(());
+                }
+            }
+        };
+
+        {
+            let mut storage = dependencies.storage.lock().await;
+            storage.record_listings(&added_flats, chrono::Utc::now());
+            let Some(subscription) = storage.chat_subscription_mut(msg.chat.id.0, id) else {
+                dependencies
+                    .message_queue
+                    .send(msg.chat.id, Text::SubscriptionNotFound.render(language))?;
+                return Ok(());
+            };
+            subscription.criteria.sources = sources.clone();
+            subscription
+                .seen_urls
+                .extend(added_flats.iter().map(|flat| flat.url.clone()));
+            storage.save()?;
+        }
+        dependencies.message_queue.send(
+            msg.chat.id,
+            Text::SourcesSet {
+                id,
+                sources: &sources
+                    .iter()
+                    .map(|source| source.name())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            }
+            .render(language),
+        )?;
+        Ok(())
+    }
+
+    /// Parses `2-3`, `2-`, `-3` or `2`; `off` clears the range.
+    fn parse_range(text: &str) -> Option<(Option<u32>, Option<u32>)> {
+        let text = text.trim();
+        if text.eq_ignore_ascii_case("off") {
+            return Some((None, None));
+        }
+        let bound = |value: &str| -> Option<Option<u32>> {
+            let value = value.trim();
+            if value.is_empty() {
+                return Some(None);
+            }
+            value.parse::<u32>().ok().map(Some)
+        };
+        let (from, to) = match text.split_once('-') {
+            Some((from, to)) => (bound(from)?, bound(to)?),
+            None => {
+                let value = bound(text)?;
+                (value, value)
+            }
+        };
+        match (from, to) {
+            (None, None) => None,
+            (Some(from), Some(to)) if from > to => None,
+            range => Some(range),
+        }
+    }
+
+    /// Applies a `/filter` value: a range, `not-first`/`not-last` floors, series codes, phrases
+    /// or a search area.
+    fn apply_filter(criteria: &mut FlatCriteria, name: &str, value: &str) -> bool {
+        let off = value.eq_ignore_ascii_case("off");
+        match name.to_lowercase().as_str() {
+            "include" => return Self::set_phrases(&mut criteria.include_keywords, value),
+            "exclude" => return Self::set_phrases(&mut criteria.exclude_keywords, value),
+            "street" => return Self::set_phrases(&mut criteria.excluded_streets, value),
+            "not-series" => return Self::set_series(&mut criteria.excluded_series, value),
+            "near" => return Self::set_area(criteria, value),
+            _ => {}
+        }
+        let Some(kind) = FilterKind::from_name(name) else {
+            return false;
+        };
+        match kind {
+            FilterKind::Series => return Self::set_series(&mut criteria.series, value),
+            FilterKind::Floor if value.eq_ignore_ascii_case("not-first") => {
+                criteria.not_first_floor = true
+            }
+            FilterKind::Floor if value.eq_ignore_ascii_case("not-last") => {
+                criteria.not_last_floor = true
+            }
+            _ => {
+                let Some((from, to)) = Self::parse_range(value) else {
+                    return false;
+                };
+                if kind == FilterKind::Floor && off {
+                    criteria.not_first_floor = false;
+                    criteria.not_last_floor = false;
+                }
+                return criteria.set_range(kind, from, to);
+            }
+        }
+        true
+    }
+
+    /// `56.9496,24.1052 2` for a 2 km radius around the point, `off` clears the area.
+    fn set_area(criteria: &mut FlatCriteria, value: &str) -> bool {
+        if value.eq_ignore_ascii_case("off") {
+            criteria.area = None;
+            return true;
+        }
+        let Some((center, radius_km)) = value.rsplit_once(char::is_whitespace) else {
+            return false;
+        };
+        let (Some(center), Ok(radius_km)) = (
+            Coordinates::parse(center),
+            radius_km.trim_end_matches("km").parse::<f64>(),
+        ) else {
+            return false;
+        };
+        if radius_km <= 0.0 || radius_km > MAX_RADIUS_KM {
+            return false;
+        }
+        criteria.area = Some(Area { center, radius_km });
+        true
+    }
+
+    /// Comma separated series codes, `off` clears them.
+    fn set_series(series: &mut BTreeSet<Series>, value: &str) -> bool {
+        if value.eq_ignore_ascii_case("off") {
+            series.clear();
+            return true;
+        }
+        let Some(parsed) = value
+            .split(',')
+            .map(Series::from_name)
+            .collect::<Option<BTreeSet<_>>>()
+        else {
+            return false;
+        };
+        *series = parsed;
+        true
+    }
+
+    /// Comma separated phrases, lowercased to match case-insensitively; `off` clears them.
+    fn set_phrases(phrases: &mut BTreeSet<String>, value: &str) -> bool {
+        if value.eq_ignore_ascii_case("off") {
+            phrases.clear();
+            return true;
+        }
+        let parsed = value
+            .split(',')
+            .map(|phrase| phrase.trim().to_lowercase())
+            .filter(|phrase| !phrase.is_empty())
+            .collect::<BTreeSet<_>>();
+        if parsed.is_empty() {
+            return false;
+        }
+        *phrases = parsed;
+        true
+    }
 
-        let Some(city) = city_option else {
-            bot.send_message(msg.chat.id, "City not found").await?;
+    /// The listings matching a changed filter are searched once right away, so relaxing it does
+    /// not report every listing it now lets through as new.
+    async fn set_filter(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        args: String,
+        msg: Message,
+    ) -> HandlerResult {
+        // keywords may contain spaces, the value is everything after the filter name
+        let mut args = args.trim().splitn(3, char::is_whitespace);
+        let id = args
+            .next()
+            .and_then(|id| id.trim_start_matches('#').parse::<u64>().ok());
+        let name = args.next();
+        let value = args.next().map(str::trim).filter(|value| !value.is_empty());
+        let (Some(id), Some(name), Some(value)) = (id, name, value) else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::FilterUsage.render(language))?;
             return Ok(());
         };
 
-        let city = city.clone();
-        let Some(district) = city.districts.iter().find(|district| district.name.eq(&district_name)) else {
-            bot.send_message(msg.chat.id, "District not found").await?;
-            return Ok(())
+        let criteria = {
+            let storage = dependencies.storage.lock().await;
+            storage
+                .chat_subscriptions(msg.chat.id.0)
+                .into_iter()
+                .find(|subscription| subscription.id == id)
+                .map(|subscription| subscription.criteria.clone())
+        };
+        let Some(mut criteria) = criteria else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::SubscriptionNotFound.render(language))?;
+            return Ok(());
         };
-        let is_deal_type_valid = district.deal_types.iter().any(|dt| dt.eq(deal_type));
-        if !is_deal_type_valid {
-            bot.send_message(msg.chat.id, "Deal type not found").await?;
+        if !Self::apply_filter(&mut criteria, name, value) {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::FilterUsage.render(language))?;
             return Ok(());
         }
 
-        bot.send_message(
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::Searching.render(language))?;
+        let flats = match dependencies
+            .listing_sources
+            .search_matching(&criteria)
+            .await
+        {
+            Ok(flats) => flats,
+            Err(error) => {
+                Logger::error(
+                    format!(
+                        "Failed to search the changed filter of subscription {}: {}",
+                        id, error
+                    )
+                    .as_str(),
+                );
+                dependencies
+                    .message_queue
+                    .send(msg.chat.id, Text::SearchFailed.render(language))?;
+                return Ok(());
+            }
+        };
+
+        let text = {
+            let mut storage = dependencies.storage.lock().await;
+            storage.record_listings(&flats, chrono::Utc::now());
+            let Some(subscription) = storage.chat_subscription_mut(msg.chat.id.0, id) else {
+                dependencies
+                    .message_queue
+                    .send(msg.chat.id, Text::SubscriptionNotFound.render(language))?;
+                return Ok(());
+            };
+            subscription
+                .seen_urls
+                .extend(flats.iter().map(|flat| flat.url.clone()));
+            subscription.criteria = criteria;
+            let text = Text::FilterSet {
+                id,
+                criteria: &subscription.criteria,
+            }
+            .render(language);
+            storage.save()?;
+            text
+        };
+        dependencies.message_queue.send(msg.chat.id, text)?;
+        Ok(())
+    }
+
+    async fn set_min_discount(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        args: String,
+        msg: Message,
+    ) -> HandlerResult {
+        let mut args = args.split_whitespace();
+        let id = args
+            .next()
+            .and_then(|id| id.trim_start_matches('#').parse::<u64>().ok());
+        let min_discount = args.next().and_then(|value| {
+            if value.eq_ignore_ascii_case("off") {
+                return Some(None);
+            }
+            value
+                .trim_end_matches('%')
+                .parse::<u32>()
+                .ok()
+                .filter(|percent| *percent < 100)
+                .map(Some)
+        });
+        let (Some(id), Some(min_discount)) = (id, min_discount) else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::DealsUsage.render(language))?;
+            return Ok(());
+        };
+
+        let mut storage = dependencies.storage.lock().await;
+        let Some(subscription) = storage.chat_subscription_mut(msg.chat.id.0, id) else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::SubscriptionNotFound.render(language))?;
+            return Ok(());
+        };
+        subscription.min_discount = min_discount;
+        storage.save()?;
+        drop(storage);
+        dependencies.message_queue.send(
             msg.chat.id,
-            "Please enter the price range using the following format: 'min_price-max_price'",
-        )
-        .await?;
+            Text::DealsSet { id, min_discount }.render(language),
+        )?;
+        Ok(())
+    }
 
-        dialogue
-            .update(State::ReceivePriceRange {
-                city_name: city_name.into(),
-                district_name: district_name.into(),
-                deal_type: deal_type.into(),
+    async fn subscription_map(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        id: String,
+        msg: Message,
+    ) -> HandlerResult {
+        let Ok(id) = id.trim().trim_start_matches('#').parse::<u64>() else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::MapUsage.render(language))?;
+            return Ok(());
+        };
+        // the stored listings the polls keep finding, keywords were only checked for the ones
+        // the chat was shown
+        let map = {
+            let storage = dependencies.storage.lock().await;
+            let subscription = storage
+                .chat_subscriptions(msg.chat.id.0)
+                .into_iter()
+                .find(|subscription| subscription.id == id);
+            subscription.map(|subscription| {
+                let criteria = &subscription.criteria;
+                let listed_since = Utc::now() - chrono::Duration::hours(MAP_WINDOW_HOURS);
+                let flats = storage
+                    .data
+                    .listings
+                    .values()
+                    .filter(|listing| listing.last_seen >= listed_since)
+                    .filter(|listing| {
+                        let url = &listing.flat.url;
+                        !subscription.rejected_urls.contains(url)
+                            && (!criteria.has_keywords() || subscription.seen_urls.contains(url))
+                    })
+                    .filter(|listing| {
+                        criteria.matches(&listing.flat) && criteria.matches_area(&listing.flat)
+                    })
+                    .map(|listing| listing.flat.clone())
+                    .collect::<Vec<_>>();
+                (criteria.clone(), flats)
             })
-            .await?;
+        };
+        let Some((criteria, flats)) = map else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::SubscriptionNotFound.render(language))?;
+            return Ok(());
+        };
+
+        let geocoder = dependencies.listing_sources.geocoder();
+        let Some(png) = geo::render_map(&flats, criteria.area.as_ref(), geocoder)? else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::NothingToMap.render(language))?;
+            return Ok(());
+        };
+        dependencies.message_queue.send_photo(
+            msg.chat.id,
+            png,
+            format!("subscription-{}.png", id),
+        )?;
+        Ok(())
+    }
+
+    async fn market_stats(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        location: String,
+        msg: Message,
+    ) -> HandlerResult {
+        if location.trim().is_empty() {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::MarketUsage.render(language))?;
+            return Ok(());
+        }
+        let stats = {
+            let storage = dependencies.storage.lock().await;
+            let listings = storage.data.listings.values();
+            stats::find_location(listings.clone(), &location).and_then(|(city, district)| {
+                MarketStats::from_listings(listings, &city, &district, Utc::now())
+            })
+        };
+        let Some(stats) = stats else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::StatsNotFound.render(language))?;
+            return Ok(());
+        };
 
+        dependencies.message_queue.send(
+            msg.chat.id,
+            Text::MarketStats { stats: &stats }.render(language),
+        )?;
+        match stats::render_chart(&stats) {
+            Ok(Some(png)) => {
+                dependencies.message_queue.send_photo(
+                    msg.chat.id,
+                    png,
+                    String::from("stats.png"),
+                )?;
+            }
+            Ok(None) => {}
+            Err(error) => {
+                Logger::error(format!("Failed to render the stats chart: {}", error).as_str())
+            }
+        }
         Ok(())
     }
 
-    async fn cancel(bot: Bot, msg: Message) -> HandlerResult {
-        bot.send_message(msg.chat.id, "Unhandled message: Please follow the prompts.")
-            .await?;
+    async fn set_quiet_hours(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        range: String,
+        msg: Message,
+    ) -> HandlerResult {
+        let mut storage = dependencies.storage.lock().await;
+        let text = if range.trim().eq_ignore_ascii_case("off") {
+            storage.chat_settings_mut(msg.chat.id.0).quiet_hours = None;
+            storage.save()?;
+            Text::QuietHoursDisabled.render(language)
+        } else if let Some(quiet_hours) = QuietHours::parse(&range) {
+            let settings = storage.chat_settings_mut(msg.chat.id.0);
+            settings.quiet_hours = Some(quiet_hours);
+            let text = Text::QuietHoursSet {
+                quiet_hours,
+                timezone: &settings.timezone,
+            }
+            .render(language);
+            storage.save()?;
+            text
+        } else {
+            Text::InvalidQuietHours.render(language)
+        };
+        drop(storage);
+        dependencies.message_queue.send(msg.chat.id, text)?;
         Ok(())
     }
 
-    async fn unhandled_message(bot: Bot, msg: Message) -> HandlerResult {
-        let help_text = Command::descriptions();
-        bot.send_message(
+    async fn set_timezone(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        timezone: String,
+        msg: Message,
+    ) -> HandlerResult {
+        let timezone = timezone.trim();
+        let text = match timezone.parse::<chrono_tz::Tz>() {
+            Ok(tz) => {
+                let mut storage = dependencies.storage.lock().await;
+                storage.chat_settings_mut(msg.chat.id.0).timezone = tz.name().to_string();
+                storage.save()?;
+                Text::TimezoneSet {
+                    timezone: tz.name(),
+                }
+                .render(language)
+            }
+            Err(_) => Text::InvalidTimezone.render(language),
+        };
+        dependencies.message_queue.send(msg.chat.id, text)?;
+        Ok(())
+    }
+
+    async fn set_delivery_mode(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        mode: String,
+        msg: Message,
+    ) -> HandlerResult {
+        let text = match DeliveryMode::from_name(&mode) {
+            Some(delivery_mode) => {
+                let mut storage = dependencies.storage.lock().await;
+                storage.chat_settings_mut(msg.chat.id.0).delivery_mode = delivery_mode;
+                storage.save()?;
+                Text::DeliveryModeSet { delivery_mode }.render(language)
+            }
+            None => Text::InvalidDeliveryMode.render(language),
+        };
+        dependencies.message_queue.send(msg.chat.id, text)?;
+        Ok(())
+    }
+
+    async fn feed_links(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        id: String,
+        msg: Message,
+    ) -> HandlerResult {
+        let Ok(id) = id.trim().trim_start_matches('#').parse::<u64>() else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::FeedUsage.render(language))?;
+            return Ok(());
+        };
+        let feed_token = {
+            let storage = dependencies.storage.lock().await;
+            storage
+                .chat_subscriptions(msg.chat.id.0)
+                .into_iter()
+                .find(|subscription| subscription.id == id)
+                .map(|subscription| subscription.feed_token.clone())
+        };
+        let Some(feed_token) = feed_token else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::SubscriptionNotFound.render(language))?;
+            return Ok(());
+        };
+        dependencies.message_queue.send(
             msg.chat.id,
-            format!("Unhandled message. Available commands:\n{}", help_text),
-        )
-        .await?;
+            Text::FeedLinks { token: &feed_token }.render(language),
+        )?;
         Ok(())
     }
 
-    async fn request_flats(
-        bot: Bot,
-        dialogue: MyDialogue,
+    async fn export_subscription(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        args: String,
         msg: Message,
-    ) -> Result<(), anyhow::Error> {
-        let Some(state) = dialogue.get().await? else {
-            bot.send_message(msg.chat.id, "Dialogue not found").await?;
-            return Ok(())
+    ) -> HandlerResult {
+        let mut args = args.split_whitespace();
+        let id = args
+            .next()
+            .and_then(|id| id.trim_start_matches('#').parse::<u64>().ok());
+        let format = match args.next() {
+            Some(name) => ExportFormat::from_name(name),
+            None => Some(ExportFormat::ExcelCsv),
+        };
+        let (Some(id), Some(format)) = (id, format) else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::ExportUsage.render(language))?;
+            return Ok(());
+        };
+
+        // the listings the chat was shown that still match, newest first
+        let flats = {
+            let storage = dependencies.storage.lock().await;
+            let subscription = storage
+                .chat_subscriptions(msg.chat.id.0)
+                .into_iter()
+                .find(|subscription| subscription.id == id);
+            subscription.map(|subscription| {
+                let criteria = &subscription.criteria;
+                let mut listings = subscription
+                    .seen_urls
+                    .iter()
+                    .filter_map(|url| storage.data.listings.get(url))
+                    .filter(|listing| {
+                        criteria.matches(&listing.flat) && criteria.matches_area(&listing.flat)
+                    })
+                    .collect::<Vec<_>>();
+                listings.sort_by_key(|listing| std::cmp::Reverse(listing.first_seen));
+                listings
+                    .into_iter()
+                    .map(|listing| listing.flat.clone())
+                    .collect::<Vec<_>>()
+            })
+        };
+        let Some(flats) = flats else {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::SubscriptionNotFound.render(language))?;
+            return Ok(());
         };
+        if flats.is_empty() {
+            dependencies
+                .message_queue
+                .send(msg.chat.id, Text::NoFlatsFound.render(language))?;
+            return Ok(());
+        }
+
+        let mut file_content: Vec<u8> = Vec::new();
+        export::write_flats(&flats, format, &mut file_content)?;
+        let file_name = format!("subscription-{}.{}", id, format.extension());
+        dependencies
+            .message_queue
+            .send_document(msg.chat.id, file_content, file_name)?;
         Ok(())
     }
+
+    async fn cancel(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        dialogue: MyDialogue,
+        msg: Message,
+    ) -> HandlerResult {
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::Cancelled.render(language))?;
+        dialogue.exit().await?;
+        Ok(())
+    }
+
+    async fn unhandled_message(
+        dependencies: Arc<BotDependencies>,
+        language: Language,
+        msg: Message,
+    ) -> HandlerResult {
+        dependencies
+            .message_queue
+            .send(msg.chat.id, Text::Unhandled.render(language))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flats::CategoryStructure;
+
+    fn criteria() -> FlatCriteria {
+        serde_json::from_value(serde_json::json!({
+            "city": "Rīga",
+            "districts": [],
+            "deal_types": [],
+            "price_from": 0,
+            "price_to": u32::MAX,
+        }))
+        .expect("criteria are valid")
+    }
+
+    #[test]
+    fn parse_range_accepts_open_and_closed_ranges() {
+        assert_eq!(
+            FlatsBotTelegram::parse_range("2-3"),
+            Some((Some(2), Some(3)))
+        );
+        assert_eq!(
+            FlatsBotTelegram::parse_range(" 2 - "),
+            Some((Some(2), None))
+        );
+        assert_eq!(FlatsBotTelegram::parse_range("-3"), Some((None, Some(3))));
+        assert_eq!(FlatsBotTelegram::parse_range("4"), Some((Some(4), Some(4))));
+        assert_eq!(FlatsBotTelegram::parse_range("OFF"), Some((None, None)));
+    }
+
+    #[test]
+    fn parse_range_rejects_invalid_ranges() {
+        assert_eq!(FlatsBotTelegram::parse_range("3-2"), None);
+        assert_eq!(FlatsBotTelegram::parse_range("-"), None);
+        assert_eq!(FlatsBotTelegram::parse_range(""), None);
+        assert_eq!(FlatsBotTelegram::parse_range("two"), None);
+        assert_eq!(FlatsBotTelegram::parse_range("1-2-3"), None);
+    }
+
+    #[test]
+    fn apply_filter_sets_and_clears_keywords() {
+        let mut criteria = criteria();
+        assert!(FlatsBotTelegram::apply_filter(
+            &mut criteria,
+            "include",
+            "Balkons, lifts"
+        ));
+        assert!(FlatsBotTelegram::apply_filter(
+            &mut criteria,
+            "exclude",
+            "bez remonta"
+        ));
+        assert_eq!(
+            criteria.include_keywords,
+            BTreeSet::from([String::from("balkons"), String::from("lifts")])
+        );
+        assert!(criteria.matches_text("Balkons, lifts, renovēta māja"));
+        assert!(!criteria.matches_text("Balkons, lifts, bez remonta"));
+
+        assert!(FlatsBotTelegram::apply_filter(
+            &mut criteria,
+            "include",
+            "off"
+        ));
+        assert!(criteria.include_keywords.is_empty());
+    }
+
+    #[test]
+    fn apply_filter_sets_ranges_by_name() {
+        let mut criteria = criteria();
+        assert!(FlatsBotTelegram::apply_filter(
+            &mut criteria,
+            "rooms",
+            "2-3"
+        ));
+        assert_eq!((criteria.rooms_from, criteria.rooms_to), (Some(2), Some(3)));
+        assert!(FlatsBotTelegram::apply_filter(
+            &mut criteria,
+            "floor",
+            "not-first"
+        ));
+        assert!(criteria.not_first_floor);
+        assert!(FlatsBotTelegram::apply_filter(
+            &mut criteria,
+            "floor",
+            "off"
+        ));
+        assert!(!criteria.not_first_floor);
+        assert!(!FlatsBotTelegram::apply_filter(
+            &mut criteria,
+            "balcony",
+            "1"
+        ));
+    }
+
+    fn riga(districts: &[&str]) -> City {
+        City {
+            name: String::from("Rīga"),
+            href: String::new(),
+            districts: districts
+                .iter()
+                .map(|name| CategoryStructure {
+                    name: name.to_string(),
+                    href: String::new(),
+                    deal_types: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn districts_near_keeps_nearby_and_unknown_districts() {
+        let geocoder = Geocoder::load(None).expect("bundled dataset loads");
+        let city = riga(&["Centrs", "Vecrīga", "Imanta", "Jugla", "Nezināms"]);
+        let area = Area {
+            center: Coordinates::parse("56.9560,24.1180").expect("valid coordinates"),
+            radius_km: 1.0,
+        };
+        assert_eq!(
+            FlatsBotTelegram::districts_near(&geocoder, &city, &area),
+            Some(vec![
+                String::from("Centrs"),
+                String::from("Nezināms"),
+                String::from("Vecrīga")
+            ])
+        );
+    }
+
+    #[test]
+    fn districts_near_rejects_places_far_from_every_district() {
+        let geocoder = Geocoder::load(None).expect("bundled dataset loads");
+        let city = riga(&["Centrs", "Imanta", "Jugla", "Nezināms"]);
+        // Daugavpils
+        let area = Area {
+            center: Coordinates::parse("55.8747,26.5362").expect("valid coordinates"),
+            radius_km: 5.0,
+        };
+        assert_eq!(
+            FlatsBotTelegram::districts_near(&geocoder, &city, &area),
+            None
+        );
+        assert_eq!(
+            FlatsBotTelegram::districts_near(&geocoder, &riga(&["Nezināms"]), &area),
+            None
+        );
+    }
 }
@@ -0,0 +1,280 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::logger::{LogContext, Logger};
+use crate::metrics::metrics;
+use crate::storage::Storage;
+use teloxide::prelude::*;
+use teloxide::types::InputFile;
+use teloxide::{ApiError, RequestError};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// Telegram allows about 30 messages per second across all chats.
+const GLOBAL_SEND_INTERVAL: Duration = Duration::from_millis(35);
+/// No more than one message per second to a private chat.
+const PRIVATE_CHAT_SEND_INTERVAL: Duration = Duration::from_secs(1);
+/// No more than 20 messages per minute to a group.
+const GROUP_CHAT_SEND_INTERVAL: Duration = Duration::from_secs(3);
+const MAX_SEND_ATTEMPTS: u32 = 5;
+const NETWORK_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// What an [`OutboundMessage`] delivers, files are sent from memory.
+#[derive(Clone)]
+pub enum Content {
+    Text(String),
+    Photo { bytes: Vec<u8>, file_name: String },
+    Document { bytes: Vec<u8>, file_name: String },
+}
+
+pub struct OutboundMessage {
+    pub chat_id: ChatId,
+    pub content: Content,
+    /// Log context of the sender, so delivery shows up under the same correlation id.
+    pub context: LogContext,
+}
+
+/// Handle used to enqueue outbound messages, cheap to clone.
+#[derive(Clone)]
+pub struct MessageQueue {
+    sender: mpsc::UnboundedSender<OutboundMessage>,
+}
+
+impl MessageQueue {
+    pub fn new(bot: Bot, storage: Arc<Mutex<Storage>>) -> (Self, MessageQueueWorker) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let worker = MessageQueueWorker {
+            bot,
+            storage,
+            receiver,
+            pending: HashMap::new(),
+            last_sent_by_chat: HashMap::new(),
+            last_sent: None,
+        };
+        (Self { sender }, worker)
+    }
+
+    pub fn send(&self, chat_id: ChatId, text: String) -> Result<(), anyhow::Error> {
+        self.enqueue(chat_id, Content::Text(text))
+    }
+
+    pub fn send_photo(
+        &self,
+        chat_id: ChatId,
+        bytes: Vec<u8>,
+        file_name: String,
+    ) -> Result<(), anyhow::Error> {
+        self.enqueue(chat_id, Content::Photo { bytes, file_name })
+    }
+
+    pub fn send_document(
+        &self,
+        chat_id: ChatId,
+        bytes: Vec<u8>,
+        file_name: String,
+    ) -> Result<(), anyhow::Error> {
+        self.enqueue(chat_id, Content::Document { bytes, file_name })
+    }
+
+    fn enqueue(&self, chat_id: ChatId, content: Content) -> Result<(), anyhow::Error> {
+        self.sender
+            .send(OutboundMessage {
+                chat_id,
+                content,
+                context: LogContext::current().with("chat_id", chat_id),
+            })
+            .map_err(|_| anyhow::anyhow!("Outbound message queue is closed"))
+    }
+}
+
+/// Sends queued messages while respecting per-chat and global Telegram flood limits.
+pub struct MessageQueueWorker {
+    bot: Bot,
+    storage: Arc<Mutex<Storage>>,
+    receiver: mpsc::UnboundedReceiver<OutboundMessage>,
+    pending: HashMap<ChatId, VecDeque<OutboundMessage>>,
+    last_sent_by_chat: HashMap<ChatId, Instant>,
+    last_sent: Option<Instant>,
+}
+
+enum SendOutcome {
+    Sent,
+    Failed,
+    ChatUnreachable,
+}
+
+impl MessageQueueWorker {
+    /// Runs until every [`MessageQueue`] handle is dropped or `drain` is cancelled,
+    /// in both cases only after every queued message was sent.
+    pub async fn run(mut self, drain: CancellationToken) {
+        loop {
+            while let Ok(message) = self.receiver.try_recv() {
+                self.push(message);
+            }
+            if self.pending.is_empty() {
+                tokio::select! {
+                    biased;
+                    message = self.receiver.recv() => match message {
+                        Some(message) => self.push(message),
+                        None => break,
+                    },
+                    _ = drain.cancelled() => break,
+                }
+                continue;
+            }
+
+            let Some((chat_id, ready_at)) = self.next_ready_chat() else {
+                continue;
+            };
+            tokio::time::sleep_until(ready_at).await;
+
+            let Some(message) = self
+                .pending
+                .get_mut(&chat_id)
+                .and_then(|messages| messages.pop_front())
+            else {
+                continue;
+            };
+            if self
+                .pending
+                .get(&chat_id)
+                .is_some_and(|messages| messages.is_empty())
+            {
+                self.pending.remove(&chat_id);
+            }
+
+            let outcome = message
+                .context
+                .scope(self.send_with_retries(chat_id, message.content))
+                .await;
+            match outcome {
+                SendOutcome::Sent => metrics().notifications_sent.inc(),
+                SendOutcome::Failed => metrics().notifications_failed.inc(),
+                SendOutcome::ChatUnreachable => {
+                    metrics().notifications_failed.inc();
+                    self.pending.remove(&chat_id);
+                    self.deactivate_chat(chat_id).await;
+                }
+            }
+        }
+        Logger::info("Outbound message queue drained");
+    }
+
+    fn push(&mut self, message: OutboundMessage) {
+        self.pending
+            .entry(message.chat_id)
+            .or_default()
+            .push_back(message);
+    }
+
+    fn chat_send_interval(chat_id: ChatId) -> Duration {
+        if chat_id.is_user() {
+            PRIVATE_CHAT_SEND_INTERVAL
+        } else {
+            GROUP_CHAT_SEND_INTERVAL
+        }
+    }
+
+    /// Picks the chat that may receive a message the soonest.
+    fn next_ready_chat(&self) -> Option<(ChatId, Instant)> {
+        let now = Instant::now();
+        let global_ready_at = self
+            .last_sent
+            .map_or(now, |last_sent| last_sent + GLOBAL_SEND_INTERVAL);
+        self.pending
+            .keys()
+            .map(|chat_id| {
+                let chat_ready_at = self
+                    .last_sent_by_chat
+                    .get(chat_id)
+                    .map_or(now, |last_sent| {
+                        *last_sent + Self::chat_send_interval(*chat_id)
+                    });
+                (*chat_id, chat_ready_at.max(global_ready_at))
+            })
+            .min_by_key(|(_, ready_at)| *ready_at)
+    }
+
+    async fn send_with_retries(&mut self, chat_id: ChatId, content: Content) -> SendOutcome {
+        for _ in 0..MAX_SEND_ATTEMPTS {
+            let result = self.send_content(chat_id, content.clone()).await;
+            let sent_at = Instant::now();
+            self.last_sent = Some(sent_at);
+            self.last_sent_by_chat.insert(chat_id, sent_at);
+
+            match result {
+                Ok(_) => return SendOutcome::Sent,
+                Err(RequestError::RetryAfter(retry_after)) => {
+                    Logger::warn(
+                        format!(
+                            "Telegram flood limit hit for chat {}, retrying after {:?}",
+                            chat_id, retry_after
+                        )
+                        .as_str(),
+                    );
+                    tokio::time::sleep(retry_after).await;
+                }
+                Err(RequestError::Api(
+                    ApiError::BotBlocked
+                    | ApiError::BotKicked
+                    | ApiError::BotKickedFromSupergroup
+                    | ApiError::ChatNotFound
+                    | ApiError::UserDeactivated,
+                )) => return SendOutcome::ChatUnreachable,
+                Err(RequestError::Network(error)) => {
+                    Logger::warn(
+                        format!("Network error sending to chat {}: {}", chat_id, error).as_str(),
+                    );
+                    tokio::time::sleep(NETWORK_RETRY_DELAY).await;
+                }
+                Err(error) => {
+                    Logger::error(
+                        format!("Failed to send message to chat {}: {}", chat_id, error).as_str(),
+                    );
+                    return SendOutcome::Failed;
+                }
+            }
+        }
+        Logger::error(
+            format!(
+                "Giving up sending message to chat {} after {} attempts",
+                chat_id, MAX_SEND_ATTEMPTS
+            )
+            .as_str(),
+        );
+        SendOutcome::Failed
+    }
+
+    async fn send_content(&self, chat_id: ChatId, content: Content) -> Result<(), RequestError> {
+        match content {
+            Content::Text(text) => self.bot.send_message(chat_id, text).await.map(drop),
+            Content::Photo { bytes, file_name } => self
+                .bot
+                .send_photo(chat_id, InputFile::memory(bytes).file_name(file_name))
+                .await
+                .map(drop),
+            Content::Document { bytes, file_name } => self
+                .bot
+                .send_document(chat_id, InputFile::memory(bytes).file_name(file_name))
+                .await
+                .map(drop),
+        }
+    }
+
+    async fn deactivate_chat(&self, chat_id: ChatId) {
+        let mut storage = self.storage.lock().await;
+        let deactivated = storage.set_chat_subscriptions_active(chat_id.0, false);
+        Logger::info(
+            format!(
+                "Chat {} is unreachable, deactivated {} subscriptions",
+                chat_id, deactivated
+            )
+            .as_str(),
+        );
+        if let Err(error) = storage.save() {
+            Logger::error(format!("Failed to save storage: {}", error).as_str());
+        }
+    }
+}